@@ -1,55 +1,543 @@
-use crate::engine::AI;
-use crate::model::{board::Board, color::Color, move_::Move};
-use crate::player::Player;
+use crate::engine::{
+    book::Book, endgame::EndgameTables, eval_params::EvalParams, nnue::Network, skill::Skill,
+    syzygy::Tablebase, AI,
+};
+use crate::model::{board::Board, color::Color, move_::Move, notation, piece_type::PieceType};
+use crate::player::{Action, GameResult, Player};
+use crate::render::Renderer;
+use crate::saved_game::SavedGame;
+use crate::tui::{GameView, Terminal};
 use crate::user_input::Human;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 pub(crate) trait Controller {
     fn play_game(&self, board: Board) {}
 }
 
-pub(crate) struct HumanGame {}
+pub(crate) struct HumanGame {
+    pub(crate) engine_params: EvalParams,
+    pub(crate) engine_network: Option<Arc<Network>>,
+    pub(crate) engine_book: Option<Arc<Book>>,
+    pub(crate) engine_tablebase: Option<Arc<Tablebase>>,
+    pub(crate) engine_endgame_tables: Option<Arc<EndgameTables>>,
+    pub(crate) human_color: Color,
+    // Play in the full screen terminal interface instead of reading typed moves
+    pub(crate) terminal_ui: bool,
+    // Time each side starts with in the terminal interface
+    pub(crate) clock: Duration,
+    pub(crate) renderer: Renderer,
+    // Let the engine think while the human does
+    pub(crate) ponder: bool,
+    // How well the engine plays; hints are always at full strength
+    pub(crate) engine_skill: Skill,
+    // Where the engine's book and evaluation parameters came from, recorded in saves
+    pub(crate) book_file: Option<String>,
+    pub(crate) eval_params_file: Option<String>,
+    // File the game is saved to after every move
+    pub(crate) autosave: Option<String>,
+    // A game to carry on with, from its saved start position and moves
+    pub(crate) resumed: Option<SavedGame>,
+}
 
 impl Controller for HumanGame {
-    fn play_game(&self, mut board: Board) {
-        let mut turns: u8 = 0;
-        let mut current_turn: Color = Color::White;
+    fn play_game(&self, board: Board) {
+        if self.terminal_ui {
+            match Terminal::new(self.renderer) {
+                Ok(mut terminal) => return self.play_terminal(board, &mut terminal),
+                Err(e) => println!("{e}, falling back to typed moves"),
+            }
+        }
+
         let mut current_move: Move;
+        let mut game = GameState::new(board, self.resumed_moves());
+
+        let mut engine: AI = self.make_engine();
+        engine.set_skill(self.engine_skill);
+        engine.set_ponder(self.ponder);
+        let engine_color = self.human_color.opponent_color();
+        let mut adviser = self.make_engine();
+        adviser.set_verbose(false);
+        let mut human = Human::new(self.renderer, Some(adviser));
+        engine.new_game(game.board, engine_color);
+        human.new_game(game.start, self.human_color);
+        human.replay(&game.moves);
+
+        // Every way a game ends, resigning on end of input included, comes out here
+        let result = loop {
+            let board = game.board;
+            let current_turn = board.turn;
+            if let Some(result) = game.result() {
+                break result;
+            }
+
+            let outcome = if current_turn == self.human_color {
+                let action = human.take_turn(board, current_turn);
+                game.human_action(action, &mut engine)
+            } else {
+                let declining = game.human_offered_draw;
+                let outcome = game.engine_action(engine.take_turn(board, current_turn));
+                if game.engine_offered_draw {
+                    human.draw_offered(board, self.human_color);
+                } else if declining && matches!(outcome, Outcome::Move(_)) {
+                    println!("The engine declines the draw");
+                }
+                outcome
+            };
+            current_move = match outcome {
+                Outcome::Move(mv) => mv,
+                Outcome::TakenBack(plies) => {
+                    human.moves_taken_back(game.board, plies);
+                    self.autosave(&game.start, &game.moves, None, "*");
+                    continue;
+                }
+                Outcome::Refused(reason) => {
+                    println!("{reason}");
+                    continue;
+                }
+                Outcome::Over(result) => break result,
+            };
+
+            if current_turn == self.human_color {
+                engine.opponent_moved(board, current_move);
+            } else {
+                human.opponent_moved(board, current_move);
+            }
+            game.play(current_move);
+            self.autosave(&game.start, &game.moves, None, "*");
+        };
+        self.end_game(&mut engine, &mut human, result, &game.start, &game.moves);
+    }
+}
+
+// A game against the engine as both interfaces keep it: the moves, the positions they
+// were played from and the draw offers standing. The players' actions are judged here so
+// both interfaces follow the same rules.
+struct GameState {
+    start: Board,
+    board: Board,
+    // Positions before each move, for takebacks and draw claims
+    history: Vec<Board>,
+    moves: Vec<Move>,
+    // Draw offers waiting for an answer
+    human_offered_draw: bool,
+    engine_offered_draw: bool,
+}
+
+// What came of a player's action
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Move(Move),
+    // This many plies were taken back and the same side is to play again
+    TakenBack(usize),
+    // Nothing happened, for the reason given, and the same side is to play again
+    Refused(&'static str),
+    Over(GameResult),
+}
+
+impl GameState {
+    // The game from `start` after the moves already played
+    fn new(start: Board, moves: &[Move]) -> GameState {
+        let mut game = GameState {
+            start,
+            board: start,
+            history: Vec::new(),
+            moves: Vec::new(),
+            human_offered_draw: false,
+            engine_offered_draw: false,
+        };
+        for mv in moves {
+            game.play(*mv);
+        }
+        game
+    }
 
-        //let mut p2: AI = AI::new();
-        //let mut p2: Human=Human{};
-        //let mut p1: AI = AI::new();
-        let mut p1: AI = AI::new();
-        let mut p2: Human = Human {};
+    fn play(&mut self, mv: Move) {
+        self.history.push(self.board);
+        self.moves.push(mv);
+        self.board.make_move(mv);
+    }
+
+    // Checkmate or stalemate for the side to move
+    fn result(&self) -> Option<GameResult> {
+        let color = self.board.turn;
+        if self.board.checkmatep(color) {
+            Some(GameResult::win_for(color.opponent_color(), "checkmate"))
+        } else if self.board.stalematep(color) {
+            Some(GameResult::Draw(String::from("stalemate")))
+        } else {
+            None
+        }
+    }
 
-        while turns < 100 {
-            if board.checkmatep(current_turn) {
-                println!("Checkmate! Game Over");
-                return;
+    // The human's action, with the engine asked about draws and takebacks
+    fn human_action(&mut self, action: Action, engine: &mut AI) -> Outcome {
+        let human_color = self.board.turn;
+        let engine_color = human_color.opponent_color();
+        match action {
+            Action::Move(mv) => {
+                // Moving declines a standing offer
+                self.engine_offered_draw = false;
+                Outcome::Move(mv)
+            }
+            Action::OfferDraw(mv) => {
+                if let Some(reason) = draw_claim(&self.history, &self.moves, &self.board) {
+                    return Outcome::Over(GameResult::Draw(reason.to_owned()));
+                }
+                engine.draw_offered(self.board, engine_color);
+                self.human_offered_draw = true;
+                self.engine_offered_draw = false;
+                Outcome::Move(mv)
+            }
+            // Back to the human's previous turn, taking back the reply too
+            Action::RequestTakeback => {
+                match self.history.iter().rposition(|b| b.turn == human_color) {
+                    Some(index) => {
+                        if !engine.takeback_requested(self.board, engine_color) {
+                            return Outcome::Refused("The engine does not allow the takeback");
+                        }
+                        let plies = self.moves.len() - index;
+                        self.board = self.history[index];
+                        self.history.truncate(index);
+                        self.moves.truncate(index);
+                        self.engine_offered_draw = false;
+                        engine.moves_taken_back(self.board, plies);
+                        Outcome::TakenBack(plies)
+                    }
+                    None => Outcome::Refused("There is no move of yours to take back"),
+                }
             }
+            Action::Resign => Outcome::Over(GameResult::win_for(engine_color, "resignation")),
+            Action::AcceptDraw if self.engine_offered_draw => {
+                Outcome::Over(GameResult::Draw(String::from("agreement")))
+            }
+            Action::AcceptDraw => Outcome::Refused("No draw has been offered"),
+        }
+    }
 
-            if board.stalematep(current_turn) {
-                println!("Stalemate! Game Over");
-                return;
+    // The engine's action. A move declines the human's draw offer.
+    fn engine_action(&mut self, action: Action) -> Outcome {
+        let human_color = self.board.turn.opponent_color();
+        let offered = std::mem::take(&mut self.human_offered_draw);
+        match action {
+            Action::Move(mv) => Outcome::Move(mv),
+            Action::OfferDraw(mv) => {
+                self.engine_offered_draw = true;
+                Outcome::Move(mv)
+            }
+            Action::AcceptDraw if offered => {
+                Outcome::Over(GameResult::Draw(String::from("agreement")))
+            }
+            Action::Resign => Outcome::Over(GameResult::win_for(human_color, "resignation")),
+            // The engine only accepts draws it was offered and never asks for takebacks
+            Action::AcceptDraw | Action::RequestTakeback => {
+                unreachable!("the engine answered {action:?} out of turn")
             }
+        }
+    }
+}
+
+// A draw the side to move may claim: threefold repetition, or fifty moves by each side
+// without a capture or pawn move
+fn draw_claim(history: &[Board], moves: &[Move], board: &Board) -> Option<&'static str> {
+    let key = board.hash();
+    if history.iter().filter(|b| b.hash() == key).count() >= 2 {
+        return Some("threefold repetition");
+    }
+    let quiet = history
+        .iter()
+        .zip(moves)
+        .rev()
+        .take_while(|(before, mv)| {
+            before.get_piece(mv.start).piece_type != PieceType::Pawn
+                && before.get_piece(mv.end).color == Color::None
+        })
+        .count();
+    if quiet >= 100 {
+        return Some("the fifty move rule");
+    }
+    None
+}
+
+impl HumanGame {
+    fn end_game(
+        &self,
+        engine: &mut AI,
+        human: &mut Human,
+        result: GameResult,
+        start: &Board,
+        moves: &[Move],
+    ) {
+        human.game_over(&result);
+        engine.game_over(&result);
+        self.autosave(start, moves, None, result.pgn());
+    }
 
-            println!("Current Board:");
-            println!("{board}");
+    // A failed save is reported but does not stop the game
+    fn autosave(&self, start: &Board, moves: &[Move], clocks: Option<[Duration; 2]>, result: &str) {
+        let path = match &self.autosave {
+            Some(path) => path,
+            None => return,
+        };
+        let saved = SavedGame {
+            start: *start,
+            moves: moves.to_vec(),
+            human_color: self.human_color,
+            terminal_ui: self.terminal_ui,
+            clock: self.clock,
+            clocks,
+            ponder: self.ponder,
+            skill: self.engine_skill,
+            book: self.book_file.clone(),
+            eval_params: self.eval_params_file.clone(),
+            result: String::from(result),
+        };
+        if let Err(e) = saved.save(path) {
+            println!("{e}");
+        }
+    }
 
-            if current_turn == Color::White {
-                println!("White to move");
+    fn make_engine(&self) -> AI {
+        let mut engine: AI = AI::with_params(self.engine_params.clone());
+        if let Some(network) = &self.engine_network {
+            engine.set_network(network.clone());
+        }
+        if let Some(book) = &self.engine_book {
+            engine.set_book(book.clone());
+        }
+        if let Some(tablebase) = &self.engine_tablebase {
+            engine.set_tablebase(tablebase.clone());
+        }
+        if let Some(tables) = &self.engine_endgame_tables {
+            engine.set_endgame_tables(tables.clone());
+        }
+        engine
+    }
 
-                current_move = p1.take_turn(board, Color::White);
-                current_turn = Color::Black;
+    fn resumed_moves(&self) -> &[Move] {
+        self.resumed.as_ref().map_or(&[], |saved| &saved.moves)
+    }
+
+    fn play_terminal(&self, board: Board, terminal: &mut Terminal) {
+        let mut game = GameState::new(board, self.resumed_moves());
+        let mut engine = self.make_engine();
+        engine.set_skill(self.engine_skill);
+        engine.set_verbose(false);
+        engine.set_ponder(self.ponder);
+        engine.new_game(game.board, self.human_color.opponent_color());
+        let mut moves: Vec<String> = Vec::new();
+        for (before, mv) in game.history.iter().zip(&game.moves) {
+            moves.push(notation::to_san(before, *mv));
+        }
+        let mut clocks = self
+            .resumed
+            .as_ref()
+            .and_then(|saved| saved.clocks)
+            .unwrap_or([self.clock, self.clock]);
+        let mut eval: Option<i16> = None;
+        // Shown to the human on their next turn
+        let mut message = String::new();
+
+        let result = loop {
+            let board = game.board;
+            let color = board.turn;
+            let side = if color == Color::White { 0 } else { 1 };
+            let over = if clocks[side].is_zero() {
+                Some(GameResult::win_for(color.opponent_color(), "time"))
             } else {
-                println!("Black to move.");
-                current_move = p2.take_turn(board, Color::Black);
-                current_turn = Color::White;
+                game.result()
+            };
+            if let Some(result) = over {
+                break result;
             }
 
-            println!("Got move {current_move}");
-            board.make_move(current_move);
+            let move_start = Instant::now();
+            let outcome = if color == self.human_color {
+                let status = if message.is_empty() {
+                    format!("{} to move", color.name())
+                } else {
+                    std::mem::take(&mut message)
+                };
+                let view = GameView {
+                    board: &board,
+                    moves: &moves,
+                    clocks,
+                    eval,
+                    last_move: game.moves.last().copied(),
+                    status: &status,
+                };
+                match terminal.take_turn(&view) {
+                    Some(action) => game.human_action(action, &mut engine),
+                    // Quitting adjourns the game, it can be resumed from the save
+                    None => return,
+                }
+            } else {
+                let view = GameView {
+                    board: &board,
+                    moves: &moves,
+                    clocks,
+                    eval,
+                    last_move: game.moves.last().copied(),
+                    status: "Engine is thinking...",
+                };
+                terminal.draw(&view, None, None);
+                engine.time_update(clocks[side], clocks[1 - side]);
+                let declining = game.human_offered_draw;
+                let outcome = game.engine_action(engine.take_turn(board, color));
+                if let Some(score) = engine.last_score() {
+                    eval = Some(if color == Color::White { score } else { -score });
+                }
+                if game.engine_offered_draw {
+                    message = String::from("The engine offers a draw, a accepts it");
+                } else if declining && matches!(outcome, Outcome::Move(_)) {
+                    message = String::from("The engine declines the draw");
+                }
+                outcome
+            };
+            clocks[side] = clocks[side].saturating_sub(move_start.elapsed());
+
+            match outcome {
+                Outcome::Move(mv) => {
+                    if color == self.human_color {
+                        engine.opponent_moved(board, mv);
+                    }
+                    moves.push(notation::to_san(&board, mv));
+                    game.play(mv);
+                }
+                Outcome::TakenBack(_) => {
+                    moves.truncate(game.moves.len());
+                    message = String::from("Your last move was taken back");
+                }
+                Outcome::Refused(reason) => message = String::from(reason),
+                Outcome::Over(result) => break result,
+            }
+            self.autosave(&game.start, &game.moves, Some(clocks), "*");
+        };
+
+        engine.game_over(&result);
+        self.autosave(&game.start, &game.moves, Some(clocks), result.pgn());
+        let status = format!("{}. Press any key to leave.", result.description());
+        let view = GameView {
+            board: &game.board,
+            moves: &moves,
+            clocks,
+            eval,
+            last_move: game.moves.last().copied(),
+            status: &status,
+        };
+        terminal.draw(&view, None, None);
+        terminal.read_key();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::starting_board;
 
-            turns += 1;
+    fn game_after(moves: &[&str]) -> GameState {
+        let mut game = GameState::new(starting_board::make_board(), &[]);
+        for text in moves {
+            let mv = notation::parse_move(&game.board, text).unwrap();
+            game.play(mv);
         }
+        game
+    }
+
+    fn engine() -> AI {
+        let mut engine = AI::with_params(EvalParams::default());
+        engine.set_verbose(false);
+        engine
+    }
+
+    #[test]
+    fn takeback_returns_to_the_humans_last_turn() {
+        let mut game = game_after(&["e4", "e5", "Nf3", "Nc6"]);
+        let before = game_after(&["e4", "e5"]).board;
+        let outcome = game.human_action(Action::RequestTakeback, &mut engine());
+        assert_eq!(outcome, Outcome::TakenBack(2));
+        assert_eq!(game.board.fen_string(), before.fen_string());
+        assert_eq!(game.moves.len(), 2);
+        assert_eq!(game.history.len(), 2);
+
+        let mut game = game_after(&[]);
+        let outcome = game.human_action(Action::RequestTakeback, &mut engine());
+        assert!(matches!(outcome, Outcome::Refused(_)));
+    }
+
+    #[test]
+    fn draws_are_only_agreed_when_offered() {
+        let mv = notation::parse_move(&game_after(&[]).board, "e4").unwrap();
+        let mut game = game_after(&[]);
+        let outcome = game.human_action(Action::AcceptDraw, &mut engine());
+        assert!(matches!(outcome, Outcome::Refused(_)));
+
+        // The human offers and the engine accepts
+        assert_eq!(
+            game.human_action(Action::OfferDraw(mv), &mut engine()),
+            Outcome::Move(mv)
+        );
+        game.play(mv);
+        assert_eq!(
+            game.engine_action(Action::AcceptDraw),
+            Outcome::Over(GameResult::Draw(String::from("agreement")))
+        );
+
+        // The engine offers and the human accepts
+        let mut game = game_after(&["e4"]);
+        let reply = notation::parse_move(&game.board, "e5").unwrap();
+        assert_eq!(
+            game.engine_action(Action::OfferDraw(reply)),
+            Outcome::Move(reply)
+        );
+        game.play(reply);
+        assert_eq!(
+            game.human_action(Action::AcceptDraw, &mut engine()),
+            Outcome::Over(GameResult::Draw(String::from("agreement")))
+        );
+    }
+
+    #[test]
+    fn moving_declines_a_draw() {
+        let mut game = game_after(&["e4"]);
+        let reply = notation::parse_move(&game.board, "e5").unwrap();
+        game.engine_action(Action::OfferDraw(reply));
+        game.play(reply);
+        let mv = notation::parse_move(&game.board, "Nf3").unwrap();
+        assert_eq!(
+            game.human_action(Action::Move(mv), &mut engine()),
+            Outcome::Move(mv)
+        );
+        assert!(!game.engine_offered_draw);
+    }
+
+    #[test]
+    fn repetition_is_claimed_with_a_draw_offer() {
+        let mut game = game_after(&["Nf3", "Nf6", "Ng1", "Ng8", "Nf3", "Nf6", "Ng1", "Ng8"]);
+        let mv = notation::parse_move(&game.board, "e4").unwrap();
+        assert_eq!(
+            game.human_action(Action::OfferDraw(mv), &mut engine()),
+            Outcome::Over(GameResult::Draw(String::from("threefold repetition")))
+        );
+    }
+
+    #[test]
+    fn resignation() {
+        let mut game = game_after(&[]);
+        assert_eq!(
+            game.human_action(Action::Resign, &mut engine()),
+            Outcome::Over(GameResult::win_for(Color::Black, "resignation"))
+        );
+        let mut game = game_after(&["e4"]);
+        assert_eq!(
+            game.engine_action(Action::Resign),
+            Outcome::Over(GameResult::win_for(Color::White, "resignation"))
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn engine_cannot_accept_a_draw_that_was_not_offered() {
+        game_after(&["e4"]).engine_action(Action::AcceptDraw);
     }
 }
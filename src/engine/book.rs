@@ -0,0 +1,297 @@
+use super::polyglot_randoms::RANDOM64;
+use crate::model::{
+    board::Board, color::Color, move_::Move, notation, pgn, piece::Piece, piece_type::PieceType,
+    position::Position,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Opening books in the Polyglot .bin format: a list of 16 byte big endian entries
+// (key u64, move u16, weight u16, learn u32) sorted by key.
+//
+// The key is a Zobrist hash over the Random64 table published with the format, so books
+// made by other programs can be read: 768 numbers for (piece, square), 4 for castling
+// rights, 8 for the en passant file and 1 for White to move.
+
+const CASTLE_OFFSET: usize = 768;
+const EN_PASSANT_OFFSET: usize = 772;
+const TURN_OFFSET: usize = 780;
+
+pub(crate) fn polyglot_key(board: &Board) -> u64 {
+    let mut key: u64 = 0;
+
+    for y in 0..8 {
+        for x in 0..8 {
+            let piece: Piece = board.board[y][x];
+            // Black pieces come first in Polyglot's ordering: bp, wp, bn, wn ...
+            let kind = match piece.piece_type {
+                PieceType::Pawn => 0,
+                PieceType::Knight => 1,
+                PieceType::Bishop => 2,
+                PieceType::Rook => 3,
+                PieceType::Queen => 4,
+                PieceType::King => 5,
+                PieceType::Empty => continue,
+            };
+            let index = kind * 2 + if piece.color == Color::White { 1 } else { 0 };
+            key ^= RANDOM64[64 * index + 8 * y + x];
+        }
+    }
+
+    for (i, allowed) in [
+        board.white_kingside,
+        board.white_queenside,
+        board.black_kingside,
+        board.black_queenside,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        if allowed {
+            key ^= RANDOM64[CASTLE_OFFSET + i];
+        }
+    }
+
+    // Only counted when a pawn of the side to move could actually take en passant
+    let pawn = board.en_pessant;
+    if pawn.validp() {
+        let can_capture = [pawn.left(), pawn.right()].iter().any(|p| {
+            p.validp()
+                && board.get_piece(*p)
+                    == Piece {
+                        piece_type: PieceType::Pawn,
+                        color: board.turn,
+                    }
+        });
+        if can_capture {
+            key ^= RANDOM64[EN_PASSANT_OFFSET + pawn.x as usize];
+        }
+    }
+
+    if board.turn == Color::White {
+        key ^= RANDOM64[TURN_OFFSET];
+    }
+    key
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum BookMode {
+    // Always play the move with the highest weight
+    Best,
+    // Pick at random, in proportion to the weights
+    Weighted,
+}
+
+#[derive(Copy, Clone)]
+struct BookEntry {
+    key: u64,
+    mv: u16,
+    weight: u16,
+}
+
+pub(crate) struct Book {
+    entries: Vec<BookEntry>,
+    mode: BookMode,
+}
+
+// Polyglot moves: bits 0-5 destination, 6-11 origin (each row * 8 + file), 12-14 promotion
+fn encode_move(board: &Board, mv: Move) -> u16 {
+    let piece = board.get_piece(mv.start);
+    let mut end = mv.end;
+    // Castling is written as the king taking its own rook
+    if piece.piece_type == PieceType::King && mv.kingside_castlep() {
+        end = Position { x: 7, y: mv.end.y };
+    } else if piece.piece_type == PieceType::King && mv.queenside_castlep() {
+        end = Position { x: 0, y: mv.end.y };
+    }
+    let promotion: u16 = if piece.piece_type == PieceType::Pawn && (mv.end.y == 0 || mv.end.y == 7)
+    {
+        4
+    } else {
+        0
+    };
+    (end.x + end.y * 8) as u16 | (((mv.start.x + mv.start.y * 8) as u16) << 6) | (promotion << 12)
+}
+
+fn decode_move(board: &Board, encoded: u16) -> Move {
+    let end = Position {
+        x: (encoded & 7) as isize,
+        y: ((encoded >> 3) & 7) as isize,
+    };
+    let start = Position {
+        x: ((encoded >> 6) & 7) as isize,
+        y: ((encoded >> 9) & 7) as isize,
+    };
+    let piece = board.get_piece(start);
+    let rook = board.get_piece(end);
+    if piece.piece_type == PieceType::King
+        && rook.piece_type == PieceType::Rook
+        && rook.color == piece.color
+    {
+        let x = if end.x == 7 { 6 } else { 2 };
+        return Move {
+            start,
+            end: Position { x, y: end.y },
+        };
+    }
+    Move { start, end }
+}
+
+impl Book {
+    pub(crate) fn load(path: &str, mode: BookMode) -> Result<Book, String> {
+        let bytes = fs::read(path).map_err(|e| format!("Could not read {path}: {e}"))?;
+        if bytes.len() % 16 != 0 {
+            return Err(format!("{path} is not a Polyglot book"));
+        }
+        let entries = bytes
+            .chunks_exact(16)
+            .map(|e| BookEntry {
+                key: u64::from_be_bytes(e[0..8].try_into().unwrap()),
+                mv: u16::from_be_bytes([e[8], e[9]]),
+                weight: u16::from_be_bytes([e[10], e[11]]),
+            })
+            .collect();
+        Ok(Book { entries, mode })
+    }
+
+    // All book moves for the position that are legal, with their weights
+    pub(crate) fn moves(&self, board: &Board) -> Vec<(Move, u16)> {
+        let key = polyglot_key(board);
+        let first = self.entries.partition_point(|e| e.key < key);
+        let legal = board.get_all_moves(board.turn);
+        self.entries[first..]
+            .iter()
+            .take_while(|e| e.key == key)
+            .map(|e| (decode_move(board, e.mv), e.weight))
+            .filter(|(mv, _)| legal.contains(mv))
+            .collect()
+    }
+
+    pub(crate) fn pick(&self, board: &Board) -> Option<Move> {
+        let moves = self.moves(board);
+        if moves.is_empty() {
+            return None;
+        }
+        match self.mode {
+            BookMode::Best => moves.iter().max_by_key(|(_, weight)| *weight).map(|m| m.0),
+            BookMode::Weighted => {
+                let total: u64 = moves.iter().map(|(_, w)| *w as u64).sum();
+                if total == 0 {
+                    return Some(moves[0].0);
+                }
+                let mut roll = random_seed() % total;
+                for (mv, weight) in &moves {
+                    if roll < *weight as u64 {
+                        return Some(*mv);
+                    }
+                    roll -= *weight as u64;
+                }
+                Some(moves[moves.len() - 1].0)
+            }
+        }
+    }
+}
+
+pub(crate) fn random_seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64);
+    // Scramble so consecutive calls are not close together
+    nanos.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 16
+}
+
+// Builds a book from the first `max_plies` moves of every game in a PGN file. Each move
+// scores 2 for a win of the side that played it, 1 for a draw and 0 for a loss, and the
+// totals are scaled down to fit the 16 bit weights. Returns the number of games used.
+pub(crate) fn build_book(pgn_path: &str, output: &str, max_plies: usize) -> Result<usize, String> {
+    let text =
+        fs::read_to_string(pgn_path).map_err(|e| format!("Could not read {pgn_path}: {e}"))?;
+    let mut scores: HashMap<(u64, u16), u64> = HashMap::new();
+    let mut used = 0;
+
+    for game in pgn::parse_games(&text) {
+        let mut board = match game.start_board() {
+            Ok(board) => board,
+            Err(_) => continue,
+        };
+        used += 1;
+        for san in game.moves.iter().take(max_plies) {
+            // Stop at the first move we cannot follow, such as an underpromotion
+            let mv = match notation::parse_move(&board, san) {
+                Ok(mv) => mv,
+                Err(_) => break,
+            };
+            let points = match (game.result.as_str(), board.turn) {
+                ("1-0", Color::White) | ("0-1", Color::Black) => 2,
+                ("1/2-1/2", _) => 1,
+                _ => 0,
+            };
+            *scores
+                .entry((polyglot_key(&board), encode_move(&board, mv)))
+                .or_insert(0) += points;
+            board.make_move(mv);
+        }
+    }
+
+    let max = scores.values().copied().max().unwrap_or(0);
+    let scale = if max > u16::MAX as u64 {
+        max.div_ceil(u16::MAX as u64)
+    } else {
+        1
+    };
+    let mut entries: Vec<(u64, u16, u16)> = scores
+        .into_iter()
+        .map(|((key, mv), score)| (key, mv, (score / scale) as u16))
+        .collect();
+    // Polyglot readers binary search on the key, best moves first within a key
+    entries.sort_by(|a, b| a.0.cmp(&b.0).then(b.2.cmp(&a.2)));
+
+    let mut bytes: Vec<u8> = Vec::with_capacity(entries.len() * 16);
+    for (key, mv, weight) in entries {
+        bytes.extend_from_slice(&key.to_be_bytes());
+        bytes.extend_from_slice(&mv.to_be_bytes());
+        bytes.extend_from_slice(&weight.to_be_bytes());
+        bytes.extend_from_slice(&0u32.to_be_bytes());
+    }
+    fs::write(output, bytes).map_err(|e| format!("Could not write {output}: {e}"))?;
+    Ok(used)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::starting_board;
+
+    // Keys from the examples in the Polyglot book format specification
+    #[test]
+    fn start_position_key() {
+        let board = starting_board::make_board();
+        assert_eq!(polyglot_key(&board), 0x463b96181691fc9c);
+    }
+
+    #[test]
+    fn key_after_e4() {
+        let mut board = starting_board::make_board();
+        board.make_move(notation::parse_move(&board, "e4").unwrap());
+        assert_eq!(polyglot_key(&board), 0x823c9b50fd114196);
+    }
+
+    #[test]
+    fn key_counts_en_passant_only_when_capturable() {
+        let board =
+            Board::from_fen("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3")
+                .unwrap();
+        assert_eq!(polyglot_key(&board), 0x22a48b5a8e47ff78);
+        let board =
+            Board::from_fen("rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2").unwrap();
+        assert_eq!(polyglot_key(&board), 0x662fafb965db29d4);
+    }
+
+    #[test]
+    fn key_without_castling_rights() {
+        let board =
+            Board::from_fen("rnbq1bnr/ppp1pkpp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR w - - 0 4").unwrap();
+        assert_eq!(polyglot_key(&board), 0x00fdd303c946bdd9);
+    }
+}
@@ -0,0 +1,756 @@
+use super::syzygy::side_material;
+use crate::model::{
+    board::Board, color::Color, move_::Move, piece::Piece, piece_type::PieceType,
+    position::Position,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::thread;
+
+// Distance to mate tables for small endings, built by retrograde analysis.
+//
+// A table covers every placement of its pieces with either side to move. Each position
+// gets one byte: 0 for a draw, p for a win with mate in p plies, 128 + p for a loss that
+// is mated in p plies, 255 for an impossible placement. Building one goes:
+//
+// 1. Generate the moves of every position with `Board`. Mates are lost in 0 plies.
+//    Captures and promotions leave the table, their result comes from the smaller (or
+//    promoted) table, which is built first. The other moves are counted.
+// 2. Work outwards by distance. When a position is lost in p plies every position that
+//    can move into it is won in p + 1. When a position is won, every position that can
+//    move into it has one fewer move left that avoids losing, and once none remain it
+//    is lost too.
+// 3. Whatever is never reached is a draw.
+//
+// Promotions are always to a queen and en passant is not considered, matching the move
+// generator.
+//
+// On disk only positions with the white king on files a-d are kept, and without pawns
+// only those with it in the a1-d1-d4 triangle, since mirroring the board gives the rest.
+// Those bytes are run length encoded. File layout: magic "CDTM", u8 name length, name
+// ("KRvKP"), then (value u8, run length as LEB128) pairs up to the end of the file.
+
+const MAGIC: &[u8; 4] = b"CDTM";
+const DRAW: u8 = 0;
+const LOSS: u8 = 128;
+const INVALID: u8 = 255;
+const MAX_PLIES: usize = 126;
+
+const PIECE_ORDER: [(char, PieceType); 6] = [
+    ('K', PieceType::King),
+    ('Q', PieceType::Queen),
+    ('R', PieceType::Rook),
+    ('B', PieceType::Bishop),
+    ('N', PieceType::Knight),
+    ('P', PieceType::Pawn),
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Dtm {
+    // The side to move mates in this many plies
+    Win(u8),
+    // The side to move is mated in this many plies
+    Loss(u8),
+    Draw,
+}
+
+impl Dtm {
+    fn from_byte(value: u8) -> Option<Dtm> {
+        match value {
+            INVALID => None,
+            DRAW => Some(Dtm::Draw),
+            v if v >= LOSS => Some(Dtm::Loss(v - LOSS)),
+            v => Some(Dtm::Win(v)),
+        }
+    }
+}
+
+struct Table {
+    pieces: Vec<Piece>,
+    values: Vec<u8>,
+}
+
+fn piece_type_of(letter: char) -> Option<PieceType> {
+    PIECE_ORDER
+        .iter()
+        .find(|(c, _)| *c == letter)
+        .map(|(_, piece_type)| *piece_type)
+}
+
+// Puts a side's letters in the usual order, "KPR" becomes "KRP"
+fn normalize_side(side: &str) -> String {
+    let mut letters: Vec<char> = side.chars().collect();
+    letters.sort_by_key(|c| PIECE_ORDER.iter().position(|(p, _)| p == c));
+    letters.into_iter().collect()
+}
+
+fn side_value(side: &str) -> u32 {
+    side.chars()
+        .map(|c| match c {
+            'Q' => 9,
+            'R' => 5,
+            'B' | 'N' => 3,
+            'P' => 1,
+            _ => 0,
+        })
+        .sum()
+}
+
+// The name a table is stored under, stronger side first, and whether the colours had to
+// be swapped to get there
+fn canonical(white: &str, black: &str) -> (String, bool) {
+    if (side_value(white), white) >= (side_value(black), black) {
+        return (format!("{white}v{black}"), false);
+    }
+    (format!("{black}v{white}"), true)
+}
+
+// Reads "KRvKP" or "KRKP" into the two sides
+fn parse_material(material: &str) -> Result<(String, String), String> {
+    let text = material.trim().to_uppercase();
+    let (white, black) = match text.split_once('V') {
+        Some(sides) => sides,
+        None => match text.get(1..).and_then(|rest| rest.find('K')) {
+            Some(i) => text.split_at(i + 1),
+            None => return Err(format!("{material} needs a king on each side")),
+        },
+    };
+    for side in [white, black] {
+        if !side.starts_with('K') || side.matches('K').count() != 1 {
+            return Err(format!("{material} needs exactly one king on each side"));
+        }
+        if side.chars().any(|c| piece_type_of(c).is_none()) {
+            return Err(format!("{material} has an unknown piece letter"));
+        }
+    }
+    if white.len() + black.len() > 4 {
+        return Err(format!("{material} has more than 4 pieces"));
+    }
+    Ok((normalize_side(white), normalize_side(black)))
+}
+
+// Materials a capture or promotion can lead to
+fn successors(white: &str, black: &str) -> Vec<(String, String)> {
+    let mut result: Vec<(String, String)> = Vec::new();
+    for (us, them, white_moves) in [(white, black, true), (black, white, false)] {
+        let mut reachable: Vec<(String, String)> = Vec::new();
+        // Captures of each kind of enemy piece
+        for (i, c) in them.char_indices().skip(1) {
+            let mut fewer = them.to_owned();
+            fewer.remove(i);
+            reachable.push((us.to_owned(), fewer));
+            if us.contains('P') {
+                // Capturing while promoting
+                reachable.push((us.replacen('P', "Q", 1), them.replacen(c, "", 1)));
+            }
+        }
+        if us.contains('P') {
+            reachable.push((us.replacen('P', "Q", 1), them.to_owned()));
+        }
+        for (u, t) in reachable {
+            let (u, t) = (normalize_side(&u), normalize_side(&t));
+            result.push(if white_moves { (u, t) } else { (t, u) });
+        }
+    }
+    result
+}
+
+fn square(sq: usize) -> Position {
+    Position {
+        x: (sq % 8) as isize,
+        y: (sq / 8) as isize,
+    }
+}
+
+fn promotionp(board: &Board, mv: Move) -> bool {
+    board.get_piece(mv.start).piece_type == PieceType::Pawn && (mv.end.y == 0 || mv.end.y == 7)
+}
+
+impl Table {
+    fn new(white: &str, black: &str) -> Table {
+        let mut pieces: Vec<Piece> = Vec::new();
+        for (side, color) in [(white, Color::White), (black, Color::Black)] {
+            for c in side.chars() {
+                pieces.push(Piece {
+                    piece_type: piece_type_of(c).unwrap(),
+                    color,
+                });
+            }
+        }
+        let size = 2 * 64usize.pow(pieces.len() as u32);
+        Table {
+            pieces,
+            values: vec![DRAW; size],
+        }
+    }
+
+    fn squares(&self, idx: usize) -> (Color, Vec<usize>) {
+        let mut rest = idx;
+        let mut squares = vec![0; self.pieces.len()];
+        for i in (0..self.pieces.len()).rev() {
+            squares[i] = rest % 64;
+            rest /= 64;
+        }
+        let turn = if rest == 0 {
+            Color::White
+        } else {
+            Color::Black
+        };
+        (turn, squares)
+    }
+
+    fn index(&self, turn: Color, squares: &[usize]) -> usize {
+        let mut idx = if turn == Color::White { 0 } else { 1 };
+        for sq in squares {
+            idx = idx * 64 + sq;
+        }
+        idx
+    }
+
+    fn has_pawns(&self) -> bool {
+        self.pieces.iter().any(|p| p.piece_type == PieceType::Pawn)
+    }
+
+    // Whether `idx` is one of the positions written to disk
+    fn storedp(&self, idx: usize) -> bool {
+        let king = self.squares(idx).1[0];
+        let (x, y) = (king % 8, king / 8);
+        x <= 3 && (self.has_pawns() || y <= x)
+    }
+
+    // The stored position that `idx` is a mirror image of
+    fn stored_index(&self, idx: usize) -> usize {
+        let (turn, squares) = self.squares(idx);
+        // Bit 0 mirrors files, bit 1 ranks and bit 2 the a1-h8 diagonal. Pawns only allow
+        // the first.
+        let symmetries = if self.has_pawns() { 2 } else { 8 };
+        for t in 0..symmetries {
+            let mirrored: Vec<usize> = squares
+                .iter()
+                .map(|sq| {
+                    let mut sq = *sq;
+                    if t & 1 != 0 {
+                        sq ^= 7;
+                    }
+                    if t & 2 != 0 {
+                        sq ^= 56;
+                    }
+                    if t & 4 != 0 {
+                        sq = ((sq >> 3) | (sq << 3)) & 63;
+                    }
+                    sq
+                })
+                .collect();
+            let candidate = self.index(turn, &mirrored);
+            if self.storedp(candidate) {
+                return candidate;
+            }
+        }
+        idx
+    }
+
+    // The position at `idx`, or None when the placement cannot happen in a game
+    fn board_at(&self, idx: usize) -> Option<Board> {
+        let (turn, squares) = self.squares(idx);
+        let mut board = Board::empty();
+        board.turn = turn;
+        for (piece, sq) in self.pieces.iter().zip(&squares) {
+            let pos = square(*sq);
+            if board.get_piece(pos).piece_type != PieceType::Empty {
+                return None;
+            }
+            if piece.piece_type == PieceType::Pawn && (pos.y == 0 || pos.y == 7) {
+                return None;
+            }
+            board.place_piece(pos, *piece);
+        }
+        // The side that just moved cannot be left in check
+        if board.in_checkp(turn.opponent_color()) {
+            return None;
+        }
+        Some(board)
+    }
+
+    // Positions with the other side to move that reach `idx` with a move that stays in
+    // the table, that is any move but a capture or a promotion
+    fn predecessors(&self, idx: usize) -> Vec<usize> {
+        let (turn, squares) = self.squares(idx);
+        let mover = turn.opponent_color();
+        let mut occupied = [false; 64];
+        for sq in &squares {
+            occupied[*sq] = true;
+        }
+
+        let mut result: Vec<usize> = Vec::new();
+        let mut before = squares.clone();
+        for (i, piece) in self.pieces.iter().enumerate() {
+            if piece.color != mover {
+                continue;
+            }
+            let (x, y) = ((squares[i] % 8) as isize, (squares[i] / 8) as isize);
+            let free = |x: isize, y: isize| {
+                (0..8).contains(&x) && (0..8).contains(&y) && !occupied[(y * 8 + x) as usize]
+            };
+            let mut origins: Vec<(isize, isize)> = Vec::new();
+            let steps: &[(isize, isize)] = match piece.piece_type {
+                PieceType::King | PieceType::Queen => &[
+                    (1, 0),
+                    (-1, 0),
+                    (0, 1),
+                    (0, -1),
+                    (1, 1),
+                    (1, -1),
+                    (-1, 1),
+                    (-1, -1),
+                ],
+                PieceType::Rook => &[(1, 0), (-1, 0), (0, 1), (0, -1)],
+                PieceType::Bishop => &[(1, 1), (1, -1), (-1, 1), (-1, -1)],
+                PieceType::Knight => &[
+                    (1, 2),
+                    (2, 1),
+                    (2, -1),
+                    (1, -2),
+                    (-1, -2),
+                    (-2, -1),
+                    (-2, 1),
+                    (-1, 2),
+                ],
+                PieceType::Pawn | PieceType::Empty => &[],
+            };
+            let slides = matches!(
+                piece.piece_type,
+                PieceType::Queen | PieceType::Rook | PieceType::Bishop
+            );
+            for (dx, dy) in steps {
+                let (mut ox, mut oy) = (x + dx, y + dy);
+                while free(ox, oy) {
+                    origins.push((ox, oy));
+                    if !slides {
+                        break;
+                    }
+                    ox += dx;
+                    oy += dy;
+                }
+            }
+            if piece.piece_type == PieceType::Pawn {
+                let (back, start_rank) = if mover == Color::White {
+                    (-1, 1)
+                } else {
+                    (1, 6)
+                };
+                if (1..=6).contains(&(y + back)) && free(x, y + back) {
+                    origins.push((x, y + back));
+                    if y + 2 * back == start_rank && free(x, y + 2 * back) {
+                        origins.push((x, y + 2 * back));
+                    }
+                }
+            }
+
+            for (ox, oy) in origins {
+                before[i] = (oy * 8 + ox) as usize;
+                result.push(self.index(mover, &before));
+            }
+            before[i] = squares[i];
+        }
+        result
+    }
+}
+
+pub(crate) struct EndgameTables {
+    tables: HashMap<String, Table>,
+}
+
+impl EndgameTables {
+    pub(crate) fn new() -> EndgameTables {
+        EndgameTables {
+            tables: HashMap::new(),
+        }
+    }
+
+    // Builds the table for a material like "KRvK" or "KRK", along with every smaller
+    // table it depends on. Returns the names of the tables built.
+    pub(crate) fn generate(&mut self, material: &str) -> Result<Vec<String>, String> {
+        let (white, black) = parse_material(material)?;
+        let mut built: Vec<String> = Vec::new();
+        self.ensure(&white, &black, &mut built)?;
+        Ok(built)
+    }
+
+    fn ensure(&mut self, white: &str, black: &str, built: &mut Vec<String>) -> Result<(), String> {
+        let (name, flipped) = canonical(white, black);
+        if self.tables.contains_key(&name) {
+            return Ok(());
+        }
+        let (white, black) = if flipped {
+            (black, white)
+        } else {
+            (white, black)
+        };
+        for (w, b) in successors(white, black) {
+            self.ensure(&w, &b, built)?;
+        }
+        let table = self.build(white, black)?;
+        self.tables.insert(name.clone(), table);
+        built.push(name);
+        Ok(())
+    }
+
+    fn build(&self, white: &str, black: &str) -> Result<Table, String> {
+        let mut table = Table::new(white, black);
+        let size = table.values.len();
+        let mut remaining: Vec<u8> = vec![0; size];
+        // Plies to the slowest mate among captures and promotions that lose
+        let mut exit_loss: Vec<u8> = vec![0; size];
+        // False once some move is known to draw or win
+        let mut can_lose: Vec<bool> = vec![true; size];
+
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk = size.div_ceil(threads);
+        let table_ref = &table;
+        let mut values = vec![DRAW; size];
+        let pending: Vec<(usize, usize)> = thread::scope(|scope| {
+            let handles: Vec<_> = values
+                .chunks_mut(chunk)
+                .zip(remaining.chunks_mut(chunk))
+                .zip(exit_loss.chunks_mut(chunk))
+                .zip(can_lose.chunks_mut(chunk))
+                .enumerate()
+                .map(|(t, (((values, remaining), exit_loss), can_lose))| {
+                    scope.spawn(move || {
+                        let mut pending: Vec<(usize, usize)> = Vec::new();
+                        for j in 0..values.len() {
+                            let idx = t * chunk + j;
+                            let board = match table_ref.board_at(idx) {
+                                Some(board) => board,
+                                None => {
+                                    values[j] = INVALID;
+                                    continue;
+                                }
+                            };
+                            let moves = board.get_all_moves(board.turn);
+                            if moves.is_empty() {
+                                if board.in_checkp(board.turn) {
+                                    pending.push((0, idx));
+                                }
+                                can_lose[j] = false;
+                                continue;
+                            }
+                            let mut best_win: Option<usize> = None;
+                            for mv in moves {
+                                if !board.capturep(mv) && !promotionp(&board, mv) {
+                                    remaining[j] += 1;
+                                    continue;
+                                }
+                                let mut after = board;
+                                after.make_move(mv);
+                                match self.probe(&after).unwrap_or(Dtm::Draw) {
+                                    Dtm::Loss(p) => {
+                                        let plies = p as usize + 1;
+                                        best_win = Some(best_win.map_or(plies, |b| b.min(plies)));
+                                        can_lose[j] = false;
+                                    }
+                                    Dtm::Win(p) => exit_loss[j] = exit_loss[j].max(p + 1),
+                                    Dtm::Draw => can_lose[j] = false,
+                                }
+                            }
+                            if let Some(plies) = best_win {
+                                pending.push((plies, idx));
+                            } else if remaining[j] == 0 && can_lose[j] {
+                                pending.push((exit_loss[j] as usize, idx));
+                            }
+                        }
+                        pending
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        });
+        table.values = values;
+
+        let mut buckets: Vec<Vec<usize>> = vec![Vec::new(); MAX_PLIES + 2];
+        for (plies, idx) in pending {
+            buckets[plies.min(MAX_PLIES + 1)].push(idx);
+        }
+        for plies in 0..=MAX_PLIES {
+            let current = std::mem::take(&mut buckets[plies]);
+            for idx in current {
+                if table.values[idx] != DRAW {
+                    continue;
+                }
+                // Wins take an odd number of plies, losses an even number
+                table.values[idx] = if plies % 2 == 1 {
+                    plies as u8
+                } else {
+                    LOSS + plies as u8
+                };
+                for pred in table.predecessors(idx) {
+                    if table.values[pred] != DRAW {
+                        continue;
+                    }
+                    if plies % 2 == 0 {
+                        buckets[plies + 1].push(pred);
+                    } else {
+                        remaining[pred] -= 1;
+                        if remaining[pred] == 0 && can_lose[pred] {
+                            let at = (plies + 1).max(exit_loss[pred] as usize);
+                            buckets[at.min(MAX_PLIES + 1)].push(pred);
+                        }
+                    }
+                }
+            }
+        }
+        if !buckets[MAX_PLIES + 1].is_empty() {
+            return Err(format!(
+                "{white}v{black} has mates longer than {MAX_PLIES} plies"
+            ));
+        }
+        Ok(table)
+    }
+
+    // Exact result for the side to move, when the table for the material is loaded and
+    // the position has no castling rights
+    pub(crate) fn probe(&self, board: &Board) -> Option<Dtm> {
+        if board.white_kingside
+            || board.white_queenside
+            || board.black_kingside
+            || board.black_queenside
+        {
+            return None;
+        }
+        let white = side_material(board, Color::White);
+        let black = side_material(board, Color::Black);
+        let (name, flipped) = canonical(&white, &black);
+        let table = self.tables.get(&name)?;
+
+        // Swapping colours also mirrors the board so pawns keep their direction
+        let mut used = [false; 64];
+        let mut squares: Vec<usize> = Vec::with_capacity(table.pieces.len());
+        for piece in &table.pieces {
+            let color = if flipped {
+                piece.color.opponent_color()
+            } else {
+                piece.color
+            };
+            let sq = (0..64).find(|sq| {
+                !used[*sq]
+                    && board.board[sq / 8][sq % 8]
+                        == Piece {
+                            piece_type: piece.piece_type,
+                            color,
+                        }
+            })?;
+            used[sq] = true;
+            squares.push(if flipped { sq ^ 56 } else { sq });
+        }
+        let turn = if flipped {
+            board.turn.opponent_color()
+        } else {
+            board.turn
+        };
+        Dtm::from_byte(table.values[table.index(turn, &squares)])
+    }
+
+    // The move reaching the fastest mate, or failing that a draw, or the slowest loss.
+    // None when some position after a move is not covered.
+    pub(crate) fn best_move(&self, board: &Board) -> Option<(Move, Dtm)> {
+        let mut best: Option<(Move, Dtm, i32)> = None;
+        for mv in board.get_all_moves(board.turn) {
+            let mut after = *board;
+            after.make_move(mv);
+            let result = match self.probe(&after)? {
+                Dtm::Loss(p) => Dtm::Win(p + 1),
+                Dtm::Win(p) => Dtm::Loss(p + 1),
+                Dtm::Draw => Dtm::Draw,
+            };
+            let rank = match result {
+                Dtm::Win(p) => 1000 - p as i32,
+                Dtm::Loss(p) => -1000 + p as i32,
+                Dtm::Draw => 0,
+            };
+            if best.is_none_or(|(_, _, best_rank)| rank > best_rank) {
+                best = Some((mv, result, rank));
+            }
+        }
+        best.map(|(mv, result, _)| (mv, result))
+    }
+
+    // Longest mate in a table and how many positions are won, drawn and lost
+    pub(crate) fn summary(&self, name: &str) -> Option<(u8, usize, usize, usize)> {
+        let table = self.tables.get(name)?;
+        let (mut longest, mut wins, mut draws, mut losses) = (0, 0, 0, 0);
+        for value in &table.values {
+            match Dtm::from_byte(*value) {
+                Some(Dtm::Win(p)) => {
+                    wins += 1;
+                    longest = longest.max(p);
+                }
+                Some(Dtm::Loss(p)) => {
+                    losses += 1;
+                    longest = longest.max(p);
+                }
+                Some(Dtm::Draw) => draws += 1,
+                None => {}
+            }
+        }
+        Some((longest, wins, draws, losses))
+    }
+
+    pub(crate) fn save(&self, dir: &str) -> Result<(), String> {
+        fs::create_dir_all(dir).map_err(|e| format!("Could not create {dir}: {e}"))?;
+        for (name, table) in &self.tables {
+            let mut bytes: Vec<u8> = Vec::new();
+            bytes.extend_from_slice(MAGIC);
+            bytes.push(name.len() as u8);
+            bytes.extend_from_slice(name.as_bytes());
+            let stored: Vec<u8> = (0..table.values.len())
+                .filter(|idx| table.storedp(*idx))
+                .map(|idx| table.values[idx])
+                .collect();
+            let mut i = 0;
+            while i < stored.len() {
+                let value = stored[i];
+                let mut run = stored[i..].iter().take_while(|v| **v == value).count();
+                i += run;
+                bytes.push(value);
+                loop {
+                    let byte = (run & 0x7F) as u8;
+                    run >>= 7;
+                    if run == 0 {
+                        bytes.push(byte);
+                        break;
+                    }
+                    bytes.push(byte | 0x80);
+                }
+            }
+            let path = Path::new(dir).join(format!("{name}.dtm"));
+            fs::write(&path, bytes)
+                .map_err(|e| format!("Could not write {}: {e}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    // Loads every .dtm file in `dir`
+    pub(crate) fn load(dir: &str) -> Result<EndgameTables, String> {
+        let mut tables = EndgameTables::new();
+        let entries = fs::read_dir(dir).map_err(|e| format!("Could not read {dir}: {e}"))?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "dtm") {
+                let (name, table) = read_table(&path)?;
+                tables.tables.insert(name, table);
+            }
+        }
+        Ok(tables)
+    }
+
+    pub(crate) fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.tables.keys().cloned().collect();
+        names.sort();
+        names
+    }
+}
+
+fn read_table(path: &Path) -> Result<(String, Table), String> {
+    let shown = path.display();
+    let bytes = fs::read(path).map_err(|e| format!("Could not read {shown}: {e}"))?;
+    if bytes.len() < 5 || &bytes[0..4] != MAGIC {
+        return Err(format!("{shown} is not an endgame table"));
+    }
+    let name_end = 5 + bytes[4] as usize;
+    let name = bytes
+        .get(5..name_end)
+        .and_then(|name| String::from_utf8(name.to_vec()).ok())
+        .ok_or_else(|| format!("{shown} has a bad name"))?;
+    let (white, black) = parse_material(&name)?;
+    let mut table = Table::new(&white, &black);
+    let size = (0..table.values.len())
+        .filter(|idx| table.storedp(*idx))
+        .count();
+
+    let mut values: Vec<u8> = Vec::with_capacity(size);
+    let mut pos = name_end;
+    while pos < bytes.len() {
+        let value = bytes[pos];
+        pos += 1;
+        let mut run = 0usize;
+        let mut shift = 0;
+        loop {
+            let byte = *bytes
+                .get(pos)
+                .ok_or_else(|| format!("{shown} is truncated"))?;
+            pos += 1;
+            run |= ((byte & 0x7F) as usize) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        if values.len() + run > size {
+            return Err(format!("{shown} holds too many positions"));
+        }
+        values.resize(values.len() + run, value);
+    }
+    if values.len() != size {
+        return Err(format!("{shown} holds too few positions"));
+    }
+    let mut stored = values.into_iter();
+    for idx in 0..table.values.len() {
+        if table.storedp(idx) {
+            table.values[idx] = stored.next().unwrap();
+        }
+    }
+    for idx in 0..table.values.len() {
+        if !table.storedp(idx) {
+            table.values[idx] = table.values[table.stored_index(idx)];
+        }
+    }
+    Ok((name, table))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queen_mates_within_ten_moves() {
+        let mut tables = EndgameTables::new();
+        assert_eq!(
+            tables.generate("KQK"),
+            Ok(vec![String::from("KvK"), String::from("KQvK")])
+        );
+        // The longest win is mate in ten with the defender to move
+        let (longest, wins, draws, losses) = tables.summary("KQvK").unwrap();
+        assert_eq!(longest, 20);
+        assert!(wins > 0 && draws > 0 && losses > 0);
+
+        let dtm = |fen: &str| tables.probe(&Board::from_fen(fen).unwrap());
+        assert_eq!(dtm("k7/8/1K6/8/8/8/7Q/8 w - - 0 1"), Some(Dtm::Win(1)));
+        assert_eq!(dtm("k7/8/1K6/8/8/7Q/8/8 b - - 0 1"), Some(Dtm::Loss(2)));
+        // Stalemated, and a queen that can be taken
+        assert_eq!(dtm("k7/8/1K6/8/8/8/7Q/8 b - - 0 1"), Some(Dtm::Draw));
+        assert_eq!(dtm("k7/1Q6/8/8/8/8/8/6K1 b - - 0 1"), Some(Dtm::Draw));
+        // Black's queen is found by swapping colours
+        assert_eq!(dtm("8/8/7q/8/8/1k6/8/K7 w - - 0 1"), Some(Dtm::Loss(2)));
+        assert_eq!(
+            dtm("8/8/8/3k4/8/8/8/Q3K3 w - - 0 1"),
+            dtm("q3k3/8/8/8/3K4/8/8/8 b - - 0 1")
+        );
+        // The king in check with the other side to move
+        assert_eq!(dtm("k6Q/8/1K6/8/8/8/8/8 w - - 0 1"), None);
+
+        // The best move keeps to the table's distance
+        let fen = "8/8/8/3k4/8/8/8/Q3K3 w - - 0 1";
+        let (mv, result) = tables.best_move(&Board::from_fen(fen).unwrap()).unwrap();
+        assert_eq!(Some(result), dtm(fen));
+        let mut after = Board::from_fen(fen).unwrap();
+        after.make_move(mv);
+        let Some(Dtm::Win(plies)) = dtm(fen) else {
+            panic!("{fen} is a win");
+        };
+        assert_eq!(tables.probe(&after), Some(Dtm::Loss(plies - 1)));
+    }
+}
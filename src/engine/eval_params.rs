@@ -0,0 +1,363 @@
+use std::fs;
+
+// Every weight used by the evaluator. The defaults are the hand-tuned values; other sets can
+// be loaded from a plain text file to compare them in engine matches without recompiling.
+//
+// File format: a parameter name followed by its values, separated by whitespace. Values may
+// run over several lines, and anything after a `#` is a comment. Parameters missing from a
+// file keep their default value.
+//
+// All (mg, eg) pairs hold the middlegame value first. Piece-indexed arrays use the order
+// pawn, knight, bishop, rook, queen, king. Piece-square tables are written from White's
+// point of view as the board is printed: the first row is the eighth rank.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct EvalParams {
+    pub(crate) material_mg: [i32; 6],
+    pub(crate) material_eg: [i32; 6],
+    pub(crate) pst_mg: [[i32; 64]; 6],
+    pub(crate) pst_eg: [[i32; 64]; 6],
+
+    pub(crate) rook_open_file: i32,
+    pub(crate) rook_semi_open_file: i32,
+    pub(crate) bishop_pair: i32,
+
+    // Own pawn one or two squares in front of the king, or no pawn at all
+    pub(crate) king_shield: [i32; 3],
+    // Enemy pawn two, three or four squares in front of the king
+    pub(crate) king_storm: [i32; 3],
+    // File next to the king with no pawns at all, or none of our own
+    pub(crate) king_open_file: [i32; 2],
+    // Attack units per attacked king zone square: minor piece, rook, queen, other
+    pub(crate) king_attack_weights: [i32; 4],
+    // Indexed by attack units on the king zone
+    pub(crate) safety_table: [i32; 64],
+
+    pub(crate) doubled_pawn: [i32; 2],
+    pub(crate) isolated_pawn: [i32; 2],
+    pub(crate) backward_pawn: [i32; 2],
+    // Indexed by rank counted from the pawn's own side
+    pub(crate) connected_pawn_mg: [i32; 8],
+    pub(crate) connected_pawn_eg: [i32; 8],
+    pub(crate) passed_pawn_mg: [i32; 8],
+    pub(crate) passed_pawn_eg: [i32; 8],
+    // Per square of distance per rank advanced: their king, our king
+    pub(crate) passed_king_distance: [i32; 2],
+    pub(crate) unstoppable_passer: i32,
+
+    // Typical number of squares, mg and eg bonus per square above it
+    pub(crate) knight_mobility: [i32; 3],
+    pub(crate) bishop_mobility: [i32; 3],
+    pub(crate) rook_mobility: [i32; 3],
+    pub(crate) queen_mobility: [i32; 3],
+    pub(crate) knight_outpost: [i32; 2],
+    pub(crate) bishop_outpost: [i32; 2],
+    pub(crate) rook_on_seventh: [i32; 2],
+    pub(crate) trapped_bishop: [i32; 2],
+    pub(crate) trapped_rook: [i32; 2],
+    // Per minor piece still at home once the queen has moved, middlegame only
+    pub(crate) queen_early_development: i32,
+}
+
+const PIECE_NAMES: [&str; 6] = ["pawn", "knight", "bishop", "rook", "queen", "king"];
+
+const PAWN_MG: [i32; 64] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 50, 50, 50, 50, 50, 50, 50, 50, 10, 10, 20, 30, 30, 20, 10, 10, 5, 5,
+    10, 25, 25, 10, 5, 5, 0, 0, 0, 20, 20, 0, 0, 0, 5, -5, -10, 0, 0, -10, -5, 5, 5, 10, 10, -20,
+    -20, 10, 10, 5, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+const PAWN_EG: [i32; 64] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 80, 80, 80, 80, 80, 80, 80, 80, 50, 50, 50, 50, 50, 50, 50, 50, 30, 30,
+    30, 30, 30, 30, 30, 30, 20, 20, 20, 20, 20, 20, 20, 20, 10, 10, 10, 10, 10, 10, 10, 10, 5, 5,
+    5, 5, 5, 5, 5, 5, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+const KNIGHT_MG: [i32; 64] = [
+    -50, -40, -30, -30, -30, -30, -40, -50, -40, -20, 0, 0, 0, 0, -20, -40, -30, 0, 10, 15, 15, 10,
+    0, -30, -30, 5, 15, 20, 20, 15, 5, -30, -30, 0, 15, 20, 20, 15, 0, -30, -30, 5, 10, 15, 15, 10,
+    5, -30, -40, -20, 0, 5, 5, 0, -20, -40, -50, -40, -30, -30, -30, -30, -40, -50,
+];
+const KNIGHT_EG: [i32; 64] = [
+    -50, -40, -30, -30, -30, -30, -40, -50, -40, -20, 0, 0, 0, 0, -20, -40, -30, 0, 10, 15, 15, 10,
+    0, -30, -30, 5, 15, 20, 20, 15, 5, -30, -30, 0, 15, 20, 20, 15, 0, -30, -30, 5, 10, 15, 15, 10,
+    5, -30, -40, -20, 0, 5, 5, 0, -20, -40, -50, -40, -30, -30, -30, -30, -40, -50,
+];
+const BISHOP_MG: [i32; 64] = [
+    -20, -10, -10, -10, -10, -10, -10, -20, -10, 0, 0, 0, 0, 0, 0, -10, -10, 0, 5, 10, 10, 5, 0,
+    -10, -10, 5, 5, 10, 10, 5, 5, -10, -10, 0, 10, 10, 10, 10, 0, -10, -10, 10, 10, 10, 10, 10, 10,
+    -10, -10, 5, 0, 0, 0, 0, 5, -10, -20, -10, -10, -10, -10, -10, -10, -20,
+];
+const BISHOP_EG: [i32; 64] = [
+    -20, -10, -10, -10, -10, -10, -10, -20, -10, 0, 0, 0, 0, 0, 0, -10, -10, 0, 5, 10, 10, 5, 0,
+    -10, -10, 5, 10, 15, 15, 10, 5, -10, -10, 5, 10, 15, 15, 10, 5, -10, -10, 0, 5, 10, 10, 5, 0,
+    -10, -10, 0, 0, 0, 0, 0, 0, -10, -20, -10, -10, -10, -10, -10, -10, -20,
+];
+const ROOK_MG: [i32; 64] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 5, 10, 10, 10, 10, 10, 10, 5, -5, 0, 0, 0, 0, 0, 0, -5, -5, 0, 0, 0, 0,
+    0, 0, -5, -5, 0, 0, 0, 0, 0, 0, -5, -5, 0, 0, 0, 0, 0, 0, -5, -5, 0, 0, 0, 0, 0, 0, -5, 0, 0,
+    0, 5, 5, 0, 0, 0,
+];
+const ROOK_EG: [i32; 64] = [
+    5, 5, 5, 5, 5, 5, 5, 5, 10, 10, 10, 10, 10, 10, 10, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0,
+];
+const QUEEN_MG: [i32; 64] = [
+    -20, -10, -10, -5, -5, -10, -10, -20, -10, 0, 0, 0, 0, 0, 0, -10, -10, 0, 5, 5, 5, 5, 0, -10,
+    -5, 0, 5, 5, 5, 5, 0, -5, 0, 0, 5, 5, 5, 5, 0, -5, -10, 5, 5, 5, 5, 5, 0, -10, -10, 0, 5, 0, 0,
+    0, 0, -10, -20, -10, -10, -5, -5, -10, -10, -20,
+];
+const QUEEN_EG: [i32; 64] = [
+    -20, -10, -10, -5, -5, -10, -10, -20, -10, 0, 5, 5, 5, 5, 0, -10, -10, 5, 10, 10, 10, 10, 5,
+    -10, -5, 5, 10, 15, 15, 10, 5, -5, -5, 5, 10, 15, 15, 10, 5, -5, -10, 5, 10, 10, 10, 10, 5,
+    -10, -10, 0, 5, 5, 5, 5, 0, -10, -20, -10, -10, -5, -5, -10, -10, -20,
+];
+const KING_MG: [i32; 64] = [
+    -30, -40, -40, -50, -50, -40, -40, -30, -30, -40, -40, -50, -50, -40, -40, -30, -30, -40, -40,
+    -50, -50, -40, -40, -30, -30, -40, -40, -50, -50, -40, -40, -30, -20, -30, -30, -40, -40, -30,
+    -30, -20, -10, -20, -20, -20, -20, -20, -20, -10, 20, 20, 0, 0, 0, 0, 20, 20, 20, 30, 10, 0, 0,
+    10, 30, 20,
+];
+const KING_EG: [i32; 64] = [
+    -50, -40, -30, -20, -20, -30, -40, -50, -30, -20, -10, 0, 0, -10, -20, -30, -30, -10, 20, 30,
+    30, 20, -10, -30, -30, -10, 30, 40, 40, 30, -10, -30, -30, -10, 30, 40, 40, 30, -10, -30, -30,
+    -10, 20, 30, 30, 20, -10, -30, -30, -30, 0, 0, 0, 0, -30, -30, -50, -30, -30, -30, -30, -30,
+    -30, -50,
+];
+
+impl Default for EvalParams {
+    fn default() -> EvalParams {
+        EvalParams {
+            material_mg: [100, 320, 330, 500, 900, 0],
+            material_eg: [120, 300, 320, 530, 950, 0],
+            pst_mg: [PAWN_MG, KNIGHT_MG, BISHOP_MG, ROOK_MG, QUEEN_MG, KING_MG],
+            pst_eg: [PAWN_EG, KNIGHT_EG, BISHOP_EG, ROOK_EG, QUEEN_EG, KING_EG],
+
+            rook_open_file: 40,
+            rook_semi_open_file: 10,
+            bishop_pair: 10,
+
+            king_shield: [15, 8, -20],
+            king_storm: [-30, -15, -5],
+            king_open_file: [-25, -10],
+            king_attack_weights: [2, 3, 5, 1],
+            safety_table: [
+                0, 0, 1, 2, 3, 5, 7, 9, 12, 15, 18, 22, 26, 30, 35, 39, 44, 50, 56, 62, 68, 75, 82,
+                85, 89, 97, 105, 113, 122, 131, 140, 150, 169, 180, 191, 202, 213, 225, 237, 248,
+                260, 272, 283, 295, 307, 319, 330, 342, 354, 366, 377, 389, 401, 412, 424, 436,
+                448, 459, 471, 483, 494, 500, 500, 500,
+            ],
+
+            doubled_pawn: [-10, -20],
+            isolated_pawn: [-10, -15],
+            backward_pawn: [-8, -10],
+            connected_pawn_mg: [0, 3, 5, 8, 12, 20, 35, 0],
+            connected_pawn_eg: [0, 2, 4, 6, 10, 18, 30, 0],
+            passed_pawn_mg: [0, 5, 10, 15, 25, 40, 60, 0],
+            passed_pawn_eg: [0, 10, 20, 35, 60, 100, 150, 0],
+            passed_king_distance: [5, 2],
+            unstoppable_passer: 700,
+
+            knight_mobility: [4, 4, 4],
+            bishop_mobility: [7, 5, 5],
+            rook_mobility: [7, 2, 4],
+            queen_mobility: [14, 1, 2],
+            knight_outpost: [25, 15],
+            bishop_outpost: [12, 8],
+            rook_on_seventh: [20, 30],
+            trapped_bishop: [-100, -100],
+            trapped_rook: [-50, -10],
+            queen_early_development: -10,
+        }
+    }
+}
+
+impl EvalParams {
+    // Every parameter by the name used in parameter files
+    pub(crate) fn fields_mut(&mut self) -> Vec<(String, &mut [i32])> {
+        let mut fields: Vec<(String, &mut [i32])> = vec![
+            ("material_mg".to_owned(), &mut self.material_mg[..]),
+            ("material_eg".to_owned(), &mut self.material_eg[..]),
+        ];
+        for (name, table) in PIECE_NAMES.iter().zip(self.pst_mg.iter_mut()) {
+            fields.push((format!("pst_mg_{name}"), &mut table[..]));
+        }
+        for (name, table) in PIECE_NAMES.iter().zip(self.pst_eg.iter_mut()) {
+            fields.push((format!("pst_eg_{name}"), &mut table[..]));
+        }
+        fields.extend([
+            (
+                "rook_open_file".to_owned(),
+                std::slice::from_mut(&mut self.rook_open_file),
+            ),
+            (
+                "rook_semi_open_file".to_owned(),
+                std::slice::from_mut(&mut self.rook_semi_open_file),
+            ),
+            (
+                "bishop_pair".to_owned(),
+                std::slice::from_mut(&mut self.bishop_pair),
+            ),
+            ("king_shield".to_owned(), &mut self.king_shield[..]),
+            ("king_storm".to_owned(), &mut self.king_storm[..]),
+            ("king_open_file".to_owned(), &mut self.king_open_file[..]),
+            (
+                "king_attack_weights".to_owned(),
+                &mut self.king_attack_weights[..],
+            ),
+            ("safety_table".to_owned(), &mut self.safety_table[..]),
+            ("doubled_pawn".to_owned(), &mut self.doubled_pawn[..]),
+            ("isolated_pawn".to_owned(), &mut self.isolated_pawn[..]),
+            ("backward_pawn".to_owned(), &mut self.backward_pawn[..]),
+            (
+                "connected_pawn_mg".to_owned(),
+                &mut self.connected_pawn_mg[..],
+            ),
+            (
+                "connected_pawn_eg".to_owned(),
+                &mut self.connected_pawn_eg[..],
+            ),
+            ("passed_pawn_mg".to_owned(), &mut self.passed_pawn_mg[..]),
+            ("passed_pawn_eg".to_owned(), &mut self.passed_pawn_eg[..]),
+            (
+                "passed_king_distance".to_owned(),
+                &mut self.passed_king_distance[..],
+            ),
+            (
+                "unstoppable_passer".to_owned(),
+                std::slice::from_mut(&mut self.unstoppable_passer),
+            ),
+            ("knight_mobility".to_owned(), &mut self.knight_mobility[..]),
+            ("bishop_mobility".to_owned(), &mut self.bishop_mobility[..]),
+            ("rook_mobility".to_owned(), &mut self.rook_mobility[..]),
+            ("queen_mobility".to_owned(), &mut self.queen_mobility[..]),
+            ("knight_outpost".to_owned(), &mut self.knight_outpost[..]),
+            ("bishop_outpost".to_owned(), &mut self.bishop_outpost[..]),
+            ("rook_on_seventh".to_owned(), &mut self.rook_on_seventh[..]),
+            ("trapped_bishop".to_owned(), &mut self.trapped_bishop[..]),
+            ("trapped_rook".to_owned(), &mut self.trapped_rook[..]),
+            (
+                "queen_early_development".to_owned(),
+                std::slice::from_mut(&mut self.queen_early_development),
+            ),
+        ]);
+        fields
+    }
+
+    pub(crate) fn from_text(text: &str) -> Result<EvalParams, String> {
+        let mut params = EvalParams::default();
+        let mut values: Vec<(String, Vec<i32>)> = Vec::new();
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("");
+            for token in line.split_whitespace() {
+                match token.parse::<i32>() {
+                    Ok(value) => match values.last_mut() {
+                        Some((_, list)) => list.push(value),
+                        None => return Err(format!("Value {value} before any parameter name")),
+                    },
+                    Err(_) => values.push((token.to_owned(), Vec::new())),
+                }
+            }
+        }
+
+        let mut fields = params.fields_mut();
+        for (name, list) in values {
+            let field = match fields
+                .iter_mut()
+                .find(|(field_name, _)| *field_name == name)
+            {
+                Some((_, field)) => field,
+                None => return Err(format!("Unknown parameter {name}")),
+            };
+            if field.len() != list.len() {
+                return Err(format!(
+                    "Parameter {name} takes {} values, found {}",
+                    field.len(),
+                    list.len()
+                ));
+            }
+            field.copy_from_slice(&list);
+        }
+
+        Ok(params)
+    }
+
+    pub(crate) fn to_text(&self) -> String {
+        let mut text = String::from("# Evaluation parameters\n");
+        let mut copy = self.clone();
+        for (name, values) in copy.fields_mut() {
+            text.push_str(&name);
+            // Tables are written eight values to a line so they read like a board
+            let per_line = if values.len() > 8 { 8 } else { values.len() };
+            for chunk in values.chunks(per_line) {
+                if values.len() > 8 {
+                    text.push_str("\n   ");
+                }
+                for value in chunk {
+                    text.push_str(&format!(" {value}"));
+                }
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    pub(crate) fn load(path: &str) -> Result<EvalParams, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Could not read {path}: {e}"))?;
+        EvalParams::from_text(&text)
+    }
+
+    pub(crate) fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_text()).map_err(|e| format!("Could not write {path}: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_round_trip() {
+        let mut params = EvalParams::default();
+        params.material_mg[1] = 333;
+        params.trapped_rook = [-40, -20];
+        params.queen_early_development = -7;
+        let text = params.to_text();
+        assert_eq!(EvalParams::from_text(&text), Ok(params));
+    }
+
+    #[test]
+    fn missing_parameters_keep_their_defaults() {
+        let params = EvalParams::from_text("# only one\nqueen_early_development -3\n").unwrap();
+        let expected = EvalParams {
+            queen_early_development: -3,
+            ..EvalParams::default()
+        };
+        assert_eq!(params, expected);
+    }
+
+    #[test]
+    fn rejects_unknown_parameter() {
+        assert_eq!(
+            EvalParams::from_text("queen_lateness 5"),
+            Err(String::from("Unknown parameter queen_lateness"))
+        );
+    }
+
+    #[test]
+    fn rejects_value_without_a_name() {
+        assert_eq!(
+            EvalParams::from_text("12 queen_early_development -3"),
+            Err(String::from("Value 12 before any parameter name"))
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_number_of_values() {
+        assert_eq!(
+            EvalParams::from_text("trapped_rook -50"),
+            Err(String::from(
+                "Parameter trapped_rook takes 2 values, found 1"
+            ))
+        );
+    }
+}
@@ -1,111 +1,548 @@
+use crate::engine::eval_params::EvalParams;
 use crate::model::{
     board::Board, color::Color, piece::Piece, piece_type::PieceType, position::Position,
 };
 
-const PAWN_POS: [[i16; 8]; 8] = [
-    [106, 106, 107, 108, 108, 107, 106, 106],
-    [105, 105, 106, 107, 107, 106, 105, 105],
-    [104, 104, 105, 106, 106, 105, 104, 104],
-    [103, 103, 104, 105, 105, 104, 103, 103],
-    [102, 102, 103, 104, 104, 103, 102, 102],
-    [101, 101, 102, 103, 103, 102, 101, 101],
-    [100, 100, 100, 100, 100, 100, 100, 100],
-    [100, 100, 100, 100, 100, 100, 100, 100],
-];
-const KNIGHT_POS: [[i16; 8]; 8] = [
-    [300, 300, 300, 300, 300, 300, 300, 300],
-    [300, 300, 300, 300, 300, 300, 300, 300],
-    [300, 300, 300, 300, 300, 300, 300, 300],
-    [300, 300, 330, 330, 330, 330, 300, 300],
-    [300, 300, 320, 320, 320, 320, 300, 300],
-    [300, 300, 310, 310, 310, 310, 300, 300],
-    [300, 300, 300, 300, 300, 300, 300, 300],
-    [300, 300, 300, 300, 300, 300, 300, 300],
-];
-const BISHOP_POS: [[i16; 8]; 8] = [
-    [300, 300, 300, 300, 300, 300, 300, 300],
-    [300, 300, 300, 300, 300, 300, 300, 300],
-    [300, 300, 300, 300, 300, 300, 300, 300],
-    [300, 313, 300, 300, 300, 300, 313, 300],
-    [300, 310, 312, 300, 300, 312, 310, 300],
-    [310, 300, 310, 311, 311, 310, 300, 310],
-    [300, 310, 300, 300, 300, 300, 310, 300],
-    [300, 300, 300, 300, 300, 300, 300, 300],
-];
-
-pub(crate) fn evaluate(board: Board, color: Color) -> i16 {
-    // Tapered evaluation: Chess boards start with 39 * 2 = 78 points
-    // after 4 pawns + 4 pieces captured middlegame, so 78 - 16 = 62
-    let mut score: i16 = 0;
-    let my_points = points(board, color);
-    let op_points = points(board, color.opponent_color());
-    let (my_double_p, my_rook_open, my_rook_semi, my_bishops) = doubled_pawns(board, color);
-    let (op_double_p, op_rook_open, op_rook_semi, op_bishops) =
-        doubled_pawns(board, color.opponent_color());
-
-    score += my_points - op_points;
-    score += ((op_double_p - my_double_p) as i16) * 3;
-    score += ((my_rook_open - op_rook_open) as i16) * 40;
-    score += ((my_rook_semi - op_rook_semi) as i16) * 10;
-
-    score += if my_bishops { 10 } else { 0 };
-    score -= if op_bishops { 10 } else { 0 };
-
-    return score;
-}
-
-pub(crate) fn print_evaluate(board: Board, color: Color) -> i16 {
-    // Tapered evaluation: Chess boards start with 39 * 2 = 78 points
-    // after 4 pawns + 4 pieces captured middlegame, so 78 - 16 = 62
-    // (n - 62) / 16
-    let mut early_score = 0;
-    let middle_score = 0;
-    let mut late_score = 0;
-
-    let my_points = points(board, color);
-    let op_points = points(board, color.opponent_color());
-
-    let my_ep = early_points(board, color);
-    let op_ep = early_points(board, color.opponent_color());
-
-    let (my_double_p, my_rook_open, my_rook_semi, my_bishops) = doubled_pawns(board, color);
-    let (op_double_p, op_rook_open, op_rook_semi, op_bishops) =
-        doubled_pawns(board, color.opponent_color());
-    println!("{my_points}, {my_double_p}, {my_rook_open}, {my_rook_semi}, {my_bishops}");
-    println!("{op_points}, {op_double_p}, {op_rook_open}, {op_rook_semi}, {op_bishops}");
-
-    let total_points = my_points + op_points;
-
-    early_score += my_ep - op_ep;
-    early_score += (op_double_p - my_double_p) * 3;
-    early_score += (my_rook_open - op_rook_open) * 40;
-    early_score += (my_rook_semi - op_rook_semi) * 10;
-    early_score += if my_bishops { 10 } else { 0 };
-    early_score -= if op_bishops { 10 } else { 0 };
-
-    late_score += my_ep - op_ep;
-    late_score += (op_double_p - my_double_p) * 3;
-    late_score += (my_rook_open - op_rook_open) * 40;
-    late_score += (my_rook_semi - op_rook_semi) * 10;
-    late_score += if my_bishops { 10 } else { 0 };
-    late_score -= if op_bishops { 10 } else { 0 };
-
-    let score = (((early_score * total_points) as f32 / 78.0).round()
-        + (early_score as f32 * (1.0 - (total_points as f32 / 78.0))).round())
-        as i16;
-
-    return score;
-}
-
-// Returns: (number of doubled pawns, number of rooks on open files, number of rooks on semi open files,
-//           bishop pair)
-fn doubled_pawns(board: Board, color: Color) -> (i16, i16, i16, bool) {
+const PAWN_TABLE_SIZE: usize = 1 << 14;
+
+// Game phase: each minor piece counts 1, each rook 2 and each queen 4, so the starting
+// position is at phase 24 (pure middlegame) and a bare pawn ending is at phase 0.
+const TOTAL_PHASE: i32 = 24;
+
+// A pair of middlegame and endgame scores that get blended by game phase at the end.
+#[derive(Copy, Clone, Default)]
+struct Score {
+    mg: i32,
+    eg: i32,
+}
+
+impl Score {
+    fn add(&mut self, mg: i32, eg: i32) {
+        self.mg += mg;
+        self.eg += eg;
+    }
+
+    fn taper(&self, phase: i32) -> i32 {
+        (self.mg * phase + self.eg * (TOTAL_PHASE - phase)) / TOTAL_PHASE
+    }
+}
+
+pub(crate) fn evaluate(
+    board: Board,
+    color: Color,
+    params: &EvalParams,
+    pawn_table: &mut PawnTable,
+) -> i16 {
+    let phase = game_phase(board);
+    let mut score = psqt(board, color, params);
+    let op = psqt(board, color.opponent_color(), params);
+    score.add(-op.mg, -op.eg);
+    let extras = structure_terms(board, color, params);
+    score.add(extras, extras);
+    let my_king = king_safety(board, color, params);
+    let op_king = king_safety(board, color.opponent_color(), params);
+    score.add(my_king.mg - op_king.mg, my_king.eg - op_king.eg);
+    let pawns = pawn_terms(board, color, params, pawn_table);
+    score.add(pawns.mg, pawns.eg);
+    let my_activity = piece_activity(board, color, params);
+    let op_activity = piece_activity(board, color.opponent_color(), params);
+    score.add(
+        my_activity.mg - op_activity.mg,
+        my_activity.eg - op_activity.eg,
+    );
+
+    score.taper(phase).clamp(-32000, 32000) as i16
+}
+
+// Rooks on (semi) open files and the bishop pair, from `color`'s point of view.
+// These do not depend on the phase so they are added to both halves of the score.
+fn structure_terms(board: Board, color: Color, params: &EvalParams) -> i32 {
+    let (my_rook_open, my_rook_semi, my_bishops) = rook_files_and_bishops(board, color);
+    let (op_rook_open, op_rook_semi, op_bishops) =
+        rook_files_and_bishops(board, color.opponent_color());
+    let mut score: i32 = 0;
+
+    score += ((my_rook_open - op_rook_open) as i32) * params.rook_open_file;
+    score += ((my_rook_semi - op_rook_semi) as i32) * params.rook_semi_open_file;
+
+    score += if my_bishops { params.bishop_pair } else { 0 };
+    score -= if op_bishops { params.bishop_pair } else { 0 };
+
+    score
+}
+
+// Pawn shield, pawn storm, open files next to the king and pieces attacking the squares
+// around it, from the point of view of `color`'s king. Only counts in the middlegame: once
+// the queens and rooks are gone the king should come out and fight.
+fn king_safety(board: Board, color: Color, params: &EvalParams) -> Score {
+    let king: Position = if color == Color::White {
+        board.white_king
+    } else {
+        board.black_king
+    };
+    let op: Color = color.opponent_color();
+    let forward: isize = if color == Color::White { 1 } else { -1 };
+    let mut score: i32 = 0;
+
+    for x in (king.x - 1)..=(king.x + 1) {
+        if !(0..8).contains(&x) {
+            continue;
+        }
+        // Closest pawn of each colour in front of the king on this file
+        let mut shield: Option<isize> = None;
+        let mut storm: Option<isize> = None;
+        let mut y = king.y + forward;
+        while (0..8).contains(&y) {
+            let piece: Piece = board.get_piece(Position { x, y });
+            if piece.piece_type == PieceType::Pawn {
+                let distance = (y - king.y).abs();
+                if piece.color == color && shield.is_none() {
+                    shield = Some(distance);
+                } else if piece.color == op && storm.is_none() {
+                    storm = Some(distance);
+                }
+            }
+            y += forward;
+        }
+
+        score += match shield {
+            Some(1) => params.king_shield[0],
+            Some(2) => params.king_shield[1],
+            Some(_) => 0,
+            None => params.king_shield[2],
+        };
+        score += match storm {
+            Some(1) => 0, // Blocked against the king, it cannot advance any further
+            Some(2) => params.king_storm[0],
+            Some(3) => params.king_storm[1],
+            Some(4) => params.king_storm[2],
+            _ => 0,
+        };
+
+        let file_open_for_us = shield.is_none();
+        let file_open_for_them = !(0..8).any(|row| {
+            let piece = board.get_piece(Position { x, y: row });
+            piece.piece_type == PieceType::Pawn && piece.color == op
+        });
+        if file_open_for_us && file_open_for_them {
+            score += params.king_open_file[0];
+        } else if file_open_for_us {
+            score += params.king_open_file[1];
+        }
+    }
+
+    // Attack units: every enemy piece hitting the king zone adds its weight per square
+    let mut attackers: i32 = 0;
+    let mut units: i32 = 0;
+    for row in 0..8 {
+        for col in 0..8 {
+            let pos = Position { x: col, y: row };
+            let piece: Piece = board.get_piece(pos);
+            if piece.color != op || piece.piece_type == PieceType::King {
+                continue;
+            }
+            let hits = board
+                .attacked_squares(pos)
+                .iter()
+                .filter(|sq| (sq.x - king.x).abs() <= 1 && (sq.y - king.y).abs() <= 1)
+                .count() as i32;
+            if hits > 0 {
+                attackers += 1;
+                units += hits
+                    * match piece.piece_type {
+                        PieceType::Knight | PieceType::Bishop => params.king_attack_weights[0],
+                        PieceType::Rook => params.king_attack_weights[1],
+                        PieceType::Queen => params.king_attack_weights[2],
+                        _ => params.king_attack_weights[3],
+                    };
+            }
+        }
+    }
+    // A lone attacker is rarely dangerous
+    if attackers >= 2 {
+        score -= params.safety_table[(units.max(0) as usize).min(params.safety_table.len() - 1)];
+    }
+
+    Score { mg: score, eg: 0 }
+}
+
+#[derive(Copy, Clone, Default)]
+struct PawnEntry {
+    key: u64,
+    // From White's point of view
+    score: Score,
+    // One bit per square (y * 8 + x) holding a passed pawn, indexed White then Black
+    passed: [u64; 2],
+}
+
+// Cache of pawn structure evaluations, keyed by the pawn-only Zobrist key of the board.
+// Pawns move rarely compared to pieces, so the hit rate during a search is very high.
+pub(crate) struct PawnTable {
+    entries: Vec<PawnEntry>,
+}
+
+impl PawnTable {
+    pub(crate) fn new() -> PawnTable {
+        PawnTable {
+            entries: vec![PawnEntry::default(); PAWN_TABLE_SIZE],
+        }
+    }
+
+    fn probe(&mut self, board: Board, params: &EvalParams) -> PawnEntry {
+        let key = board.pawn_hash();
+        let index = (key as usize) & (PAWN_TABLE_SIZE - 1);
+        if self.entries[index].key != key {
+            self.entries[index] = pawn_structure(board, key, params);
+        }
+        self.entries[index]
+    }
+}
+
+// Pawn structure plus the passed pawn endgame terms, from `color`'s point of view
+fn pawn_terms(
+    board: Board,
+    color: Color,
+    params: &EvalParams,
+    pawn_table: &mut PawnTable,
+) -> Score {
+    let entry = pawn_table.probe(board, params);
+    let mut score = entry.score;
+    for (side, passed) in [
+        (Color::White, entry.passed[0]),
+        (Color::Black, entry.passed[1]),
+    ] {
+        let extra = passed_pawn_endgame(board, side, passed, params);
+        if side == Color::White {
+            score.add(0, extra);
+        } else {
+            score.add(0, -extra);
+        }
+    }
+    if color == Color::Black {
+        score = Score {
+            mg: -score.mg,
+            eg: -score.eg,
+        };
+    }
+    score
+}
+
+// Rank of `y` counted from `color`'s side of the board, 0 for the back rank
+fn relative_rank(y: isize, color: Color) -> usize {
+    (if color == Color::White { y } else { 7 - y } as usize)
+}
+
+fn is_pawn(board: Board, x: isize, y: isize, color: Color) -> bool {
+    if !(0..8).contains(&x) || !(0..8).contains(&y) {
+        return false;
+    }
+    let piece = board.get_piece(Position { x, y });
+    piece.piece_type == PieceType::Pawn && piece.color == color
+}
+
+// Evaluates doubled, isolated, backward, connected and passed pawns. Only looks at pawns so
+// the result can be cached under the pawn key.
+fn pawn_structure(board: Board, key: u64, params: &EvalParams) -> PawnEntry {
+    let mut entry = PawnEntry {
+        key,
+        ..Default::default()
+    };
+
+    for (index, color) in [Color::White, Color::Black].into_iter().enumerate() {
+        let op = color.opponent_color();
+        let forward: isize = if color == Color::White { 1 } else { -1 };
+        let sign: i32 = if color == Color::White { 1 } else { -1 };
+        let mut side = Score::default();
+
+        for x in 0..8 {
+            let on_file = (0..8).filter(|&y| is_pawn(board, x, y, color)).count() as i32;
+            if on_file > 1 {
+                // Only the extra pawns are penalised
+                side.add(
+                    params.doubled_pawn[0] * (on_file - 1),
+                    params.doubled_pawn[1] * (on_file - 1),
+                );
+            }
+        }
+
+        for y in 0..8 {
+            for x in 0..8 {
+                if !is_pawn(board, x, y, color) {
+                    continue;
+                }
+                let rank = relative_rank(y, color);
+                let neighbours: Vec<isize> = (0..8)
+                    .filter(|&row| {
+                        is_pawn(board, x - 1, row, color) || is_pawn(board, x + 1, row, color)
+                    })
+                    .collect();
+
+                if neighbours.is_empty() {
+                    side.add(params.isolated_pawn[0], params.isolated_pawn[1]);
+                } else {
+                    let supported_or_level = neighbours
+                        .iter()
+                        .any(|&row| relative_rank(row, color) <= rank);
+                    let stop_attacked = is_pawn(board, x - 1, y + 2 * forward, op)
+                        || is_pawn(board, x + 1, y + 2 * forward, op);
+                    if !supported_or_level && stop_attacked {
+                        side.add(params.backward_pawn[0], params.backward_pawn[1]);
+                    }
+                }
+
+                let phalanx = is_pawn(board, x - 1, y, color) || is_pawn(board, x + 1, y, color);
+                let supported = is_pawn(board, x - 1, y - forward, color)
+                    || is_pawn(board, x + 1, y - forward, color);
+                if phalanx || supported {
+                    side.add(
+                        params.connected_pawn_mg[rank],
+                        params.connected_pawn_eg[rank],
+                    );
+                }
+
+                let mut passed = true;
+                let mut ahead = y + forward;
+                while (0..8).contains(&ahead) {
+                    if is_pawn(board, x - 1, ahead, op)
+                        || is_pawn(board, x, ahead, op)
+                        || is_pawn(board, x + 1, ahead, op)
+                        || is_pawn(board, x, ahead, color)
+                    {
+                        passed = false;
+                        break;
+                    }
+                    ahead += forward;
+                }
+                if passed {
+                    side.add(params.passed_pawn_mg[rank], params.passed_pawn_eg[rank]);
+                    entry.passed[index] |= 1 << (y * 8 + x);
+                }
+            }
+        }
+
+        entry.score.add(side.mg * sign, side.eg * sign);
+    }
+
+    entry
+}
+
+fn distance(a: Position, b: Position) -> i32 {
+    (a.x - b.x).abs().max((a.y - b.y).abs()) as i32
+}
+
+// Endgame bonuses for `color`'s passed pawns that depend on the kings and pieces, so they
+// cannot be cached with the pawn structure.
+fn passed_pawn_endgame(board: Board, color: Color, passed: u64, params: &EvalParams) -> i32 {
+    let op = color.opponent_color();
+    let (own_king, their_king) = if color == Color::White {
+        (board.white_king, board.black_king)
+    } else {
+        (board.black_king, board.white_king)
+    };
+    let forward: isize = if color == Color::White { 1 } else { -1 };
+    let promotion_row: isize = if color == Color::White { 7 } else { 0 };
+    let pawns_only = board.board.iter().flatten().all(|piece| {
+        piece.color != op
+            || piece.piece_type == PieceType::Pawn
+            || piece.piece_type == PieceType::King
+    });
+    let mut score: i32 = 0;
+
+    for square in 0..64 {
+        if passed & (1 << square) == 0 {
+            continue;
+        }
+        let pawn = Position {
+            x: square % 8,
+            y: square / 8,
+        };
+        let rank = relative_rank(pawn.y, color) as i32;
+        let stop = Position {
+            x: pawn.x,
+            y: pawn.y + forward,
+        };
+
+        // Kings matter more the further the pawn has advanced
+        let weight = (rank - 2).max(0);
+        score += (distance(their_king, stop) * params.passed_king_distance[0]
+            - distance(own_king, stop) * params.passed_king_distance[1])
+            * weight;
+
+        // Rule of the square: against a bare king, a pawn the king cannot catch will queen
+        if pawns_only {
+            let promotion = Position {
+                x: pawn.x,
+                y: promotion_row,
+            };
+            let pawn_distance = (7 - rank).min(5);
+            let path_clear = ((pawn.y + forward)..=promotion_row)
+                .chain(promotion_row..=(pawn.y + forward))
+                .all(|y| board.get_piece(Position { x: pawn.x, y }).piece_type == PieceType::Empty);
+            // Assume the defending king moves first
+            if path_clear && distance(their_king, promotion) - 1 > pawn_distance {
+                score += params.unstoppable_passer;
+            }
+        }
+    }
+
+    score
+}
+
+// Mobility, outposts, rooks on the seventh, trapped pieces and early queen sorties for
+// `color`'s pieces.
+fn piece_activity(board: Board, color: Color, params: &EvalParams) -> Score {
+    let op = color.opponent_color();
+    let forward: isize = if color == Color::White { 1 } else { -1 };
+    let home_row: isize = if color == Color::White { 0 } else { 7 };
+    let mut score = Score::default();
+
+    // Squares covered by enemy pawns are not counted as mobility
+    let mut pawn_attacks = [[false; 8]; 8];
+    for y in 0..8 {
+        for x in 0..8 {
+            if is_pawn(board, x, y, op) {
+                for sq in board.attacked_squares(Position { x, y }) {
+                    pawn_attacks[sq.y as usize][sq.x as usize] = true;
+                }
+            }
+        }
+    }
+
+    let their_king = if color == Color::White {
+        board.black_king
+    } else {
+        board.white_king
+    };
+    let own_king = if color == Color::White {
+        board.white_king
+    } else {
+        board.black_king
+    };
+
+    for y in 0..8 {
+        for x in 0..8 {
+            let pos = Position { x, y };
+            let piece: Piece = board.get_piece(pos);
+            if piece.color != color {
+                continue;
+            }
+            let rank = relative_rank(y, color);
+
+            let [typical, mg, eg] = match piece.piece_type {
+                PieceType::Knight => params.knight_mobility,
+                PieceType::Bishop => params.bishop_mobility,
+                PieceType::Rook => params.rook_mobility,
+                PieceType::Queen => params.queen_mobility,
+                _ => continue,
+            };
+            let mobility = board
+                .attacked_squares(pos)
+                .iter()
+                .filter(|sq| {
+                    board.get_piece(**sq).color != color
+                        && !pawn_attacks[sq.y as usize][sq.x as usize]
+                })
+                .count() as i32;
+            score.add((mobility - typical) * mg, (mobility - typical) * eg);
+
+            // Outposts: supported by a pawn in enemy territory where no enemy pawn can chase it off
+            if (piece.piece_type == PieceType::Knight || piece.piece_type == PieceType::Bishop)
+                && (3..=5).contains(&rank)
+                && (is_pawn(board, x - 1, y - forward, color)
+                    || is_pawn(board, x + 1, y - forward, color))
+            {
+                let mut attackable = false;
+                let mut ahead = y + forward;
+                while (0..8).contains(&ahead) {
+                    if is_pawn(board, x - 1, ahead, op) || is_pawn(board, x + 1, ahead, op) {
+                        attackable = true;
+                    }
+                    ahead += forward;
+                }
+                if !attackable {
+                    let bonus = if piece.piece_type == PieceType::Knight {
+                        params.knight_outpost
+                    } else {
+                        params.bishop_outpost
+                    };
+                    score.add(bonus[0], bonus[1]);
+                }
+            }
+
+            if piece.piece_type == PieceType::Rook && rank == 6 {
+                let their_pawns_home = (0..8).any(|col| is_pawn(board, col, y, op));
+                if their_pawns_home || relative_rank(their_king.y, color) == 7 {
+                    score.add(params.rook_on_seventh[0], params.rook_on_seventh[1]);
+                }
+            }
+
+            // A bishop grabbing the a7/h7 pawn gets shut in by b6/g6
+            if piece.piece_type == PieceType::Bishop && rank == 6 && (x == 0 || x == 7) {
+                let inner = if x == 0 { 1 } else { 6 };
+                if is_pawn(board, inner, y - forward, op) {
+                    score.add(params.trapped_bishop[0], params.trapped_bishop[1]);
+                }
+            }
+
+            // A rook boxed in the corner by its own king that can no longer castle
+            if piece.piece_type == PieceType::Rook && rank <= 1 && own_king.y == home_row {
+                let (kingside, queenside) = if color == Color::White {
+                    (board.white_kingside, board.white_queenside)
+                } else {
+                    (board.black_kingside, board.black_queenside)
+                };
+                let trapped_kingside = !kingside && own_king.x >= 5 && x > own_king.x;
+                let trapped_queenside = !queenside && own_king.x <= 3 && x < own_king.x;
+                if (trapped_kingside || trapped_queenside) && mobility <= 3 {
+                    score.add(params.trapped_rook[0], params.trapped_rook[1]);
+                }
+            }
+
+            if piece.piece_type == PieceType::Queen && !(x == 3 && y == home_row) {
+                let undeveloped = [1, 2, 5, 6]
+                    .iter()
+                    .filter(|&&col| {
+                        let home = board.get_piece(Position {
+                            x: col,
+                            y: home_row,
+                        });
+                        home.color == color
+                            && (home.piece_type == PieceType::Knight
+                                || home.piece_type == PieceType::Bishop)
+                    })
+                    .count() as i32;
+                score.add(undeveloped * params.queen_early_development, 0);
+            }
+        }
+    }
+
+    score
+}
+
+// Returns a value between 0 (bare kings and pawns) and TOTAL_PHASE (all pieces on the board).
+pub(crate) fn game_phase(board: Board) -> i32 {
+    let mut phase: i32 = 0;
+    for row in board.board {
+        for piece in row {
+            phase += match piece.piece_type {
+                PieceType::Knight | PieceType::Bishop => 1,
+                PieceType::Rook => 2,
+                PieceType::Queen => 4,
+                _ => 0,
+            };
+        }
+    }
+    // Promotions can push the count past the starting material
+    phase.min(TOTAL_PHASE)
+}
+
+// Returns: (number of rooks on open files, number of rooks on semi open files, bishop pair)
+fn rook_files_and_bishops(board: Board, color: Color) -> (i16, i16, bool) {
     let mut pawns: i16;
     let mut opawns: i16;
     let mut rooks: i16;
     let mut piece: Piece;
     let mut bishops: i16 = 0;
-    let mut total_doubled_pawns: i16 = 0;
     let mut total_rooks_open: i16 = 0;
     let mut total_rooks_semi: i16 = 0;
 
@@ -127,23 +564,18 @@ fn doubled_pawns(board: Board, color: Color) -> (i16, i16, i16, bool) {
                 bishops += 1;
             }
         }
-        total_doubled_pawns += if pawns > 1 { pawns } else { 0 };
         if pawns + opawns == 1 {
             total_rooks_semi += rooks;
         } else if pawns + opawns == 0 {
             total_rooks_open += rooks;
         }
     }
-    return (
-        total_doubled_pawns,
-        total_rooks_open,
-        total_rooks_semi,
-        bishops > 1,
-    );
+    (total_rooks_open, total_rooks_semi, bishops > 1)
 }
 
-fn points(board: Board, color: Color) -> i16 {
-    let mut points: i16 = 0;
+// Material plus piece-square bonuses for every piece of `color`
+fn psqt(board: Board, color: Color, params: &EvalParams) -> Score {
+    let mut score = Score::default();
     let mut piece: Piece;
     let mut pos: Position;
 
@@ -152,94 +584,239 @@ fn points(board: Board, color: Color) -> i16 {
             pos = Position { x: i, y: l };
             piece = board.get_piece(pos);
             if piece.color == color {
-                points += piece_points(piece.piece_type, pos, color);
+                if let Some(index) = piece_index(piece.piece_type) {
+                    let square = pst_square(pos, color);
+                    score.add(
+                        params.material_mg[index] + params.pst_mg[index][square],
+                        params.material_eg[index] + params.pst_eg[index][square],
+                    );
+                }
             }
         }
     }
 
-    return points;
+    score
 }
 
-fn early_points(board: Board, color: Color) -> i16 {
-    let mut points: i16 = 0;
-    let mut piece: Piece;
-    let mut pos: Position;
-
-    for i in 0..8 {
-        for l in 0..8 {
-            pos = Position { x: i, y: l };
-            piece = board.get_piece(pos);
-            if piece.color == color {
-                points += early_piece_points(piece.piece_type, pos, color);
-            }
-        }
+// Index into the piece-indexed arrays of `EvalParams`
+fn piece_index(piece: PieceType) -> Option<usize> {
+    match piece {
+        PieceType::Pawn => Some(0),
+        PieceType::Knight => Some(1),
+        PieceType::Bishop => Some(2),
+        PieceType::Rook => Some(3),
+        PieceType::Queen => Some(4),
+        PieceType::King => Some(5),
+        PieceType::Empty => None,
     }
+}
 
-    return points;
+// Piece-square tables are written from White's side with the eighth rank first, so Black
+// pieces are looked up with the rows flipped.
+fn pst_square(pos: Position, color: Color) -> usize {
+    let row = if color == Color::Black {
+        pos.y
+    } else {
+        7 - pos.y
+    };
+    (row * 8 + pos.x) as usize
 }
 
-fn late_points(board: Board, color: Color) -> i16 {
-    let mut points: i16 = 0;
-    let mut piece: Piece;
-    let mut pos: Position;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    for i in 0..8 {
-        for l in 0..8 {
-            pos = Position { x: i, y: l };
-            piece = board.get_piece(pos);
-            if piece.color == color {
-                points += late_piece_points(piece.piece_type, pos, color);
-            }
+    // Middlegames and endings with castling rights, passed pawns and pieces off their
+    // starting squares, none of them symmetric
+    const POSITIONS: [&str; 5] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r1bqk2r/pppp1ppp/2n2n2/2b1p3/2B1P3/3P1N2/PPP2PPP/RNBQK2R w KQkq - 4 5",
+        "2r3k1/pp3ppp/4p3/3pP3/1P1P4/P4N2/5PPP/2R3K1 b - - 0 25",
+        "8/5k2/8/2P5/8/1K6/6p1/8 w - - 0 50",
+        "r4rk1/1bq1bppp/p2p1n2/1p2p3/3NP3/1BN1BQ2/PPP2PPP/R4RK1 w - - 0 12",
+    ];
+
+    fn eval(fen: &str, color: Color) -> i16 {
+        let board = Board::from_fen(fen).unwrap();
+        evaluate(board, color, &EvalParams::default(), &mut PawnTable::new())
+    }
+
+    // The same position with the board turned around and the colours swapped
+    fn mirror(fen: &str) -> String {
+        let fields: Vec<&str> = fen.split(' ').collect();
+        let swap = |text: &str| -> String {
+            text.chars()
+                .map(|c| {
+                    if c.is_ascii_uppercase() {
+                        c.to_ascii_lowercase()
+                    } else {
+                        c.to_ascii_uppercase()
+                    }
+                })
+                .collect()
+        };
+        let ranks: Vec<String> = fields[0].split('/').rev().map(swap).collect();
+        let turn = if fields[1] == "w" { "b" } else { "w" };
+        let castling = if fields[2] == "-" {
+            String::from("-")
+        } else {
+            let swapped = swap(fields[2]);
+            let mut rights: Vec<char> = swapped.chars().collect();
+            rights.sort_by_key(|c| "KQkq".find(*c));
+            rights.into_iter().collect()
+        };
+        format!("{} {turn} {castling} - 0 1", ranks.join("/"))
+    }
+
+    fn pawns(fen: &str) -> PawnEntry {
+        let board = Board::from_fen(fen).unwrap();
+        pawn_structure(board, board.pawn_hash(), &EvalParams::default())
+    }
+
+    #[test]
+    fn scores_are_symmetric() {
+        for fen in POSITIONS {
+            let mirrored = mirror(fen);
+            assert_eq!(eval(fen, Color::White), -eval(fen, Color::Black), "{fen}");
+            assert_eq!(
+                eval(fen, Color::White),
+                eval(&mirrored, Color::Black),
+                "{fen}"
+            );
+            assert_eq!(
+                eval(fen, Color::Black),
+                eval(&mirrored, Color::White),
+                "{fen}"
+            );
         }
+        assert_eq!(eval(POSITIONS[0], Color::White), 0);
     }
 
-    return points;
-}
+    #[test]
+    fn phase_blends_middlegame_and_endgame() {
+        let score = Score { mg: 240, eg: -120 };
+        assert_eq!(score.taper(TOTAL_PHASE), 240);
+        assert_eq!(score.taper(0), -120);
+        assert_eq!(score.taper(TOTAL_PHASE / 2), 60);
+        assert_eq!(score.taper(TOTAL_PHASE / 4), -30);
 
-fn piece_points(piece: PieceType, pos: Position, color: Color) -> i16 {
-    match piece {
-        PieceType::Bishop => return 300,
-        PieceType::Knight => return 300,
-        PieceType::Rook => return 500,
-        PieceType::King => return 0,
-        PieceType::Queen => return 900,
-        PieceType::Pawn => return 100,
-        PieceType::Empty => return 0,
-    };
-}
+        let phase = |fen: &str| game_phase(Board::from_fen(fen).unwrap());
+        assert_eq!(phase(POSITIONS[0]), TOTAL_PHASE);
+        assert_eq!(phase("4k3/pppp4/8/8/8/8/4PPPP/4K3 w - - 0 1"), 0);
+        assert_eq!(phase("4k3/8/8/8/8/8/8/R2QK3 w - - 0 1"), 6);
+        assert_eq!(phase("4k3/8/8/8/8/8/8/2BNK3 w - - 0 1"), 2);
+        // Extra queens from promotions do not go past a full middlegame
+        assert_eq!(
+            phase("QQQQkQQQ/8/8/8/8/8/8/RNBQKBNR w - - 0 1"),
+            TOTAL_PHASE
+        );
+    }
 
-fn early_piece_points(piece: PieceType, pos: Position, color: Color) -> i16 {
-    match piece {
-        PieceType::Bishop => return get_pos_points(pos, color, BISHOP_POS),
-        PieceType::Knight => return get_pos_points(pos, color, KNIGHT_POS),
-        PieceType::Rook => return 500,
-        PieceType::King => return 0,
-        PieceType::Queen => return 900,
-        PieceType::Pawn => return get_pos_points(pos, color, PAWN_POS),
-        PieceType::Empty => return 0,
-    };
-}
+    #[test]
+    fn passed_doubled_and_isolated_pawns() {
+        let params = EvalParams::default();
 
-fn late_piece_points(piece: PieceType, pos: Position, color: Color) -> i16 {
-    match piece {
-        PieceType::Bishop => return get_pos_points(pos, color, BISHOP_POS),
-        PieceType::Knight => return get_pos_points(pos, color, KNIGHT_POS),
-        PieceType::Rook => return 500,
-        PieceType::King => return 0,
-        PieceType::Queen => return 900,
-        PieceType::Pawn => return get_pos_points(pos, color, PAWN_POS),
-        PieceType::Empty => return 0,
-    };
-}
+        // A lone pawn on a2 is isolated and passed
+        let entry = pawns("4k3/8/8/8/8/8/P7/4K3 w - - 0 1");
+        assert_eq!(entry.passed, [1 << 8, 0]);
+        assert_eq!(
+            entry.score.mg,
+            params.isolated_pawn[0] + params.passed_pawn_mg[1]
+        );
+        assert_eq!(
+            entry.score.eg,
+            params.isolated_pawn[1] + params.passed_pawn_eg[1]
+        );
 
-fn get_pos_points(pos: Position, color: Color, grid: [[i16; 8]; 8]) -> i16 {
-    let mp: Position = if color == Color::Black {
-        pos
-    } else {
-        Position {
-            x: 7 - pos.x,
-            y: 7 - pos.y,
+        // Doubled on a2 and a3: both isolated, and only the front one is passed
+        let entry = pawns("4k3/8/8/8/8/P7/P7/4K3 w - - 0 1");
+        assert_eq!(entry.passed, [1 << 16, 0]);
+        assert_eq!(
+            entry.score.mg,
+            params.doubled_pawn[0] + 2 * params.isolated_pawn[0] + params.passed_pawn_mg[2]
+        );
+
+        // Pawns on neighbouring files in front of each other stop both being passed
+        let entry = pawns("4k3/8/8/8/1p6/8/P7/4K3 w - - 0 1");
+        assert_eq!(entry.passed, [0, 0]);
+        assert_eq!(entry.score.mg, 0);
+        // A black pawn on h4 is passed and counts against White
+        let entry = pawns("4k3/8/8/8/7p/8/P7/4K3 w - - 0 1");
+        assert_eq!(entry.passed, [1 << 8, 1 << 31]);
+        assert_eq!(
+            entry.score.mg,
+            params.passed_pawn_mg[1] - params.passed_pawn_mg[4]
+        );
+
+        // Connected pawns on d4 and e4 are neither isolated nor blocked
+        let entry = pawns("4k3/8/8/8/3PP3/8/8/4K3 w - - 0 1");
+        assert_eq!(entry.passed, [(1 << 27) | (1 << 28), 0]);
+        assert_eq!(
+            entry.score.mg,
+            2 * (params.connected_pawn_mg[3] + params.passed_pawn_mg[3])
+        );
+    }
+
+    #[test]
+    fn pawn_table_hits_match_fresh_evaluations() {
+        let params = EvalParams::default();
+        let mut table = PawnTable::new();
+        for fen in POSITIONS {
+            let board = Board::from_fen(fen).unwrap();
+            let fresh = evaluate(board, Color::White, &params, &mut PawnTable::new());
+            // The first call fills the entry and the second one reads it back
+            assert_eq!(evaluate(board, Color::White, &params, &mut table), fresh);
+            assert_eq!(evaluate(board, Color::White, &params, &mut table), fresh);
         }
-    };
-    return grid[mp.y as usize][mp.x as usize];
+        // Same pawns, different pieces: the cached structure still gives the same answer
+        let moved =
+            Board::from_fen("2r3k1/pp3ppp/4p3/3pP3/1P1P4/P7/3N1PPP/1R4K1 b - - 0 25").unwrap();
+        let fresh = evaluate(moved, Color::Black, &params, &mut PawnTable::new());
+        assert_eq!(evaluate(moved, Color::Black, &params, &mut table), fresh);
+    }
+
+    #[test]
+    fn king_safety_rewards_a_shield_and_punishes_attackers() {
+        let params = EvalParams::default();
+        let safety =
+            |fen: &str| king_safety(Board::from_fen(fen).unwrap(), Color::White, &params).mg;
+        let sheltered = safety("4k3/8/8/8/8/8/5PPP/6K1 w - - 0 1");
+        let exposed = safety("4k3/8/8/8/8/8/8/6K1 w - - 0 1");
+        let advanced = safety("4k3/8/8/8/8/5PPP/8/6K1 w - - 0 1");
+        assert!(sheltered > advanced);
+        assert!(advanced > exposed);
+        // A queen and a rook bearing down on the king zone
+        let attacked = safety("4k3/8/8/8/8/7q/5PPP/3r2K1 w - - 0 1");
+        assert!(attacked < sheltered);
+        // A lone attacker is not counted
+        let one = safety("4k3/8/8/8/8/7q/5PPP/6K1 w - - 0 1");
+        assert_eq!(one, sheltered);
+        // Nothing in the endgame half
+        assert_eq!(
+            king_safety(
+                Board::from_fen(POSITIONS[4]).unwrap(),
+                Color::Black,
+                &params
+            )
+            .eg,
+            0
+        );
+    }
+
+    #[test]
+    fn mobility_favours_active_pieces() {
+        let params = EvalParams::default();
+        let activity =
+            |fen: &str| piece_activity(Board::from_fen(fen).unwrap(), Color::White, &params);
+        let central = activity("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1");
+        let corner = activity("4k3/8/8/8/8/8/8/N3K3 w - - 0 1");
+        assert_eq!(central.mg - corner.mg, (8 - 2) * params.knight_mobility[1]);
+        assert_eq!(central.eg - corner.eg, (8 - 2) * params.knight_mobility[2]);
+        // Squares the enemy pawns cover do not count
+        let watched = activity("4k3/3p4/8/8/3N4/8/8/4K3 w - - 0 1");
+        assert_eq!(central.mg - watched.mg, 2 * params.knight_mobility[1]);
+        let open = activity("4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+        let boxed = activity("4k3/8/8/8/8/8/PP6/RN2K3 w - - 0 1");
+        assert!(open.mg > boxed.mg);
+    }
 }
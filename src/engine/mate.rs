@@ -0,0 +1,224 @@
+use crate::model::{board::Board, move_::Move};
+use std::collections::HashMap;
+
+// Proves forced mates with an alpha-beta search that only asks "can the side to move
+// mate within n moves?". Unlike the normal search there is no evaluation and no horizon,
+// so a mate it finds is forced against every defence and a mate it does not find does
+// not exist within n moves. Only queen promotions are generated, so mates that need an
+// underpromotion, or defences that use one, are outside what it can prove.
+
+pub(crate) struct MateSolver {
+    // Only try checking moves for the attacker. Much faster, but misses quiet first moves.
+    checks_only: bool,
+    // Positions already proved or refuted, by hash and number of moves left
+    cache: HashMap<(u64, u8), bool>,
+    nodes: u64,
+}
+
+// A forced mate: its length in moves and the main line against the longest defence
+pub(crate) struct Mate {
+    pub(crate) moves: u8,
+    pub(crate) line: Vec<Move>,
+}
+
+impl MateSolver {
+    pub(crate) fn new(checks_only: bool) -> MateSolver {
+        MateSolver {
+            checks_only,
+            cache: HashMap::new(),
+            nodes: 0,
+        }
+    }
+
+    pub(crate) fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    // The shortest forced mate for the side to move in at most `max_moves` moves
+    pub(crate) fn solve(&mut self, board: &Board, max_moves: u8) -> Option<Mate> {
+        let moves = self.shortest(board, max_moves)?;
+        Some(Mate {
+            moves,
+            line: self.line(board, moves),
+        })
+    }
+
+    fn shortest(&mut self, board: &Board, max_moves: u8) -> Option<u8> {
+        (1..=max_moves).find(|n| self.matesp(board, *n))
+    }
+
+    // Attacker moves worth trying, checks first
+    fn attacker_moves(&self, board: &Board) -> Vec<(Move, Board)> {
+        let defender = board.turn.opponent_color();
+        let mut checks: Vec<(Move, Board)> = Vec::new();
+        let mut quiet: Vec<(Move, Board)> = Vec::new();
+        for mv in board.get_all_moves(board.turn) {
+            let mut after = *board;
+            after.make_move(mv);
+            if after.in_checkp(defender) {
+                checks.push((mv, after));
+            } else if !self.checks_only {
+                quiet.push((mv, after));
+            }
+        }
+        checks.extend(quiet);
+        checks
+    }
+
+    // Whether the side to move mates in at most `n` moves
+    fn matesp(&mut self, board: &Board, n: u8) -> bool {
+        self.nodes += 1;
+        let key = (board.hash(), n);
+        if let Some(result) = self.cache.get(&key) {
+            return *result;
+        }
+        let mut result = false;
+        for (_, after) in self.attacker_moves(board) {
+            if self.defender_lostp(&after, n) {
+                result = true;
+                break;
+            }
+        }
+        self.cache.insert(key, result);
+        result
+    }
+
+    // Whether the side to move, just after the attacker's move, is mated within the
+    // attacker's `n` moves counting the one just played
+    fn defender_lostp(&mut self, board: &Board, n: u8) -> bool {
+        let replies = board.get_all_moves(board.turn);
+        if replies.is_empty() {
+            return board.in_checkp(board.turn);
+        }
+        if n == 1 {
+            return false;
+        }
+        for reply in replies {
+            let mut after = *board;
+            after.make_move(reply);
+            if !self.matesp(&after, n - 1) {
+                return false;
+            }
+        }
+        true
+    }
+
+    // The mating line for a position already known to be mate in exactly `n`. The
+    // defender always picks the reply that delays mate the longest.
+    fn line(&mut self, board: &Board, n: u8) -> Vec<Move> {
+        for (mv, after) in self.attacker_moves(board) {
+            if !self.defender_lostp(&after, n) {
+                continue;
+            }
+            let mut line = vec![mv];
+            let mut longest: Option<(u8, Move, Board)> = None;
+            for reply in after.get_all_moves(after.turn) {
+                let mut next = after;
+                next.make_move(reply);
+                let moves = self.shortest(&next, n - 1).unwrap_or(n - 1);
+                if longest.is_none_or(|(most, _, _)| moves > most) {
+                    longest = Some((moves, reply, next));
+                }
+            }
+            if let Some((moves, reply, next)) = longest {
+                line.push(reply);
+                line.extend(self.line(&next, moves));
+            }
+            return line;
+        }
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::notation;
+
+    // The mate found from `fen` within `max_moves`, as its length and the line in SAN
+    fn solve(fen: &str, max_moves: u8, checks_only: bool) -> Option<(u8, Vec<String>)> {
+        let board = Board::from_fen(fen).unwrap();
+        let mate = MateSolver::new(checks_only).solve(&board, max_moves)?;
+        let mut position = board;
+        let mut san: Vec<String> = Vec::new();
+        for mv in &mate.line {
+            san.push(notation::to_san(&position, *mv));
+            position.make_move(*mv);
+        }
+        Some((mate.moves, san))
+    }
+
+    fn line(moves: &[&str]) -> Vec<String> {
+        moves.iter().map(|m| m.to_string()).collect()
+    }
+
+    #[test]
+    fn mate_in_one() {
+        assert_eq!(
+            solve("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 3, false),
+            Some((1, line(&["Ra8#"])))
+        );
+        assert_eq!(
+            solve("6k1/5ppp/8/8/8/8/5PPP/1r4K1 b - - 0 1", 3, false),
+            Some((1, line(&["Ra1#"])))
+        );
+    }
+
+    #[test]
+    fn mate_in_two() {
+        // Philidor's smothered mate
+        assert_eq!(
+            solve("3r3k/6pp/7N/8/8/1Q6/8/6K1 w - - 0 1", 3, false),
+            Some((2, line(&["Qg8+", "Rxg8", "Nf7#"])))
+        );
+    }
+
+    #[test]
+    fn mate_in_three() {
+        assert_eq!(
+            solve(
+                "r1b1kb1r/pppp1ppp/5q2/4n3/3KP3/2N3PN/PPP4P/R1BQ1B1R b kq - 0 1",
+                3,
+                false
+            ),
+            Some((3, line(&["Bc5+", "Kxc5", "Qb6+", "Kd5", "Qd6#"])))
+        );
+    }
+
+    #[test]
+    fn no_mate_within_the_limit() {
+        let fen = "k7/8/8/2K5/8/8/8/1R6 w - - 0 1";
+        assert_eq!(solve(fen, 2, false), None);
+        assert_eq!(
+            solve(fen, 3, false),
+            Some((3, line(&["Kb6", "Kb8", "Rc1", "Ka8", "Rc8#"])))
+        );
+    }
+
+    #[test]
+    fn checks_only() {
+        // Every attacking move checks on the way to the smothered mate
+        assert_eq!(
+            solve("3r3k/6pp/8/6N1/8/1Q6/8/6K1 w - - 0 1", 4, true),
+            Some((
+                4,
+                line(&["Nf7+", "Kg8", "Nh6+", "Kh8", "Qg8+", "Rxg8", "Nf7#"])
+            ))
+        );
+        // Mate in two that starts with a quiet king move
+        let fen = "k7/8/2K5/8/8/8/8/6R1 w - - 0 1";
+        assert_eq!(
+            solve(fen, 2, false),
+            Some((2, line(&["Kc7", "Ka7", "Ra1#"])))
+        );
+        assert_eq!(solve(fen, 2, true), None);
+    }
+
+    #[test]
+    fn stalemate_is_a_defence() {
+        // Ka6 stalemates and anything else gives up the pawn
+        assert_eq!(solve("k7/P7/1K6/8/8/8/8/8 w - - 0 1", 5, false), None);
+        // Already stalemated
+        assert_eq!(solve("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1", 2, false), None);
+    }
+}
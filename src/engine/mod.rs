@@ -1,22 +1,137 @@
+pub(crate) mod book;
+pub(crate) mod endgame;
+pub(crate) mod eval_params;
 mod evaluator;
-mod node;
-use crate::model::{
-    board::Board, color::Color, move_::Move, piece_type::PieceType, position::Position,
-};
-use crate::player;
+pub(crate) mod mate;
+pub(crate) mod nnue;
+mod polyglot_randoms;
+pub(crate) mod ponder;
+pub(crate) mod skill;
+pub(crate) mod syzygy;
+pub(crate) mod tuner;
+use self::book::Book;
+use self::endgame::{Dtm, EndgameTables};
+use self::eval_params::EvalParams;
+use self::evaluator::PawnTable;
+use self::nnue::{Accumulator, Network};
+use self::ponder::{Ponder, SearchControl};
+use self::skill::Skill;
+use self::syzygy::Tablebase;
+use crate::model::{board::Board, color::Color, move_::Move, notation, position::Position};
+use crate::player::{self, Action, GameResult};
 use std::cmp;
+use std::sync::Arc;
 
 use std::thread;
 use std::thread::JoinHandle;
 use std::time::Duration;
 use std::time::Instant;
 
-const THREADED: bool = true;
+// The threaded root search runs fixed depth searches until TIME_LIMIT and then plays the
+// first legal move, so it stays switched off in favour of iterative deepening
+const THREADED: bool = false;
 const TIME_LIMIT: Duration = Duration::from_secs(60);
 const NUM_THREADS: usize = 8;
+// Iterative deepening stops here, for searches without a time limit
+const MAX_DEPTH: u8 = 64;
+// Score for delivering checkmate at the root. A mate found `ply` half moves into the
+// search scores `MATE - ply`, so shorter mates are preferred.
+const MATE: i16 = 32000;
+// Scores beyond this are mates. The search never gets deeper than 255 plies.
+const MATE_BOUND: i16 = MATE - 256;
+// Score for a position the tablebases say is won, below any checkmate score
+const TB_WIN: i16 = 20000;
+// Draw offers are accepted when the engine scores its position at most this
+const DRAW_ACCEPT_SCORE: i16 = 0;
+
+// How long the engine may think about a move. The search stops at whichever limit it
+// reaches first.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct SearchLimits {
+    pub(crate) depth: Option<u8>,
+    pub(crate) move_time: Duration,
+}
+
+// Narrows the window to the scores still possible `ply` half moves from the root. The
+// side to move can at best mate with its next move and at worst is mated right now.
+// Scores are from the point of view of the maximizing side.
+fn mate_distance_window(al: i16, be: i16, max: bool, ply: u8) -> (i16, i16) {
+    let ply = ply as i16;
+    if max {
+        return (cmp::max(al, -(MATE - ply)), cmp::min(be, MATE - ply - 1));
+    }
+    (cmp::max(al, -(MATE - ply - 1)), cmp::min(be, MATE - ply))
+}
+
+// Moves until mate for a mate score, positive when the side the score is for mates
+pub(crate) fn mate_in(score: i16) -> Option<i16> {
+    if score > MATE_BOUND {
+        return Some((MATE - score + 1) / 2);
+    } else if score < -MATE_BOUND {
+        return Some(-(MATE + score) / 2);
+    }
+    None
+}
+
+// A score as UCI reports it, "cp 35" or "mate -3"
+pub(crate) fn format_score(score: i16) -> String {
+    match mate_in(score) {
+        Some(moves) => format!("mate {moves}"),
+        None => format!("cp {score}"),
+    }
+}
+
+impl Default for SearchLimits {
+    fn default() -> SearchLimits {
+        SearchLimits {
+            depth: None,
+            move_time: TIME_LIMIT,
+        }
+    }
+}
 
 pub struct AI {
     pos_evaluated: u64,
+    limits: SearchLimits,
+    deadline: Instant,
+    stopped: bool,
+    // Print search progress and where moves came from
+    verbose: bool,
+    params: EvalParams,
+    pawn_table: PawnTable,
+    // When set, positions are scored by the network instead of the handcrafted evaluation.
+    // The accumulator stack mirrors the moves made along the current search path.
+    network: Option<Arc<Network>>,
+    accumulators: Vec<Accumulator>,
+    book: Option<Arc<Book>>,
+    tablebase: Option<Arc<Tablebase>>,
+    endgame_tables: Option<Arc<EndgameTables>>,
+    // Number of best lines to search for, 1 for normal play
+    multi_pv: usize,
+    // Score of the last move searched, for the side that played it
+    last_score: Option<i16>,
+    // Principal variation of the last move searched, starting with that move
+    last_pv: Vec<Move>,
+    // The opponent offered a draw, answered on the next turn
+    draw_offered: bool,
+    // Think on the opponent's time after each move
+    ponder: bool,
+    pondering: Option<Ponder>,
+    // Set when another thread decides when the search ends, instead of `limits`
+    control: Option<Arc<SearchControl>>,
+    skill: Skill,
+    // Varies the evaluation noise of weaker skill levels from one search to the next
+    noise_seed: u64,
+}
+
+// One line of analysis: a root move, its score for the side to move and the expected
+// continuation starting with that move
+#[derive(Clone, Debug)]
+pub(crate) struct PvLine {
+    pub(crate) mv: Move,
+    pub(crate) score: i16,
+    pub(crate) depth: u8,
+    pub(crate) pv: Vec<Move>,
 }
 
 pub struct MoveScore {
@@ -26,7 +141,7 @@ pub struct MoveScore {
 
 impl PartialEq for MoveScore {
     fn eq(&self, other: &Self) -> bool {
-        return self.board == other.board && self.score == other.score;
+        self.board == other.board && self.score == other.score
     }
 }
 
@@ -40,20 +155,143 @@ impl PartialOrd for MoveScore {
 
 impl Ord for MoveScore {
     fn cmp(&self, other: &Self) -> cmp::Ordering {
-        return self.score.cmp(&other.score);
+        self.score.cmp(&other.score)
     }
 }
 
 impl player::Player for AI {
-    fn take_turn(&mut self, board: Board, color: Color) -> Move {
-        if THREADED {
-            return self.take_turn_threaded(board, color);
+    fn new_game(&mut self, _board: Board, _color: Color) {
+        self.pondering = None;
+        self.last_score = None;
+        self.draw_offered = false;
+    }
+
+    fn take_turn(&mut self, board: Board, color: Color) -> Action {
+        let mv = self.choose_move(board, color);
+        if self.draw_offered {
+            self.draw_offered = false;
+            let score = match self.last_score {
+                Some(score) => score,
+                None => match self.probe_tablebase(&board, 0) {
+                    Some(score) => score,
+                    None => self.static_eval(&board, color),
+                },
+            };
+            if score <= DRAW_ACCEPT_SCORE {
+                if self.verbose {
+                    println!("Draw accepted at {}", format_score(score));
+                }
+                return Action::AcceptDraw;
+            }
         }
-        return self.take_turn_private(board, color);
+        Action::Move(mv)
+    }
+
+    fn draw_offered(&mut self, _board: Board, _color: Color) {
+        self.draw_offered = true;
+    }
+
+    // Takebacks are always allowed
+    fn takeback_requested(&mut self, _board: Board, _color: Color) -> bool {
+        self.pondering = None;
+        true
+    }
+
+    fn opponent_moved(&mut self, _board: Board, mv: Move) {
+        match &self.pondering {
+            Some(ponder) if ponder.reply() == mv => ponder.hit(self.limits.move_time),
+            Some(_) => self.pondering = None,
+            None => {}
+        }
+    }
+
+    // On a clock, spend about a thirtieth of the remaining time on each move
+    fn time_update(&mut self, own: Duration, _opponent: Duration) {
+        self.limits.move_time = (own / 30).max(Duration::from_millis(100));
+    }
+
+    fn game_over(&mut self, _result: &GameResult) {
+        self.pondering = None;
     }
 }
 
 impl AI {
+    // The move to play: from the search started on the opponent's time, the book, the
+    // tablebases or a new search. With pondering on, starts thinking about the reply.
+    pub(crate) fn choose_move(&mut self, board: Board, color: Color) -> Move {
+        let mv = match self.finish_ponder(&board, color) {
+            Some(mv) => mv,
+            None => self.select_move(board, color),
+        };
+        if self.ponder {
+            let mut position = board;
+            position.make_move(mv);
+            if let Some(reply) = self.last_pv.get(1) {
+                self.pondering = Some(Ponder::start(self, position, *reply, color));
+            }
+        }
+        mv
+    }
+
+    // The best move from a ponder search of this position, if there is one
+    fn finish_ponder(&mut self, board: &Board, color: Color) -> Option<Move> {
+        let ponder = self.pondering.take()?;
+        if ponder.board() != *board || board.turn != color {
+            return None;
+        }
+        let (lines, nodes) = ponder.finish(self.limits.move_time);
+        let line = self.skill.pick(&lines)?;
+        self.pos_evaluated = nodes;
+        self.last_score = Some(line.score);
+        self.last_pv = line.pv.clone();
+        if self.verbose {
+            println!("Ponder hit");
+            println!("{}", self.info_line(board, 1, line));
+        }
+        Some(line.mv)
+    }
+
+    fn select_move(&mut self, board: Board, color: Color) -> Move {
+        self.last_score = None;
+        self.last_pv.clear();
+        if let Some(book) = &self.book {
+            if let Some(mv) = book.pick(&board) {
+                if self.verbose {
+                    println!("Book move");
+                }
+                return mv;
+            }
+        }
+        if let Some(tablebase) = self
+            .tablebase
+            .as_ref()
+            .filter(|_| self.skill.full_strengthp())
+        {
+            if let Some((mv, dtz)) = tablebase.best_move(&board) {
+                if self.verbose {
+                    println!("Tablebase move, DTZ {dtz}");
+                }
+                return mv;
+            }
+        }
+        if let Some(tables) = self
+            .endgame_tables
+            .as_ref()
+            .filter(|_| self.skill.full_strengthp())
+        {
+            if let Some((mv, result)) = tables.best_move(&board) {
+                if self.verbose {
+                    println!("Endgame table move, {result:?}");
+                }
+                return mv;
+            }
+        }
+        if THREADED {
+            return self.take_turn_threaded(board, color);
+        }
+        self.take_turn_private(board, color)
+    }
+
     fn take_turn_threaded(&mut self, board: Board, color: Color) -> Move {
         let start_time: Instant = Instant::now();
 
@@ -103,58 +341,188 @@ impl AI {
             }
             // Explore board threaded...
         }
-        return board.get_all_moves(color)[0];
+        board.get_all_moves(color)[0]
     }
 
     fn take_turn_private(&mut self, board: Board, color: Color) -> Move {
-        let now: Instant;
-        let elapsed: Duration;
-        now = Instant::now();
-
-        let mut mv: Move;
-        let mut score: i16 = -32768;
-        self.pos_evaluated = 0;
-        let mut temp_score: i16;
-
         let moves = board.get_all_moves(color);
-
         if moves.len() == 1 {
             return moves[0];
-        } else if moves.len() == 0 {
+        } else if moves.is_empty() {
             return Move {
                 start: Position { x: 0, y: 0 },
                 end: Position { x: 0, y: 0 },
             };
         }
+        let lines = self.analyze(board, color);
+        match self.skill.pick(&lines) {
+            Some(line) => {
+                self.last_pv = line.pv.clone();
+                line.mv
+            }
+            None => moves[0],
+        }
+    }
 
-        mv = moves[0];
-        let mut depth: u8 = 1;
-
-        while now.elapsed() < TIME_LIMIT {
-            for mov in &moves {
-                let mut nb = board.clone();
-                nb.make_move(*mov);
-                temp_score = self
-                    .alphabeta_trace(nb, depth, -32768, 32767, false, color.opponent_color())
-                    .0;
-
-                println!("{mov}: {temp_score}");
+    // Searches for the best `multi_pv` root moves with iterative deepening, best first.
+    // Each pass of an iteration searches the root moves not already chosen by an earlier
+    // pass, so line k is the best move once lines 1 to k - 1 are excluded, as with the UCI
+    // MultiPV option. Weaker skill levels search more lines to choose from.
+    pub(crate) fn analyze(&mut self, board: Board, color: Color) -> Vec<PvLine> {
+        let now: Instant = Instant::now();
+        self.deadline = now + self.limits.move_time;
+        self.stopped = false;
+        self.pos_evaluated = 0;
+        self.noise_seed = book::random_seed();
+        let lines_wanted = cmp::max(self.multi_pv, self.skill.candidates());
+        let max_depth = cmp::min(
+            self.limits.depth.unwrap_or(MAX_DEPTH),
+            self.skill.depth().unwrap_or(MAX_DEPTH),
+        );
 
-                if temp_score > score {
-                    mv = *mov;
-                    score = temp_score;
+        let mut moves = board.get_all_moves(color);
+        let mut lines: Vec<PvLine> = Vec::new();
+        let mut depth: u8 = 1;
+        let mut pv: Vec<Move> = Vec::new();
+        self.refresh_accumulator(&board);
+
+        while !moves.is_empty() && !self.time_up() && depth <= max_depth {
+            let mut iteration: Vec<PvLine> = Vec::new();
+            for _ in 0..cmp::min(lines_wanted, moves.len()) {
+                let mut best: Option<PvLine> = None;
+                for mov in &moves {
+                    if iteration.iter().any(|line| line.mv == *mov) {
+                        continue;
+                    }
+                    // Moves that cannot beat the best of this pass only need a bound
+                    let alpha = best.as_ref().map_or(-32768, |line| line.score);
+                    let mut nb = board;
+                    nb.make_move(*mov);
+                    self.push_move(&board, *mov);
+                    let score = self
+                        .alphabeta_trace(
+                            nb,
+                            depth,
+                            alpha,
+                            32767,
+                            false,
+                            color.opponent_color(),
+                            1,
+                            syzygy::zeroingp(&board, *mov),
+                            &mut pv,
+                        )
+                        .0;
+                    self.pop_move();
+                    if self.stopped {
+                        break;
+                    }
+                    if best.as_ref().is_none_or(|line| score > line.score) {
+                        let mut line_moves = vec![*mov];
+                        line_moves.extend_from_slice(&pv);
+                        best = Some(PvLine {
+                            mv: *mov,
+                            score,
+                            depth,
+                            pv: line_moves,
+                        });
+                    }
+                }
+                if let Some(line) = best {
+                    iteration.push(line);
+                }
+                if self.stopped {
+                    break;
+                }
+            }
+            // An unfinished iteration only counts when there is nothing better to go on
+            if !self.stopped || lines.is_empty() {
+                lines = iteration;
+            }
+            if self.verbose {
+                for (i, line) in lines.iter().enumerate() {
+                    println!("{}", self.info_line(&board, i + 1, line));
+                }
+            }
+            if self.stopped {
+                break;
+            }
+            // Search the best lines first next time, for earlier cutoffs
+            for line in lines.iter().rev() {
+                if let Some(index) = moves.iter().position(|m| *m == line.mv) {
+                    let mv = moves.remove(index);
+                    moves.insert(0, mv);
                 }
             }
             depth += 1;
         }
 
+        self.last_score = lines.first().map(|line| line.score);
+        self.last_pv = lines.first().map_or(Vec::new(), |line| line.pv.clone());
+        if let Some(control) = &self.control {
+            control.wait();
+        }
         let evals = self.pos_evaluated;
-        elapsed = now.elapsed();
+        let elapsed: Duration = now.elapsed();
         let per_second = (evals as f64) / elapsed.as_secs_f64();
-        println!("Evaluted {evals} positions in {elapsed:?} for a speed of {per_second} positions per second");
-        println!("Best Position: {score}");
+        if self.verbose {
+            println!("Evaluted {evals} positions in {elapsed:?} for a speed of {per_second} positions per second");
+            if let Some(line) = lines.first() {
+                println!("Best Position: {}", format_score(line.score));
+            }
+        }
+
+        lines
+    }
 
-        return mv;
+    // A line in the form of a UCI info string, with moves in long algebraic notation
+    pub(crate) fn info_line(&self, board: &Board, index: usize, line: &PvLine) -> String {
+        let mut position = *board;
+        let mut moves: Vec<String> = Vec::new();
+        for mv in &line.pv {
+            moves.push(notation::to_long_algebraic(&position, *mv));
+            position.make_move(*mv);
+        }
+        format!(
+            "info depth {} multipv {index} score {} nodes {} pv {}",
+            line.depth,
+            format_score(line.score),
+            self.pos_evaluated,
+            moves.join(" ")
+        )
+    }
+
+    fn time_up(&self) -> bool {
+        match &self.control {
+            Some(control) => control.expiredp(Instant::now()),
+            None => Instant::now() >= self.deadline,
+        }
+    }
+
+    // Checked at every node, looks at the clock every 1024 nodes
+    fn out_of_time(&mut self) -> bool {
+        if !self.stopped && self.pos_evaluated.is_multiple_of(1024) && self.time_up() {
+            self.stopped = true;
+        }
+        if self
+            .skill
+            .nodes()
+            .is_some_and(|max| self.pos_evaluated >= max)
+        {
+            self.stopped = true;
+        }
+        self.stopped
+    }
+
+    // A quiet engine with the same evaluation and tables, to search on another thread
+    fn helper(&self) -> AI {
+        let mut ai = AI::with_params(self.params.clone());
+        ai.network = self.network.clone();
+        ai.tablebase = self.tablebase.clone();
+        ai.endgame_tables = self.endgame_tables.clone();
+        ai.limits = self.limits;
+        ai.skill = self.skill;
+        ai.verbose = false;
+        ai
     }
 
     fn new_thread(
@@ -164,93 +532,176 @@ impl AI {
         color: Color,
         depth: u8,
     ) -> JoinHandle<(i16, Board, u64)> {
-        let mut ai = AI { pos_evaluated: 0 };
-        let mut nb = board.clone();
+        let mut ai = self.helper();
+        let mut nb = board;
         nb.make_move(move_);
-        return thread::spawn(move || {
-            let (rscore, rboard) = ai.alphabeta_trace(nb, depth, -32768, 32767, false, color);
-            return (rscore, rboard, ai.pos_evaluated);
-        });
+        let zeroing = syzygy::zeroingp(&board, move_);
+        thread::spawn(move || {
+            ai.refresh_accumulator(&nb);
+            let (rscore, rboard) = ai.alphabeta_trace(
+                nb,
+                depth,
+                -32768,
+                32767,
+                false,
+                color,
+                1,
+                zeroing,
+                &mut Vec::new(),
+            );
+            (rscore, rboard, ai.pos_evaluated)
+        })
     }
 
-    pub fn new() -> AI {
-        return AI { pos_evaluated: 0 };
+    pub(crate) fn with_params(params: EvalParams) -> AI {
+        AI {
+            pos_evaluated: 0,
+            limits: SearchLimits::default(),
+            deadline: Instant::now(),
+            stopped: false,
+            verbose: true,
+            params,
+            pawn_table: PawnTable::new(),
+            network: None,
+            accumulators: Vec::new(),
+            book: None,
+            tablebase: None,
+            endgame_tables: None,
+            multi_pv: 1,
+            last_score: None,
+            last_pv: Vec::new(),
+            draw_offered: false,
+            ponder: false,
+            pondering: None,
+            control: None,
+            skill: Skill::default(),
+            noise_seed: 0,
+        }
     }
 
-    fn alphabeta(
-        &mut self,
-        board: Board,
-        depth: u8,
-        al: i16,
-        be: i16,
-        max: bool,
-        color: Color,
-    ) -> i16 {
-        self.pos_evaluated += 1;
+    pub(crate) fn set_limits(&mut self, limits: SearchLimits) {
+        self.limits = limits;
+    }
 
-        if board.checkmatep(color) {
-            // This possible introduces a bug, could be just return -32768?
-            return if max { -32768 } else { 32767 };
-        } else if depth == 0 {
-            return if max {
-                evaluator::evaluate(board, color)
-            } else {
-                evaluator::evaluate(board, color) * -1
-            };
-        }
+    pub(crate) fn set_verbose(&mut self, verbose: bool) {
+        self.verbose = verbose;
+    }
 
-        let mut best_score: i16;
-        let mut cur_score: i16;
-        let mut temp_board: Board;
-        let op: Color = color.opponent_color();
+    // Evaluation of the position without searching, for `color`
+    pub(crate) fn static_eval(&mut self, board: &Board, color: Color) -> i16 {
+        self.refresh_accumulator(board);
+        self.evaluate(*board, color)
+    }
 
-        let mut a = al;
-        let mut b = be;
+    pub(crate) fn last_score(&self) -> Option<i16> {
+        self.last_score
+    }
 
-        let moves: Vec<Move> = board.get_all_moves(color);
-        if max {
-            best_score = -32768;
+    pub(crate) fn last_pv(&self) -> &[Move] {
+        &self.last_pv
+    }
 
-            for mv in moves {
-                temp_board = board.clone();
-                temp_board.make_move(mv);
-                cur_score = self.alphabeta(temp_board, depth - 1, a, b, false, op);
+    pub(crate) fn set_ponder(&mut self, ponder: bool) {
+        self.ponder = ponder;
+        if !ponder {
+            self.pondering = None;
+        }
+    }
 
-                if cur_score > best_score {
-                    best_score = cur_score;
-                }
+    pub(crate) fn set_skill(&mut self, skill: Skill) {
+        self.skill = skill;
+    }
 
-                a = cmp::max(a, best_score);
+    // Hands the decision when to stop searching to another thread
+    pub(crate) fn set_control(&mut self, control: Option<Arc<SearchControl>>) {
+        self.control = control;
+    }
 
-                if best_score >= b {
-                    return best_score;
-                }
-            }
+    pub(crate) fn set_multi_pv(&mut self, lines: usize) {
+        self.multi_pv = cmp::max(lines, 1);
+    }
 
-            return best_score;
-        } else {
-            best_score = 32767;
+    pub(crate) fn set_book(&mut self, book: Arc<Book>) {
+        self.book = Some(book);
+    }
 
-            for mv in moves {
-                temp_board = board.clone();
-                temp_board.make_move(mv);
-                cur_score = self.alphabeta(temp_board, depth - 1, a, b, true, op);
+    pub(crate) fn set_tablebase(&mut self, tablebase: Arc<Tablebase>) {
+        self.tablebase = Some(tablebase);
+    }
 
-                if cur_score < best_score {
-                    best_score = cur_score;
-                }
+    pub(crate) fn set_endgame_tables(&mut self, tables: Arc<EndgameTables>) {
+        self.endgame_tables = Some(tables);
+    }
 
-                b = cmp::min(b, best_score);
+    pub(crate) fn set_network(&mut self, network: Arc<Network>) {
+        self.network = Some(network);
+    }
+
+    fn evaluate(&mut self, board: Board, color: Color) -> i16 {
+        match &self.network {
+            Some(network) => network.evaluate(self.accumulators.last().unwrap(), color),
+            None => evaluator::evaluate(board, color, &self.params, &mut self.pawn_table),
+        }
+    }
+
+    // The evaluation at the leaves of the search, blurred for weaker skill levels. The
+    // noise is from White's point of view so both sides see the same blur.
+    fn leaf_evaluate(&mut self, board: Board, color: Color) -> i16 {
+        if self.skill.noise() == 0 {
+            return self.evaluate(board, color);
+        }
+        let noise = self.skill.noise_for(board.hash(), self.noise_seed);
+        let noise = if color == Color::White { noise } else { -noise };
+        self.evaluate(board, color).saturating_add(noise)
+    }
 
-                if best_score <= a {
-                    return best_score;
+    // Exact score from the Syzygy or our own endgame tables, from the point of view of
+    // the side to move, for a position `ply` half moves from the root. Faster mates score
+    // higher.
+    fn probe_tablebase(&self, board: &Board, ply: u8) -> Option<i16> {
+        let ply = ply as i16;
+        if let Some(tablebase) = &self.tablebase {
+            if tablebase.coveredp(board) {
+                if let Some(wdl) = tablebase.probe_wdl(board) {
+                    return match wdl {
+                        2 => Some(TB_WIN - ply),
+                        -2 => Some(ply - TB_WIN),
+                        // Results the 50 move rule turns into draws
+                        wdl => Some(wdl as i16),
+                    };
                 }
             }
+        }
+        match self.endgame_tables.as_ref()?.probe(board)? {
+            Dtm::Win(plies) => Some(TB_WIN - ply - plies as i16),
+            Dtm::Loss(plies) => Some(ply + plies as i16 - TB_WIN),
+            Dtm::Draw => Some(0),
+        }
+    }
+
+    // Starts a new accumulator stack at the root of a search
+    fn refresh_accumulator(&mut self, board: &Board) {
+        if let Some(network) = &self.network {
+            self.accumulators = vec![network.refresh(board)];
+        }
+    }
+
+    // Call after making `mv` on `before` and before searching the position it leads to
+    fn push_move(&mut self, before: &Board, mv: Move) {
+        if let Some(network) = &self.network {
+            let next = network.update(self.accumulators.last().unwrap(), before, mv);
+            self.accumulators.push(next);
+        }
+    }
 
-            return best_score;
+    // Call when done searching the position added by the last `push_move`
+    fn pop_move(&mut self) {
+        if self.network.is_some() {
+            self.accumulators.pop();
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn alphabeta_trace(
         &mut self,
         board: Board,
@@ -259,17 +710,39 @@ impl AI {
         be: i16,
         max: bool,
         color: Color,
+        ply: u8,
+        zeroing: bool,
+        pv: &mut Vec<Move>,
     ) -> (i16, Board) {
         self.pos_evaluated += 1;
+        pv.clear();
+        if self.out_of_time() {
+            return (0, board);
+        }
 
         if board.checkmatep(color) {
-            // This possible introduces a bug, could be just return -32768?
-            return if max { (-32768, board) } else { (32767, board) };
-        } else if depth == 0 {
             return if max {
-                (evaluator::evaluate(board, color), board)
+                (-(MATE - ply as i16), board)
+            } else {
+                (MATE - ply as i16, board)
+            };
+        }
+        // The tables are only probed after a capture or pawn move. Captures are how a
+        // search reaches a covered piece count, and the WDL result is only exact while the
+        // 50 move counter is zero.
+        if let Some(score) = zeroing.then(|| self.probe_tablebase(&board, ply)).flatten() {
+            return if max { (score, board) } else { (-score, board) };
+        }
+
+        let (a, b) = mate_distance_window(al, be, max, ply);
+        if a >= b {
+            return if max { (a, board) } else { (b, board) };
+        }
+        if depth == 0 {
+            return if max {
+                (self.leaf_evaluate(board, color), board)
             } else {
-                (evaluator::evaluate(board, color.opponent_color()), board)
+                (self.leaf_evaluate(board, color.opponent_color()), board)
             };
         }
 
@@ -280,12 +753,17 @@ impl AI {
         let mut temp_board: Board;
         let op: Color = color.opponent_color();
 
-        let mut a = al;
-        let mut b = be;
+        let mut a = a;
+        let mut b = b;
 
         let moves: Vec<Move> = board.get_all_moves(color);
-        ret_board = board.clone();
+        if moves.is_empty() {
+            // Not checkmate, so stalemate
+            return (0, board);
+        }
+        ret_board = board;
         ret_board.make_move(moves[0]);
+        let mut child_pv: Vec<Move> = Vec::new();
         //let mut mvv_lva_moves: Vec<(Move, i16)> = moves.into_iter().map(|mva| (mva, self.mvv_lva_score(board, mva))).collect();
         if max {
             best_score = -32768;
@@ -294,14 +772,28 @@ impl AI {
             //self.pick_move(&mut mvv_lva_moves, i);
             //mv = mvv_lva_moves[i].0;
             for mv in moves {
-                temp_board = board.clone();
+                temp_board = board;
                 temp_board.make_move(mv);
-                (cur_score, temp_ret_board) =
-                    self.alphabeta_trace(temp_board, depth - 1, a, b, false, op);
+                self.push_move(&board, mv);
+                (cur_score, temp_ret_board) = self.alphabeta_trace(
+                    temp_board,
+                    depth - 1,
+                    a,
+                    b,
+                    false,
+                    op,
+                    ply + 1,
+                    syzygy::zeroingp(&board, mv),
+                    &mut child_pv,
+                );
+                self.pop_move();
 
                 if cur_score > best_score {
                     best_score = cur_score;
                     ret_board = temp_ret_board;
+                    pv.clear();
+                    pv.push(mv);
+                    pv.extend_from_slice(&child_pv);
                 }
 
                 a = cmp::max(a, best_score);
@@ -311,7 +803,7 @@ impl AI {
                 }
             }
 
-            return (best_score, board);
+            (best_score, board)
         } else {
             best_score = 32767;
 
@@ -319,14 +811,28 @@ impl AI {
             //self.pick_move(&mut mvv_lva_moves, i);
             //mv = mvv_lva_moves[i].0;
             for mv in moves {
-                temp_board = board.clone();
+                temp_board = board;
                 temp_board.make_move(mv);
-                (cur_score, temp_ret_board) =
-                    self.alphabeta_trace(temp_board, depth - 1, a, b, true, op);
+                self.push_move(&board, mv);
+                (cur_score, temp_ret_board) = self.alphabeta_trace(
+                    temp_board,
+                    depth - 1,
+                    a,
+                    b,
+                    true,
+                    op,
+                    ply + 1,
+                    syzygy::zeroingp(&board, mv),
+                    &mut child_pv,
+                );
+                self.pop_move();
 
                 if cur_score < best_score {
                     best_score = cur_score;
                     ret_board = temp_ret_board;
+                    pv.clear();
+                    pv.push(mv);
+                    pv.extend_from_slice(&child_pv);
                 }
 
                 b = cmp::min(b, best_score);
@@ -336,43 +842,111 @@ impl AI {
                 }
             }
 
-            return (best_score, ret_board);
-        }
-    }
-
-    fn mvv_lva_score(&self, board: Board, mv: Move) -> i16 {
-        return match board.get_piece(mv.end).piece_type {
-            PieceType::Bishop => 30,
-            PieceType::Knight => 20,
-            PieceType::Rook => 40,
-            PieceType::King => 0,
-            PieceType::Queen => 50,
-            PieceType::Pawn => 10,
-            PieceType::Empty => 0,
-        } + match board.get_piece(mv.start).piece_type {
-            PieceType::Bishop => 3,
-            PieceType::Knight => 4,
-            PieceType::Rook => 2,
-            PieceType::King => 0,
-            PieceType::Queen => 1,
-            PieceType::Pawn => 5,
-            PieceType::Empty => 0,
-        };
+            (best_score, ret_board)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::notation;
+
+    // The best `lines` lines from `fen` searched to `depth`
+    fn analyze(fen: &str, depth: u8, lines: usize) -> Vec<PvLine> {
+        let board = Board::from_fen(fen).unwrap();
+        let mut ai = AI::with_params(EvalParams::default());
+        ai.set_verbose(false);
+        ai.set_multi_pv(lines);
+        ai.set_limits(SearchLimits {
+            depth: Some(depth),
+            move_time: Duration::from_secs(60),
+        });
+        ai.analyze(board, board.turn)
+    }
+
+    // The best move from `fen` searched to `depth`, in SAN, and its score
+    fn search(fen: &str, depth: u8) -> (String, i16) {
+        let board = Board::from_fen(fen).unwrap();
+        let line = &analyze(fen, depth, 1)[0];
+        (notation::to_san(&board, line.mv), line.score)
+    }
+
+    #[test]
+    fn mate_scores_count_moves() {
+        assert_eq!(mate_in(MATE - 1), Some(1));
+        assert_eq!(mate_in(MATE - 3), Some(2));
+        assert_eq!(mate_in(-(MATE - 2)), Some(-1));
+        assert_eq!(mate_in(-(MATE - 4)), Some(-2));
+        assert_eq!(mate_in(TB_WIN), None);
+        assert_eq!(mate_in(-35), None);
+        assert_eq!(format_score(MATE - 3), "mate 2");
+        assert_eq!(format_score(-(MATE - 2)), "mate -1");
+        assert_eq!(format_score(35), "cp 35");
     }
 
-    fn pick_move(&self, move_list: &mut Vec<(Move, i16)>, i: usize) {
-        let mut max_ind = i;
-        let mut max_val = move_list[i].1;
-        let mut iter = i;
-        while iter < move_list.len() {
-            if move_list[iter].1 > max_val {
-                max_ind = iter;
-                max_val = move_list[iter].1;
+    #[test]
+    fn finds_mate_in_one() {
+        let (mv, score) = search("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 4);
+        assert_eq!(mv, "Ra8#");
+        assert_eq!(mate_in(score), Some(1));
+    }
+
+    #[test]
+    fn finds_mate_in_two() {
+        let (mv, score) = search("3r3k/6pp/7N/8/8/1Q6/8/6K1 w - - 0 1", 5);
+        assert_eq!(mv, "Qg8+");
+        assert_eq!(mate_in(score), Some(2));
+    }
+
+    #[test]
+    fn sees_being_mated() {
+        let (mv, score) = search("k7/8/1K6/8/8/8/8/7R b - - 0 1", 4);
+        assert_eq!(mv, "Kb8");
+        assert_eq!(mate_in(score), Some(-1));
+    }
+
+    #[test]
+    fn prefers_the_shorter_mate() {
+        // Mate in one is there, and a deeper search also sees the slower mates
+        let (mv, quick) = search("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 6);
+        assert_eq!(mv, "Ra8#");
+        let (_, slow) = search("3r3k/6pp/7N/8/8/1Q6/8/6K1 w - - 0 1", 6);
+        assert!(quick > slow);
+        assert!(slow > MATE_BOUND);
+    }
+
+    #[test]
+    fn multi_pv_lines_are_distinct_and_sorted() {
+        let fen = "r1bqk2r/pppp1ppp/2n2n2/2b1p3/2B1P3/3P1N2/PPP2PPP/RNBQK2R w KQkq - 4 5";
+        let lines = analyze(fen, 3, 4);
+        assert_eq!(lines.len(), 4);
+        for (i, line) in lines.iter().enumerate() {
+            assert_eq!(line.depth, 3);
+            assert_eq!(line.pv.first(), Some(&line.mv));
+            assert!(lines[..i].iter().all(|other| other.mv != line.mv));
+            if i > 0 {
+                assert!(lines[i - 1].score >= line.score);
             }
-            iter += 1;
         }
-        let temp = move_list[i];
-        move_list[i] = move_list[max_ind];
-        move_list[max_ind] = temp;
+        // The first line is the one a single line search finds
+        assert_eq!(analyze(fen, 3, 1)[0].mv, lines[0].mv);
+    }
+
+    #[test]
+    fn multi_pv_ranks_the_mate_first() {
+        let lines = analyze("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 3, 3);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(mate_in(lines[0].score), Some(1));
+        assert!(lines[1..].iter().all(|line| mate_in(line.score).is_none()));
+    }
+
+    #[test]
+    fn multi_pv_stops_at_the_legal_moves() {
+        // In check from the rook with only Kg8 and Kg7
+        let lines = analyze("7k/8/8/8/8/8/8/K6R b - - 0 1", 3, 5);
+        assert_eq!(lines.len(), 2);
+        assert_ne!(lines[0].mv, lines[1].mv);
+        assert_eq!(analyze("7k/8/8/8/8/8/8/K6R b - - 0 1", 3, 0).len(), 1);
     }
 }
@@ -0,0 +1,217 @@
+use crate::model::{
+    board::Board, color::Color, move_::Move, piece::Piece, piece_type::PieceType,
+    position::Position, zobrist,
+};
+use std::fs;
+
+// A small efficiently updatable neural network, used as an alternative to the handcrafted
+// evaluation. The input layer has one feature per (piece, square), 768 in total, seen from
+// each side's point of view. The hidden layer is kept for both sides in an `Accumulator`,
+// which only needs a few columns added or removed after each move. The two halves go
+// through a clipped ReLU into a single output neuron.
+//
+// Everything runs in integers. Hidden activations are clipped to [0, QA] and the output
+// weights are scaled by QB, so the output is divided by QA * QB and scaled to centipawns.
+//
+// Weights file layout, all little endian:
+//   4 bytes  magic "CNUE"
+//   u32      hidden size H
+//   i16      768 * H feature weights, feature by feature
+//   i16      H hidden biases
+//   i16      2 * H output weights, side to move half first
+//   i32      output bias
+// A feature is piece * 64 + square, with pieces ordered as in `zobrist::piece_index` from
+// the perspective's point of view (its own pieces first) and squares numbered y * 8 + x,
+// flipped vertically for Black.
+
+const INPUTS: usize = 768;
+const QA: i32 = 255;
+const QB: i32 = 64;
+const SCALE: i32 = 400;
+
+pub(crate) struct Network {
+    hidden: usize,
+    feature_weights: Vec<i16>,
+    hidden_bias: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i32,
+}
+
+// Hidden layer values for the position, from White's and from Black's point of view
+#[derive(Clone)]
+pub(crate) struct Accumulator {
+    white: Vec<i16>,
+    black: Vec<i16>,
+}
+
+impl Network {
+    pub(crate) fn load(path: &str) -> Result<Network, String> {
+        let bytes = fs::read(path).map_err(|e| format!("Could not read {path}: {e}"))?;
+        if bytes.len() < 8 || &bytes[0..4] != b"CNUE" {
+            return Err(format!("{path} is not a network file"));
+        }
+        let hidden = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize;
+        let expected = 8 + 2 * (INPUTS * hidden + hidden + 2 * hidden) + 4;
+        if hidden == 0 || bytes.len() != expected {
+            return Err(format!(
+                "{path} should be {expected} bytes for a hidden layer of {hidden}, found {}",
+                bytes.len()
+            ));
+        }
+
+        let mut offset = 8;
+        let mut read_i16s = |count: usize| -> Vec<i16> {
+            let values = bytes[offset..offset + 2 * count]
+                .chunks_exact(2)
+                .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                .collect();
+            offset += 2 * count;
+            values
+        };
+        let feature_weights = read_i16s(INPUTS * hidden);
+        let hidden_bias = read_i16s(hidden);
+        let output_weights = read_i16s(2 * hidden);
+        let end = bytes.len();
+        let output_bias = i32::from_le_bytes([
+            bytes[end - 4],
+            bytes[end - 3],
+            bytes[end - 2],
+            bytes[end - 1],
+        ]);
+
+        Ok(Network {
+            hidden,
+            feature_weights,
+            hidden_bias,
+            output_weights,
+            output_bias,
+        })
+    }
+
+    fn feature(piece: Piece, square: usize, perspective: Color) -> Option<usize> {
+        let index = zobrist::piece_index(piece)?;
+        if perspective == Color::White {
+            Some(index * 64 + square)
+        } else {
+            // Swap colours and flip the board so Black sees its own pieces first
+            Some(((index + 6) % 12) * 64 + (square ^ 56))
+        }
+    }
+
+    fn add_feature(&self, values: &mut [i16], feature: usize) {
+        let column = &self.feature_weights[feature * self.hidden..(feature + 1) * self.hidden];
+        for (value, weight) in values.iter_mut().zip(column) {
+            *value = value.wrapping_add(*weight);
+        }
+    }
+
+    fn remove_feature(&self, values: &mut [i16], feature: usize) {
+        let column = &self.feature_weights[feature * self.hidden..(feature + 1) * self.hidden];
+        for (value, weight) in values.iter_mut().zip(column) {
+            *value = value.wrapping_sub(*weight);
+        }
+    }
+
+    // Builds the accumulator for a position from scratch
+    pub(crate) fn refresh(&self, board: &Board) -> Accumulator {
+        let mut acc = Accumulator {
+            white: self.hidden_bias.clone(),
+            black: self.hidden_bias.clone(),
+        };
+        for square in 0..64 {
+            let piece = board.board[square / 8][square % 8];
+            if let Some(feature) = Network::feature(piece, square, Color::White) {
+                self.add_feature(&mut acc.white, feature);
+            }
+            if let Some(feature) = Network::feature(piece, square, Color::Black) {
+                self.add_feature(&mut acc.black, feature);
+            }
+        }
+        acc
+    }
+
+    // Adds or removes `piece` on `square` in both halves of the accumulator
+    fn toggle(&self, acc: &mut Accumulator, piece: Piece, square: usize, add: bool) {
+        for (perspective, values) in [
+            (Color::White, &mut acc.white),
+            (Color::Black, &mut acc.black),
+        ] {
+            if let Some(feature) = Network::feature(piece, square, perspective) {
+                if add {
+                    self.add_feature(values, feature);
+                } else {
+                    self.remove_feature(values, feature);
+                }
+            }
+        }
+    }
+
+    // The accumulator after `mv` is made on `before`, given the one for `before`. Only the
+    // pieces the move touches are updated: the piece moved, anything it captures, the
+    // queen a pawn promotes to, the pawn taken en passant and the rook when castling.
+    pub(crate) fn update(&self, acc: &Accumulator, before: &Board, mv: Move) -> Accumulator {
+        let mut next = acc.clone();
+        let square = |pos: Position| (pos.y * 8 + pos.x) as usize;
+        let piece = before.get_piece(mv.start);
+        let captured = before.get_piece(mv.end);
+
+        self.toggle(&mut next, piece, square(mv.start), false);
+        self.toggle(&mut next, captured, square(mv.end), false);
+        let mut placed = piece;
+        if piece.piece_type == PieceType::Pawn {
+            if mv.end.y == 0 || mv.end.y == 7 {
+                placed.piece_type = PieceType::Queen;
+            } else if mv.end.x != mv.start.x && captured.piece_type == PieceType::Empty {
+                let taken = before.en_pessant;
+                self.toggle(&mut next, before.get_piece(taken), square(taken), false);
+            }
+        }
+        self.toggle(&mut next, placed, square(mv.end), true);
+
+        if piece.piece_type == PieceType::King {
+            let rook = if mv.kingside_castlep() {
+                Some((7, 5))
+            } else if mv.queenside_castlep() {
+                Some((0, 3))
+            } else {
+                None
+            };
+            if let Some((from, to)) = rook {
+                let from = Position {
+                    x: from,
+                    y: mv.start.y,
+                };
+                let rook_piece = before.get_piece(from);
+                self.toggle(&mut next, rook_piece, square(from), false);
+                self.toggle(
+                    &mut next,
+                    rook_piece,
+                    square(Position {
+                        x: to,
+                        y: mv.start.y,
+                    }),
+                    true,
+                );
+            }
+        }
+        next
+    }
+
+    // Score in centipawns from `color`'s point of view
+    pub(crate) fn evaluate(&self, acc: &Accumulator, color: Color) -> i16 {
+        let (us, them) = if color == Color::Black {
+            (&acc.black, &acc.white)
+        } else {
+            (&acc.white, &acc.black)
+        };
+        let mut output: i64 = 0;
+        for (value, weight) in us.iter().zip(&self.output_weights[..self.hidden]) {
+            output += ((*value as i32).clamp(0, QA) * *weight as i32) as i64;
+        }
+        for (value, weight) in them.iter().zip(&self.output_weights[self.hidden..]) {
+            output += ((*value as i32).clamp(0, QA) * *weight as i32) as i64;
+        }
+        let score = (output + self.output_bias as i64) * SCALE as i64 / (QA * QB) as i64;
+        score.clamp(-32000, 32000) as i16
+    }
+}
@@ -0,0 +1,115 @@
+use super::{PvLine, AI};
+use crate::model::{board::Board, color::Color, move_::Move};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// Thinking on the opponent's time. After moving, the engine takes the expected reply from
+// its principal variation and searches the position after it on another thread. If the
+// opponent plays that reply (a ponder hit) the search carries on as a normal one with a
+// deadline, otherwise (a miss) it is stopped and its work thrown away.
+
+// Lets another thread stop a search, or give a search without a deadline one
+pub(crate) struct SearchControl {
+    stop: AtomicBool,
+    // None while pondering or analysing without a limit: the search runs until stopped
+    deadline: Mutex<Option<Instant>>,
+}
+
+impl SearchControl {
+    pub(crate) fn new(deadline: Option<Instant>) -> Arc<SearchControl> {
+        Arc::new(SearchControl {
+            stop: AtomicBool::new(false),
+            deadline: Mutex::new(deadline),
+        })
+    }
+
+    pub(crate) fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stoppedp(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_deadline(&self, deadline: Instant) {
+        *self.deadline.lock().unwrap() = Some(deadline);
+    }
+
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        return *self.deadline.lock().unwrap();
+    }
+
+    pub(crate) fn expiredp(&self, now: Instant) -> bool {
+        self.stoppedp() || self.deadline().is_some_and(|deadline| now >= deadline)
+    }
+
+    // A search that finishes early while still unlimited waits here, since the answer may
+    // only be given once the search has been stopped or given a deadline
+    pub(crate) fn wait(&self) {
+        while !self.stoppedp() && self.deadline().is_none() {
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+}
+
+pub(crate) struct Ponder {
+    // The position after the expected reply, with the engine to move
+    board: Board,
+    reply: Move,
+    control: Arc<SearchControl>,
+    handle: Option<JoinHandle<(Vec<PvLine>, u64)>>,
+}
+
+impl Ponder {
+    // Starts searching the position after `reply` is played on `board`, for `color`
+    pub(crate) fn start(engine: &AI, board: Board, reply: Move, color: Color) -> Ponder {
+        let mut position = board;
+        position.make_move(reply);
+        let control = SearchControl::new(None);
+        let mut helper = engine.helper();
+        helper.control = Some(control.clone());
+        let handle = thread::spawn(move || {
+            let lines = helper.analyze(position, color);
+            (lines, helper.pos_evaluated)
+        });
+        Ponder {
+            board: position,
+            reply,
+            control,
+            handle: Some(handle),
+        }
+    }
+
+    pub(crate) fn reply(&self) -> Move {
+        self.reply
+    }
+
+    pub(crate) fn board(&self) -> Board {
+        self.board
+    }
+
+    // The expected reply was played: from now on the search has `move_time` left
+    pub(crate) fn hit(&self, move_time: Duration) {
+        if self.control.deadline().is_none() {
+            self.control.set_deadline(Instant::now() + move_time);
+        }
+    }
+
+    // Waits for the search after a hit, returning its lines and the nodes it searched
+    pub(crate) fn finish(mut self, move_time: Duration) -> (Vec<PvLine>, u64) {
+        self.hit(move_time);
+        return match self.handle.take().map(|handle| handle.join()) {
+            Some(Ok(result)) => result,
+            _ => (Vec::new(), 0),
+        };
+    }
+}
+
+impl Drop for Ponder {
+    // A miss: stop the search and let the thread finish on its own
+    fn drop(&mut self) {
+        self.control.stop();
+    }
+}
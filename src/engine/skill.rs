@@ -0,0 +1,224 @@
+use super::book::random_seed;
+use super::PvLine;
+
+// Weaker play for casual games and training. A level from 0 to 20 combines four
+// handicaps: a depth limit, a node limit, noise added to the evaluation and choosing
+// among the best few root moves instead of always the best. Level 20 is full strength.
+
+pub(crate) const MAX_LEVEL: u8 = 20;
+
+// Ratings of the levels, which UCI_Elo and --elo map onto. Measured with --match: 160
+// games between each pair of levels two apart at 50 ms a move, with the differences added
+// up from level 0 pinned at 800. Each step is good to about 50 either way, 75 for the
+// last. Steps that came out below zero count as none so the ratings never fall, and odd
+// levels were not played and sit halfway between their neighbours. The scale is relative
+// to level 0, not to rated human play.
+//
+//   levels    0-2   2-4   4-6   6-8  8-10 10-12 12-14 14-16 16-18 18-20
+//   Elo        -4   +86   +96  +120   +31    -7   +48   +11    -4  +338
+const LEVEL_ELO: [u16; MAX_LEVEL as usize + 1] = [
+    800, 800, 800, 843, 886, 934, 982, 1042, 1102, 1117, 1132, 1132, 1132, 1156, 1180, 1186, 1191,
+    1191, 1191, 1360, 1529,
+];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct Skill {
+    level: u8,
+}
+
+impl Default for Skill {
+    fn default() -> Skill {
+        Skill { level: MAX_LEVEL }
+    }
+}
+
+impl Skill {
+    pub(crate) fn new(level: u8) -> Result<Skill, String> {
+        if level > MAX_LEVEL {
+            return Err(format!("Skill levels go from 0 to {MAX_LEVEL}"));
+        }
+        Ok(Skill { level })
+    }
+
+    // The strongest level whose measured rating is at or below `elo`, or the weakest level
+    // when none is
+    pub(crate) fn from_elo(elo: u16) -> Skill {
+        let level = LEVEL_ELO
+            .iter()
+            .rposition(|rating| *rating <= elo)
+            .unwrap_or(0);
+        Skill { level: level as u8 }
+    }
+
+    pub(crate) fn level(&self) -> u8 {
+        self.level
+    }
+
+    pub(crate) fn min_elo() -> u16 {
+        LEVEL_ELO[0]
+    }
+
+    pub(crate) fn max_elo() -> u16 {
+        LEVEL_ELO[MAX_LEVEL as usize]
+    }
+
+    pub(crate) fn full_strengthp(&self) -> bool {
+        self.level == MAX_LEVEL
+    }
+
+    // 1 ply at level 0, one more every three levels
+    pub(crate) fn depth(&self) -> Option<u8> {
+        if self.full_strengthp() {
+            return None;
+        }
+        Some(1 + self.level / 3)
+    }
+
+    // Positions searched per move, doubling every two levels
+    pub(crate) fn nodes(&self) -> Option<u64> {
+        if self.full_strengthp() {
+            return None;
+        }
+        Some(1000 << (self.level / 2))
+    }
+
+    // Largest change the noise makes to an evaluation, in centipawns
+    pub(crate) fn noise(&self) -> i16 {
+        (MAX_LEVEL - self.level) as i16 * 10
+    }
+
+    // Root moves to search fully, so there is something to choose from
+    pub(crate) fn candidates(&self) -> usize {
+        if self.full_strengthp() {
+            1
+        } else {
+            4
+        }
+    }
+
+    // How far below the best a chosen move may score, in centipawns
+    fn margin(&self) -> i32 {
+        (MAX_LEVEL - self.level) as i32 * 15
+    }
+
+    // Picks one of `lines`, best first, at random among those within the margin of the
+    // best and favouring the better ones
+    pub(crate) fn pick<'a>(&self, lines: &'a [PvLine]) -> Option<&'a PvLine> {
+        let best = lines.first()?;
+        let margin = self.margin();
+        let weights: Vec<u64> = lines
+            .iter()
+            .map(|line| {
+                let loss = best.score as i32 - line.score as i32;
+                if loss <= margin {
+                    (margin - loss + 1) as u64
+                } else {
+                    0
+                }
+            })
+            .collect();
+        let total: u64 = weights.iter().sum();
+        let mut roll = random_seed() % total;
+        for (line, weight) in lines.iter().zip(&weights) {
+            if roll < *weight {
+                return Some(line);
+            }
+            roll -= weight;
+        }
+        Some(best)
+    }
+
+    // Noise for the position with hash `key`, the same every time within one search
+    pub(crate) fn noise_for(&self, key: u64, seed: u64) -> i16 {
+        let amplitude = self.noise() as u64;
+        if amplitude == 0 {
+            return 0;
+        }
+        let mixed = (key ^ seed).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32;
+        (mixed % (2 * amplitude + 1)) as i16 - amplitude as i16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{move_::Move, position::Position};
+
+    fn line(x: isize, score: i16) -> PvLine {
+        let mv = Move {
+            start: Position { x, y: 1 },
+            end: Position { x, y: 2 },
+        };
+        PvLine {
+            mv,
+            score,
+            depth: 1,
+            pv: vec![mv],
+        }
+    }
+
+    #[test]
+    fn ratings_rise_with_the_level() {
+        assert!(LEVEL_ELO.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(Skill::min_elo() < Skill::max_elo());
+    }
+
+    #[test]
+    fn elo_picks_the_strongest_level_at_or_below() {
+        assert_eq!(Skill::from_elo(0).level(), 0);
+        assert_eq!(Skill::from_elo(Skill::min_elo() - 1).level(), 0);
+        assert_eq!(Skill::from_elo(Skill::max_elo()), Skill::default());
+        assert_eq!(Skill::from_elo(u16::MAX), Skill::default());
+        assert_eq!(Skill::from_elo(Skill::max_elo() - 1).level(), MAX_LEVEL - 1);
+        for level in 0..=MAX_LEVEL {
+            let rating = LEVEL_ELO[level as usize];
+            let skill = Skill::from_elo(rating);
+            // Levels measured as equal give the strongest of them
+            assert_eq!(LEVEL_ELO[skill.level() as usize], rating);
+            assert!(skill.level() >= level);
+        }
+        assert_eq!(Skill::from_elo(900).level(), 4);
+    }
+
+    #[test]
+    fn levels_are_bounded() {
+        assert!(Skill::new(MAX_LEVEL).is_ok());
+        assert!(Skill::new(MAX_LEVEL + 1).is_err());
+        let full = Skill::default();
+        assert!(full.full_strengthp());
+        assert_eq!(full.depth(), None);
+        assert_eq!(full.nodes(), None);
+        assert_eq!(full.candidates(), 1);
+        let weakest = Skill::new(0).unwrap();
+        assert_eq!(weakest.depth(), Some(1));
+        assert_eq!(weakest.nodes(), Some(1000));
+    }
+
+    #[test]
+    fn pick_stays_within_the_margin() {
+        let lines = [line(0, 100), line(1, 90), line(2, -500)];
+        // Full strength always takes the best line
+        for _ in 0..50 {
+            assert_eq!(Skill::default().pick(&lines).unwrap().mv, lines[0].mv);
+        }
+        // A weak level sometimes takes the second line but never the blunder
+        let weak = Skill::new(0).unwrap();
+        let picks: Vec<Move> = (0..200).map(|_| weak.pick(&lines).unwrap().mv).collect();
+        assert!(picks.iter().all(|mv| *mv != lines[2].mv));
+        assert!(picks.contains(&lines[1].mv));
+        assert!(weak.pick(&[]).is_none());
+    }
+
+    #[test]
+    fn noise_is_bounded_and_repeatable() {
+        assert_eq!(Skill::default().noise_for(12345, 678), 0);
+        let weak = Skill::new(0).unwrap();
+        let amplitude = weak.noise();
+        let values: Vec<i16> = (0..1000u64)
+            .map(|key| weak.noise_for(key.wrapping_mul(0x2545_F491_4F6C_DD1D), 42))
+            .collect();
+        assert!(values.iter().all(|v| v.abs() <= amplitude));
+        assert!(values.iter().any(|v| *v > 0) && values.iter().any(|v| *v < 0));
+        assert_eq!(weak.noise_for(7, 42), weak.noise_for(7, 42));
+    }
+}
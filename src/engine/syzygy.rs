@@ -0,0 +1,1072 @@
+use crate::model::{board::Board, color::Color, move_::Move, piece::Piece, piece_type::PieceType};
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+
+// Probing of Syzygy endgame tablebases. WDL files (.rtbw) give win/draw/loss for the side
+// to move, DTZ files (.rtbz) the distance to the next capture or pawn move (zeroing move)
+// on an optimal path. Tables are read from disk the first time a material combination is
+// probed and kept in memory after that.
+//
+// Tables assume no castling rights, and are only correct when the best move is not a
+// capture, so every probe first looks at the captures itself. Promotions are always to a
+// queen since that is all the move generator plays.
+//
+// WDL values: -2 loss, -1 loss saved by the 50 move rule, 0 draw, 1 win spoiled by the
+// 50 move rule, 2 win.
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+const FLAG_STM: u8 = 1;
+const FLAG_MAPPED: u8 = 2;
+const FLAG_WIN_PLIES: u8 = 4;
+const FLAG_LOSS_PLIES: u8 = 8;
+const FLAG_WIDE: u8 = 16;
+const FLAG_SINGLE_VALUE: u8 = 128;
+
+// Squares are numbered file + 8 * rank as in the table files, so a1 = 0 and h8 = 63
+fn file_of(sq: usize) -> usize {
+    sq & 7
+}
+
+fn rank_of(sq: usize) -> usize {
+    sq >> 3
+}
+
+// Negative below the a1-h8 diagonal, zero on it and positive above it
+fn off_diagonal(sq: usize) -> isize {
+    rank_of(sq) as isize - file_of(sq) as isize
+}
+
+// Lookup tables for turning a position into a table index
+struct Indices {
+    binomial: [[u64; 64]; 6],
+    map_pawns: [usize; 64],
+    lead_pawn_idx: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+    // Squares below the diagonal to 0..27
+    map_b1h1h7: [u64; 64],
+    // Squares in the a1-d1-d4 triangle to 0..9, diagonal squares last
+    map_a1d1d4: [usize; 64],
+    // The 462 ways to place two kings with the first one in the triangle
+    map_kk: [[u64; 64]; 10],
+}
+
+fn indices() -> &'static Indices {
+    static INDICES: OnceLock<Indices> = OnceLock::new();
+    INDICES.get_or_init(|| {
+        let mut ind = Indices {
+            binomial: [[0; 64]; 6],
+            map_pawns: [0; 64],
+            lead_pawn_idx: [[0; 64]; 6],
+            lead_pawns_size: [[0; 4]; 6],
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+        };
+
+        let mut code = 0;
+        for sq in 0..64 {
+            if off_diagonal(sq) < 0 {
+                ind.map_b1h1h7[sq] = code;
+                code += 1;
+            }
+        }
+
+        let mut diagonal: Vec<usize> = Vec::new();
+        let mut code = 0;
+        for sq in 0..=27 {
+            if off_diagonal(sq) < 0 && file_of(sq) <= 3 {
+                ind.map_a1d1d4[sq] = code;
+                code += 1;
+            } else if off_diagonal(sq) == 0 && file_of(sq) <= 3 {
+                diagonal.push(sq);
+            }
+        }
+        for sq in diagonal {
+            ind.map_a1d1d4[sq] = code;
+            code += 1;
+        }
+
+        let mut both_on_diagonal: Vec<(usize, usize)> = Vec::new();
+        let mut code = 0;
+        for idx in 0..10 {
+            for s1 in 0..=27 {
+                // b1 is the square mapped to 0, the others at 0 are just unset
+                if ind.map_a1d1d4[s1] != idx || (idx == 0 && s1 != 1) {
+                    continue;
+                }
+                for s2 in 0..64 {
+                    let file_gap = (file_of(s1) as isize - file_of(s2) as isize).abs();
+                    let rank_gap = (rank_of(s1) as isize - rank_of(s2) as isize).abs();
+                    if file_gap <= 1 && rank_gap <= 1 {
+                        continue; // Kings touching
+                    } else if off_diagonal(s1) == 0 && off_diagonal(s2) > 0 {
+                        continue; // First on the diagonal, second above it
+                    } else if off_diagonal(s1) == 0 && off_diagonal(s2) == 0 {
+                        both_on_diagonal.push((idx, s2));
+                    } else {
+                        ind.map_kk[idx][s2] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, s2) in both_on_diagonal {
+            ind.map_kk[idx][s2] = code;
+            code += 1;
+        }
+
+        ind.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..6 {
+                if k > n {
+                    break;
+                }
+                ind.binomial[k][n] = if k > 0 { ind.binomial[k - 1][n - 1] } else { 0 }
+                    + if k < n { ind.binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        // Pawn squares a2-h7 to 0..47, edge files and low ranks highest. The pawn with the
+        // highest value leads and decides which of the four per-file tables is used.
+        let mut available = 47;
+        for lead_count in 1..=5 {
+            for file in 0..4 {
+                let mut idx: u64 = 0;
+                for rank in 1..=6 {
+                    let sq = file + 8 * rank;
+                    if lead_count == 1 {
+                        ind.map_pawns[sq] = available;
+                        available = available.saturating_sub(1);
+                        ind.map_pawns[sq ^ 7] = available;
+                        available = available.saturating_sub(1);
+                    }
+                    ind.lead_pawn_idx[lead_count][sq] = idx;
+                    idx += ind.binomial[lead_count - 1][ind.map_pawns[sq]];
+                }
+                ind.lead_pawns_size[lead_count][file] = idx;
+            }
+        }
+
+        ind
+    })
+}
+
+#[derive(Clone, Default)]
+struct PairsData {
+    flags: u8,
+    pieces: [u8; 8],
+    group_len: [usize; 8],
+    group_idx: [u64; 8],
+    size_of_block: u64,
+    span: u64,
+    sparse_index_size: u64,
+    num_blocks: u64,
+    block_length_size: u64,
+    min_sym_len: u8,
+    // Offsets into the file data
+    lowest_sym: usize,
+    btree: usize,
+    sparse_index: usize,
+    block_length: usize,
+    data: usize,
+    base64: Vec<u64>,
+    symlen: Vec<u8>,
+    map_idx: [u16; 4],
+}
+
+struct Table {
+    bytes: Vec<u8>,
+    wdl: bool,
+    symmetric: bool,
+    piece_count: usize,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    // Pawns of the leading colour first
+    pawn_count: [usize; 2],
+    sides: usize,
+    // Indexed by side, then by file of the leading pawn (only file 0 without pawns)
+    pairs: Vec<Vec<PairsData>>,
+    map: usize,
+}
+
+fn u16_le(bytes: &[u8], at: usize) -> Option<u64> {
+    Some(u16::from_le_bytes([*bytes.get(at)?, *bytes.get(at + 1)?]) as u64)
+}
+
+fn u32_le(bytes: &[u8], at: usize) -> Option<u64> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?) as u64)
+}
+
+fn u32_be(bytes: &[u8], at: usize) -> Option<u64> {
+    Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?) as u64)
+}
+
+fn u64_be(bytes: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(bytes.get(at..at + 8)?.try_into().ok()?))
+}
+
+// Children of a node of the pairing tree, stored as two 12 bit numbers in 3 bytes
+fn tree_left(bytes: &[u8], btree: usize, sym: usize) -> usize {
+    let at = btree + 3 * sym;
+    (((bytes[at + 1] & 0xF) as usize) << 8) | bytes[at] as usize
+}
+
+fn tree_right(bytes: &[u8], btree: usize, sym: usize) -> usize {
+    let at = btree + 3 * sym;
+    ((bytes[at + 2] as usize) << 4) | (bytes[at + 1] >> 4) as usize
+}
+
+impl Table {
+    // `name` is the material of the file, like "KRPvKR", stronger side first
+    fn parse(bytes: Vec<u8>, name: &str, wdl: bool) -> Option<Table> {
+        let magic = if wdl { WDL_MAGIC } else { DTZ_MAGIC };
+        if bytes.len() < 6 || bytes[0..4] != magic {
+            return None;
+        }
+        let (white, black) = name.split_once('v')?;
+        let count = |side: &str, c: char| side.chars().filter(|p| *p == c).count();
+        let white_pawns = count(white, 'P');
+        let black_pawns = count(black, 'P');
+        let has_pawns = white_pawns + black_pawns > 0;
+        let has_unique_pieces = [white, black]
+            .iter()
+            .any(|side| "PNBRQ".chars().any(|c| count(side, c) == 1));
+        // The side with fewer pawns leads, which compresses better
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        let pawn_count = if white_leads {
+            [white_pawns, black_pawns]
+        } else {
+            [black_pawns, white_pawns]
+        };
+        let symmetric = white == black;
+
+        let mut table = Table {
+            bytes,
+            wdl,
+            symmetric,
+            piece_count: white.len() + black.len(),
+            has_pawns,
+            has_unique_pieces,
+            pawn_count,
+            sides: if wdl && !symmetric { 2 } else { 1 },
+            pairs: Vec::new(),
+            map: 0,
+        };
+        if table.piece_count > 7 || (table.bytes[4] & 2 != 0) != has_pawns {
+            return None;
+        }
+
+        let files = if has_pawns { 4 } else { 1 };
+        let both_pawns = has_pawns && pawn_count[1] > 0;
+        table.pairs = vec![vec![PairsData::default(); files]; table.sides];
+        let mut pos = 5;
+
+        for f in 0..files {
+            let first = *table.bytes.get(pos)?;
+            let second = if both_pawns {
+                *table.bytes.get(pos + 1)?
+            } else {
+                0xFF
+            };
+            let order = [[first & 0xF, second & 0xF], [first >> 4, second >> 4]];
+            pos += 1 + both_pawns as usize;
+
+            for k in 0..table.piece_count {
+                let byte = *table.bytes.get(pos)?;
+                for i in 0..table.sides {
+                    table.pairs[i][f].pieces[k] = if i == 1 { byte >> 4 } else { byte & 0xF };
+                }
+                pos += 1;
+            }
+            for (i, side_order) in order.iter().enumerate().take(table.sides) {
+                table.set_groups(i, f, *side_order);
+            }
+        }
+        pos += pos & 1;
+
+        for f in 0..files {
+            for i in 0..table.sides {
+                pos = table.set_sizes(i, f, pos)?;
+            }
+        }
+
+        if !wdl {
+            table.map = pos;
+            for f in 0..files {
+                let flags = table.pairs[0][f].flags;
+                if flags & FLAG_MAPPED == 0 {
+                    continue;
+                }
+                if flags & FLAG_WIDE != 0 {
+                    pos += pos & 1;
+                    for i in 0..4 {
+                        table.pairs[0][f].map_idx[i] = ((pos - table.map) / 2 + 1) as u16;
+                        pos += 2 * u16_le(&table.bytes, pos)? as usize + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        table.pairs[0][f].map_idx[i] = (pos - table.map + 1) as u16;
+                        pos += *table.bytes.get(pos)? as usize + 1;
+                    }
+                }
+            }
+            pos += pos & 1;
+        }
+
+        for f in 0..files {
+            for i in 0..table.sides {
+                table.pairs[i][f].sparse_index = pos;
+                pos += table.pairs[i][f].sparse_index_size as usize * 6;
+            }
+        }
+        for f in 0..files {
+            for i in 0..table.sides {
+                table.pairs[i][f].block_length = pos;
+                pos += table.pairs[i][f].block_length_size as usize * 2;
+            }
+        }
+        for f in 0..files {
+            for i in 0..table.sides {
+                pos = (pos + 0x3F) & !0x3F;
+                table.pairs[i][f].data = pos;
+                let d = &table.pairs[i][f];
+                pos += (d.num_blocks * d.size_of_block) as usize;
+            }
+        }
+        if pos > table.bytes.len() {
+            return None;
+        }
+
+        Some(table)
+    }
+
+    // Splits the pieces into groups of identical pieces and works out the factor each
+    // group's index is multiplied by
+    fn set_groups(&mut self, side: usize, file: usize, order: [u8; 2]) {
+        let ind = indices();
+        let has_pawns = self.has_pawns;
+        let has_unique = self.has_unique_pieces;
+        let piece_count = self.piece_count;
+        let both_pawns = has_pawns && self.pawn_count[1] > 0;
+        let d = &mut self.pairs[side][file];
+
+        let mut first_len: isize = if has_pawns {
+            0
+        } else if has_unique {
+            3
+        } else {
+            2
+        };
+        let mut n = 0;
+        d.group_len[0] = 1;
+        for i in 1..piece_count {
+            first_len -= 1;
+            if first_len > 0 || d.pieces[i] != d.pieces[i - 1] {
+                n += 1;
+                d.group_len[n] = 1;
+            } else {
+                d.group_len[n] += 1;
+            }
+        }
+        n += 1;
+        d.group_len[n] = 0;
+
+        let mut next = if both_pawns { 2 } else { 1 };
+        let mut free_squares = 64 - d.group_len[0] - if both_pawns { d.group_len[1] } else { 0 };
+        let mut idx: u64 = 1;
+        let mut k = 0;
+        while next < n || k == order[0] as usize || k == order[1] as usize {
+            if k == order[0] as usize {
+                d.group_idx[0] = idx;
+                idx *= if has_pawns {
+                    ind.lead_pawns_size[d.group_len[0]][file]
+                } else if has_unique {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] as usize {
+                d.group_idx[1] = idx;
+                idx *= ind.binomial[d.group_len[1]][48 - d.group_len[0]];
+            } else {
+                d.group_idx[next] = idx;
+                idx *= ind.binomial[d.group_len[next]][free_squares];
+                free_squares -= d.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        d.group_idx[n] = idx;
+    }
+
+    // Reads the block and Huffman code layout of one sub-table, returns where the next begins
+    fn set_sizes(&mut self, side: usize, file: usize, mut pos: usize) -> Option<usize> {
+        let bytes = &self.bytes;
+        let d = &mut self.pairs[side][file];
+        d.flags = *bytes.get(pos)?;
+        pos += 1;
+        if d.flags & FLAG_SINGLE_VALUE != 0 {
+            // The one value every position in the table has
+            d.min_sym_len = *bytes.get(pos)?;
+            return Some(pos + 1);
+        }
+
+        let groups = d.group_len.iter().position(|len| *len == 0)?;
+        let tb_size = d.group_idx[groups];
+        d.size_of_block = 1u64 << *bytes.get(pos)?;
+        d.span = 1u64 << *bytes.get(pos + 1)?;
+        d.sparse_index_size = tb_size.div_ceil(d.span);
+        let padding = *bytes.get(pos + 2)? as u64;
+        d.num_blocks = u32_le(bytes, pos + 3)?;
+        d.block_length_size = d.num_blocks + padding;
+        let max_sym_len = *bytes.get(pos + 7)?;
+        d.min_sym_len = *bytes.get(pos + 8)?;
+        pos += 9;
+        if max_sym_len < d.min_sym_len {
+            return None;
+        }
+        d.lowest_sym = pos;
+
+        // Canonical Huffman codes: longer codes have lower values. base64[i] is the
+        // smallest 64 bit left aligned code of length min_sym_len + i.
+        let lengths = (max_sym_len - d.min_sym_len) as usize + 1;
+        d.base64 = vec![0; lengths];
+        for i in (0..lengths.saturating_sub(1)).rev() {
+            let here = u16_le(bytes, d.lowest_sym + 2 * i)? as i64;
+            let next = u16_le(bytes, d.lowest_sym + 2 * (i + 1))? as i64;
+            d.base64[i] = d.base64[i + 1].wrapping_add((here - next) as u64) / 2;
+        }
+        for i in 0..lengths {
+            let shift = 64 - i as u32 - d.min_sym_len as u32;
+            d.base64[i] = d.base64[i].checked_shl(shift).unwrap_or(0);
+        }
+        pos += lengths * 2;
+
+        let symbols = u16_le(bytes, pos)? as usize;
+        pos += 2;
+        d.btree = pos;
+        if bytes.len() < d.btree + 3 * symbols {
+            return None;
+        }
+
+        // Each symbol stands for a run of values, found by expanding the pairing tree
+        d.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for sym in 0..symbols {
+            if !visited[sym] {
+                d.symlen[sym] = symbol_length(bytes, d, sym, &mut visited);
+            }
+        }
+
+        Some(pos + symbols * 3 + (symbols & 1))
+    }
+
+    // The value stored at `idx` in one sub-table
+    fn decompress(&self, d: &PairsData, idx: u64) -> Option<i32> {
+        let bytes = &self.bytes;
+        if d.flags & FLAG_SINGLE_VALUE != 0 {
+            return Some(d.min_sym_len as i32);
+        }
+
+        // The sparse index points near the right block, walk from there
+        let k = (idx / d.span) as usize;
+        let mut block = u32_le(bytes, d.sparse_index + 6 * k)? as usize;
+        let mut offset = u16_le(bytes, d.sparse_index + 6 * k + 4)? as i64;
+        offset += (idx % d.span) as i64 - (d.span / 2) as i64;
+        while offset < 0 {
+            block = block.checked_sub(1)?;
+            offset += u16_le(bytes, d.block_length + 2 * block)? as i64 + 1;
+        }
+        while offset > u16_le(bytes, d.block_length + 2 * block)? as i64 {
+            offset -= u16_le(bytes, d.block_length + 2 * block)? as i64 + 1;
+            block += 1;
+        }
+
+        let mut ptr = d.data + block * d.size_of_block as usize;
+        let mut buf64 = u64_be(bytes, ptr)?;
+        ptr += 8;
+        let mut buf64_size: i32 = 64;
+        let mut sym: usize;
+
+        loop {
+            let mut len = 0;
+            while buf64 < d.base64[len] {
+                len += 1;
+                if len >= d.base64.len() {
+                    return None;
+                }
+            }
+            sym = ((buf64 - d.base64[len]) >> (64 - len as u32 - d.min_sym_len as u32)) as usize;
+            sym += u16_le(bytes, d.lowest_sym + 2 * len)? as usize;
+            let run = *d.symlen.get(sym)? as i64 + 1;
+            if offset < run {
+                break;
+            }
+            offset -= run;
+            let bits = len as i32 + d.min_sym_len as i32;
+            buf64 = buf64.checked_shl(bits as u32).unwrap_or(0);
+            buf64_size -= bits;
+            if buf64_size <= 32 {
+                buf64_size += 32;
+                buf64 |= u32_be(bytes, ptr)? << (64 - buf64_size);
+                ptr += 4;
+            }
+        }
+
+        // Expand the symbol down to the single value we want
+        while d.symlen[sym] != 0 {
+            let left = tree_left(bytes, d.btree, sym);
+            if offset < d.symlen[left] as i64 + 1 {
+                sym = left;
+            } else {
+                offset -= d.symlen[left] as i64 + 1;
+                sym = tree_right(bytes, d.btree, sym);
+            }
+        }
+        Some(tree_left(bytes, d.btree, sym) as i32)
+    }
+}
+
+fn symbol_length(bytes: &[u8], d: &mut PairsData, sym: usize, visited: &mut Vec<bool>) -> u8 {
+    visited[sym] = true;
+    let right = tree_right(bytes, d.btree, sym);
+    if right == 0xFFF {
+        return 0;
+    }
+    let left = tree_left(bytes, d.btree, sym);
+    if left >= d.symlen.len() || right >= d.symlen.len() {
+        return 0;
+    }
+    if !visited[left] {
+        d.symlen[left] = symbol_length(bytes, d, left, visited);
+    }
+    if !visited[right] {
+        d.symlen[right] = symbol_length(bytes, d, right, visited);
+    }
+    d.symlen[left].wrapping_add(d.symlen[right]).wrapping_add(1)
+}
+
+// Piece codes used inside the files: 1-6 for white pawn to king, 9-14 for black
+fn piece_code(piece: Piece) -> u8 {
+    let kind = match piece.piece_type {
+        PieceType::Pawn => 1,
+        PieceType::Knight => 2,
+        PieceType::Bishop => 3,
+        PieceType::Rook => 4,
+        PieceType::Queen => 5,
+        PieceType::King => 6,
+        PieceType::Empty => return 0,
+    };
+    if piece.color == Color::Black {
+        kind + 8
+    } else {
+        kind
+    }
+}
+
+// Material of one side as used in file names, e.g. "KRP"
+pub(crate) fn side_material(board: &Board, color: Color) -> String {
+    let mut name = String::from("K");
+    for (piece_type, letter) in [
+        (PieceType::Queen, 'Q'),
+        (PieceType::Rook, 'R'),
+        (PieceType::Bishop, 'B'),
+        (PieceType::Knight, 'N'),
+        (PieceType::Pawn, 'P'),
+    ] {
+        for piece in board.board.iter().flatten() {
+            if piece.piece_type == piece_type && piece.color == color {
+                name.push(letter);
+            }
+        }
+    }
+    name
+}
+
+pub(crate) fn zeroingp(board: &Board, mv: Move) -> bool {
+    board.capturep(mv) || board.get_piece(mv.start).piece_type == PieceType::Pawn
+}
+
+fn dtz_before_zeroing(wdl: i32) -> i32 {
+    match wdl {
+        2 => 1,
+        1 => 101,
+        -1 => -101,
+        -2 => -1,
+        _ => 0,
+    }
+}
+
+enum TableValue {
+    Value(i32),
+    // A DTZ table only stores one side to move and this is the other one
+    ChangeStm,
+}
+
+pub(crate) struct Tablebase {
+    dirs: Vec<PathBuf>,
+    max_pieces: usize,
+    tables: Mutex<HashMap<String, Option<Arc<Table>>>>,
+}
+
+impl Tablebase {
+    // `path` lists directories with the table files, separated like the PATH variable
+    pub(crate) fn new(path: &str) -> Result<Tablebase, String> {
+        let dirs: Vec<PathBuf> = env::split_paths(&OsString::from(path)).collect();
+        let mut max_pieces = 0;
+        for dir in &dirs {
+            let entries =
+                fs::read_dir(dir).map_err(|e| format!("Could not read {}: {e}", dir.display()))?;
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if let Some(material) = name.strip_suffix(".rtbw") {
+                    let pieces = material.chars().filter(|c| c.is_ascii_uppercase()).count();
+                    max_pieces = max_pieces.max(pieces);
+                }
+            }
+        }
+        if max_pieces == 0 {
+            return Err(format!("No Syzygy tables found in {path}"));
+        }
+        Ok(Tablebase {
+            dirs,
+            max_pieces,
+            tables: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub(crate) fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    // Whether the position is small enough and simple enough to look up
+    pub(crate) fn coveredp(&self, board: &Board) -> bool {
+        let pieces = board
+            .board
+            .iter()
+            .flatten()
+            .filter(|p| p.piece_type != PieceType::Empty)
+            .count();
+        pieces <= self.max_pieces
+            && !board.white_kingside
+            && !board.white_queenside
+            && !board.black_kingside
+            && !board.black_queenside
+    }
+
+    fn table(&self, name: &str, wdl: bool) -> Option<Arc<Table>> {
+        let file = format!("{name}.{}", if wdl { "rtbw" } else { "rtbz" });
+        let mut tables = self.tables.lock().unwrap();
+        if let Some(table) = tables.get(&file) {
+            return table.clone();
+        }
+        let table = self
+            .dirs
+            .iter()
+            .find_map(|dir| fs::read(dir.join(&file)).ok())
+            .and_then(|bytes| Table::parse(bytes, name, wdl))
+            .map(Arc::new);
+        tables.insert(file, table.clone());
+        table
+    }
+
+    // Looks the position up in its table, without looking at captures first
+    fn probe_table(&self, board: &Board, wdl_table: bool, wdl: i32) -> Option<TableValue> {
+        let white = side_material(board, Color::White);
+        let black = side_material(board, Color::Black);
+        if white == "K" && black == "K" {
+            return Some(TableValue::Value(0));
+        }
+        let (table, black_stronger) = match self.table(&format!("{white}v{black}"), wdl_table) {
+            Some(table) => (table, false),
+            None => (self.table(&format!("{black}v{white}"), wdl_table)?, true),
+        };
+        let ind = indices();
+
+        let black_to_move = board.turn == Color::Black;
+        let flip = (table.symmetric && black_to_move) || black_stronger;
+        let flip_color: u8 = if flip { 8 } else { 0 };
+        let flip_squares: usize = if flip { 56 } else { 0 };
+        let stm = (flip ^ black_to_move) as usize;
+
+        let mut on_board: Vec<(usize, u8)> = Vec::with_capacity(8);
+        for sq in 0..64 {
+            let code = piece_code(board.board[sq / 8][sq % 8]);
+            if code != 0 {
+                on_board.push((sq, code));
+            }
+        }
+
+        let mut squares: Vec<usize> = Vec::with_capacity(8);
+        let mut pieces: Vec<u8> = Vec::with_capacity(8);
+        let mut lead_count = 0;
+        let mut tb_file = 0;
+        let mut lead_code = 0;
+
+        if table.has_pawns {
+            // The leading pawns' colour is that of the first piece of the table
+            lead_code = table.pairs[0][0].pieces[0] ^ flip_color;
+            for (sq, code) in &on_board {
+                if *code == lead_code {
+                    squares.push(sq ^ flip_squares);
+                    pieces.push(code ^ flip_color);
+                }
+            }
+            lead_count = squares.len();
+            let lead = (0..lead_count).max_by_key(|i| ind.map_pawns[squares[*i]])?;
+            squares.swap(0, lead);
+            tb_file = file_of(squares[0]).min(7 - file_of(squares[0]));
+        }
+
+        if !wdl_table {
+            let flags = table.pairs[stm % table.sides][tb_file].flags;
+            if (flags & FLAG_STM) as usize != stm && !table.symmetric || table.has_pawns {
+                return Some(TableValue::ChangeStm);
+            }
+        }
+
+        for (sq, code) in &on_board {
+            if table.has_pawns && *code == lead_code {
+                continue;
+            }
+            squares.push(sq ^ flip_squares);
+            pieces.push(code ^ flip_color);
+        }
+        if squares.len() != table.piece_count {
+            return None;
+        }
+        let size = squares.len();
+        let d = &table.pairs[stm % table.sides][tb_file];
+
+        // Put the pieces in the order the table lists them
+        for i in lead_count..size.saturating_sub(1) {
+            for j in (i + 1)..size {
+                if d.pieces[i] == pieces[j] {
+                    pieces.swap(i, j);
+                    squares.swap(i, j);
+                    break;
+                }
+            }
+        }
+
+        // Mirror so the leading piece is on files a-d
+        if file_of(squares[0]) > 3 {
+            for sq in squares.iter_mut() {
+                *sq ^= 7;
+            }
+        }
+
+        let mut idx: u64;
+        if table.has_pawns {
+            idx = ind.lead_pawn_idx[lead_count][squares[0]];
+            squares[1..lead_count].sort_by_key(|sq| ind.map_pawns[*sq]);
+            for (i, sq) in squares.iter().enumerate().take(lead_count).skip(1) {
+                idx += ind.binomial[i][ind.map_pawns[*sq]];
+            }
+        } else {
+            // Without pawns also mirror to ranks 1-4, then into the a1-d1-d4 triangle
+            if rank_of(squares[0]) > 3 {
+                for sq in squares.iter_mut() {
+                    *sq ^= 56;
+                }
+            }
+            for i in 0..d.group_len[0] {
+                if off_diagonal(squares[i]) == 0 {
+                    continue;
+                }
+                if off_diagonal(squares[i]) > 0 {
+                    for sq in squares[i..].iter_mut() {
+                        *sq = ((*sq >> 3) | (*sq << 3)) & 63;
+                    }
+                }
+                break;
+            }
+
+            if table.has_unique_pieces {
+                let (s0, s1, s2) = (squares[0], squares[1], squares[2]);
+                let adjust1 = (s1 > s0) as u64;
+                let adjust2 = (s2 > s0) as u64 + (s2 > s1) as u64;
+                idx = if off_diagonal(s0) != 0 {
+                    (ind.map_a1d1d4[s0] as u64 * 63 + (s1 as u64 - adjust1)) * 62 + s2 as u64
+                        - adjust2
+                } else if off_diagonal(s1) != 0 {
+                    (6 * 63 + rank_of(s0) as u64 * 28 + ind.map_b1h1h7[s1]) * 62 + s2 as u64
+                        - adjust2
+                } else if off_diagonal(s2) != 0 {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + rank_of(s0) as u64 * 7 * 28
+                        + (rank_of(s1) as u64 - adjust1) * 28
+                        + ind.map_b1h1h7[s2]
+                } else {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + rank_of(s0) as u64 * 7 * 6
+                        + (rank_of(s1) as u64 - adjust1) * 6
+                        + (rank_of(s2) as u64 - adjust2)
+                };
+            } else {
+                idx = ind.map_kk[ind.map_a1d1d4[squares[0]]][squares[1]];
+            }
+        }
+
+        // The remaining groups, each as a combination of squares not used before it
+        idx *= d.group_idx[0];
+        let mut group_start = d.group_len[0];
+        let mut remaining_pawns = table.has_pawns && table.pawn_count[1] > 0;
+        let mut next = 1;
+        while d.group_len[next] != 0 {
+            let len = d.group_len[next];
+            squares[group_start..group_start + len].sort();
+            let mut n: u64 = 0;
+            for i in 0..len {
+                let sq = squares[group_start + i];
+                let adjust = squares[..group_start].iter().filter(|s| sq > **s).count();
+                let pawn_shift = if remaining_pawns { 8 } else { 0 };
+                n += ind.binomial[i + 1][sq.checked_sub(adjust + pawn_shift)?];
+            }
+            remaining_pawns = false;
+            idx += n * d.group_idx[next];
+            group_start += len;
+            next += 1;
+        }
+
+        let value = table.decompress(d, idx)?;
+        if table.wdl {
+            return Some(TableValue::Value(value - 2));
+        }
+
+        // DTZ values may be remapped and stored in moves rather than plies
+        let d = &table.pairs[0][tb_file];
+        let mut value = value;
+        if d.flags & FLAG_MAPPED != 0 {
+            let slot = d.map_idx[[1, 3, 0, 2, 0][(wdl + 2) as usize]] as usize;
+            value = if d.flags & FLAG_WIDE != 0 {
+                u16_le(&table.bytes, table.map + 2 * (slot + value as usize))? as i32
+            } else {
+                *table.bytes.get(table.map + slot + value as usize)? as i32
+            };
+        }
+        if (wdl == 2 && d.flags & FLAG_WIN_PLIES == 0)
+            || (wdl == -2 && d.flags & FLAG_LOSS_PLIES == 0)
+            || wdl == 1
+            || wdl == -1
+        {
+            value *= 2;
+        }
+        Some(TableValue::Value(value + 1))
+    }
+
+    // Resolves captures (and with `zeroing` pawn moves too) before trusting the table.
+    // Returns the WDL value and whether the best move is a zeroing move.
+    fn search(&self, board: &Board, zeroing: bool) -> Option<(i32, bool)> {
+        let moves = board.get_all_moves(board.turn);
+        if moves.is_empty() {
+            return Some((if board.in_checkp(board.turn) { -2 } else { 0 }, false));
+        }
+
+        let mut best = -2;
+        let mut searched = 0;
+        for mv in &moves {
+            let pawn = board.get_piece(mv.start).piece_type == PieceType::Pawn;
+            if !board.capturep(*mv) && (!zeroing || !pawn) {
+                continue;
+            }
+            searched += 1;
+            let mut child = *board;
+            child.make_move(*mv);
+            let value = -self.search(&child, false)?.0;
+            if value > best {
+                best = value;
+                if value >= 2 {
+                    return Some((value, true));
+                }
+            }
+        }
+
+        let all_searched = searched == moves.len();
+        let value = if all_searched {
+            best
+        } else {
+            match self.probe_table(board, true, 0)? {
+                TableValue::Value(value) => value,
+                TableValue::ChangeStm => return None,
+            }
+        };
+        // The table holds a "don't care" value when a capture wins
+        if best >= value {
+            return Some((best, best > 0 || all_searched));
+        }
+        Some((value, false))
+    }
+
+    pub(crate) fn probe_wdl(&self, board: &Board) -> Option<i32> {
+        Some(self.search(board, false)?.0)
+    }
+
+    // Plies to the next zeroing move with best play, positive when the side to move wins,
+    // negative when it loses and 0 for draws
+    pub(crate) fn probe_dtz(&self, board: &Board) -> Option<i32> {
+        let (wdl, zeroing_best) = self.search(board, true)?;
+        if wdl == 0 {
+            return Some(0);
+        }
+        if zeroing_best {
+            return Some(dtz_before_zeroing(wdl));
+        }
+        match self.probe_table(board, false, wdl)? {
+            TableValue::Value(dtz) => {
+                let cursed = if wdl == 1 || wdl == -1 { 100 } else { 0 };
+                return Some((dtz + cursed) * wdl.signum());
+            }
+            TableValue::ChangeStm => {}
+        }
+
+        // Only the other side to move is stored, so look one move ahead
+        let mut min_dtz = i32::MAX;
+        for mv in board.get_all_moves(board.turn) {
+            let zeroing = zeroingp(board, mv);
+            let mut child = *board;
+            child.make_move(mv);
+            let mut dtz = if zeroing {
+                -dtz_before_zeroing(self.search(&child, false)?.0)
+            } else {
+                -self.probe_dtz(&child)?
+            };
+            if dtz == 1 && child.checkmatep(child.turn) {
+                min_dtz = 1;
+            }
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz < min_dtz && dtz.signum() == wdl.signum() {
+                min_dtz = dtz;
+            }
+        }
+        Some(if min_dtz == i32::MAX { -1 } else { min_dtz })
+    }
+
+    // The move that wins fastest, or loses slowest, according to the DTZ tables. Returns
+    // None when the position or one of the tables it needs is not available.
+    pub(crate) fn best_move(&self, board: &Board) -> Option<(Move, i32)> {
+        if !self.coveredp(board) {
+            return None;
+        }
+        let mut best: Option<(Move, i32, i64)> = None;
+        for mv in board.get_all_moves(board.turn) {
+            let mut child = *board;
+            child.make_move(mv);
+            let dtz = if child.checkmatep(child.turn) {
+                1
+            } else if zeroingp(board, mv) {
+                dtz_before_zeroing(-self.probe_wdl(&child)?)
+            } else {
+                let dtz = -self.probe_dtz(&child)?;
+                dtz + dtz.signum()
+            };
+            // Quick wins first, then draws, then slow losses
+            let rank: i64 = match dtz.signum() {
+                1 => 1_000_000 - dtz as i64,
+                -1 => -1_000_000 - dtz as i64,
+                _ => 0,
+            };
+            if best.is_none_or(|(_, _, best_rank)| rank > best_rank) {
+                best = Some((mv, dtz, rank));
+            }
+        }
+        best.map(|(mv, dtz, _)| (mv, dtz))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::position::Position;
+
+    // Needs the KRvK and KQvKR files from the standard 3-4-5 piece set in the
+    // directories named by SYZYGY_PATH. Without them there is nothing to check.
+    fn tablebase(files: &[&str]) -> Option<Tablebase> {
+        let path = env::var("SYZYGY_PATH").ok()?;
+        let tablebase = Tablebase::new(&path).ok()?;
+        let present = files
+            .iter()
+            .all(|file| tablebase.dirs.iter().any(|dir| dir.join(file).exists()));
+        if !present {
+            eprintln!("Skipping, {files:?} not found in SYZYGY_PATH");
+            return None;
+        }
+        Some(tablebase)
+    }
+
+    fn board(fen: &str) -> Board {
+        Board::from_fen(fen).unwrap()
+    }
+
+    #[test]
+    fn krvk_wdl() {
+        let Some(tablebase) = tablebase(&["KRvK.rtbw"]) else {
+            return;
+        };
+        assert_eq!(
+            tablebase.probe_wdl(&board("8/8/8/4k3/8/8/8/R3K3 w - - 0 1")),
+            Some(2)
+        );
+        assert_eq!(
+            tablebase.probe_wdl(&board("8/8/8/4k3/8/8/8/R3K3 b - - 0 1")),
+            Some(-2)
+        );
+        // Black takes the rook
+        assert_eq!(
+            tablebase.probe_wdl(&board("8/8/8/8/8/4R3/3k4/K7 b - - 0 1")),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn krvk_mate_in_one() {
+        let Some(tablebase) = tablebase(&["KRvK.rtbw", "KRvK.rtbz"]) else {
+            return;
+        };
+        let position = board("7k/8/6K1/8/8/8/8/R7 w - - 0 1");
+        assert_eq!(tablebase.probe_dtz(&position), Some(1));
+        let mate = Move {
+            start: Position::parse("a1").unwrap(),
+            end: Position::parse("a8").unwrap(),
+        };
+        assert_eq!(tablebase.best_move(&position), Some((mate, 1)));
+    }
+
+    #[test]
+    fn kqvkr_philidor_position() {
+        let Some(tablebase) = tablebase(&["KQvKR.rtbw", "KQvKR.rtbz"]) else {
+            return;
+        };
+        let position = board("1k6/1r6/K7/4Q3/8/8/8/8 b - - 0 1");
+        assert_eq!(tablebase.probe_wdl(&position), Some(-2));
+        assert!(tablebase.probe_dtz(&position).is_some_and(|dtz| dtz < 0));
+        let position = board("1k6/1r6/K7/4Q3/8/8/8/8 w - - 0 1");
+        assert_eq!(tablebase.probe_wdl(&position), Some(2));
+        assert!(tablebase
+            .best_move(&position)
+            .is_some_and(|(_, dtz)| dtz > 0));
+    }
+
+    #[test]
+    fn coverage_needs_few_pieces_and_no_castling() {
+        let Some(tablebase) = tablebase(&["KRvK.rtbw"]) else {
+            return;
+        };
+        assert!(tablebase.coveredp(&board("8/8/8/4k3/8/8/8/R3K3 w - - 0 1")));
+        assert!(!tablebase.coveredp(&board("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1")));
+        assert!(!tablebase.coveredp(&board(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        )));
+    }
+}
@@ -0,0 +1,195 @@
+use crate::engine::eval_params::EvalParams;
+use crate::engine::evaluator::{self, PawnTable};
+use crate::model::{board::Board, color::Color};
+use std::fs;
+use std::thread;
+
+// Texel's tuning method: the evaluation, mapped through a sigmoid, is treated as a
+// prediction of the game result, and the weights are nudged one at a time for as long as
+// that lowers the mean squared error over a set of labeled positions.
+//
+// Positions are read one per line as a FEN/EPD followed somewhere by the game result,
+// written either as 1-0, 0-1 and 1/2-1/2 (plain or in an EPD c9 "..." opcode) or as
+// [1.0], [0.5] and [0.0]. The static evaluation is used directly, so the positions
+// should be quiet ones.
+
+pub(crate) struct LabeledPosition {
+    board: Board,
+    // From White's point of view: 1 for a win, 0.5 for a draw, 0 for a loss
+    result: f64,
+}
+
+pub(crate) fn load_positions(path: &str) -> Result<Vec<LabeledPosition>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Could not read {path}: {e}"))?;
+    let mut positions = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let result = parse_result(line)
+            .ok_or_else(|| format!("Line {}: no game result found", number + 1))?;
+        let board = Board::from_fen(line).map_err(|e| format!("Line {}: {e}", number + 1))?;
+        positions.push(LabeledPosition { board, result });
+    }
+
+    Ok(positions)
+}
+
+fn parse_result(line: &str) -> Option<f64> {
+    // Draws first, "1/2-1/2" must not be mistaken for anything else
+    if line.contains("1/2-1/2") || line.contains("[0.5]") {
+        return Some(0.5);
+    } else if line.contains("1-0") || line.contains("[1.0]") || line.contains("[1]") {
+        return Some(1.0);
+    } else if line.contains("0-1") || line.contains("[0.0]") || line.contains("[0]") {
+        return Some(0.0);
+    }
+    None
+}
+
+fn sigmoid(score: f64, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * score / 400.0))
+}
+
+// Mean squared difference between the results and the sigmoid of the evaluations
+pub(crate) fn evaluation_error(positions: &[LabeledPosition], params: &EvalParams, k: f64) -> f64 {
+    if positions.is_empty() {
+        return 0.0;
+    }
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = positions.len().div_ceil(threads);
+
+    let total: f64 = thread::scope(|scope| {
+        let handles: Vec<_> = positions
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    // A fresh table every time, cached entries belong to the old weights
+                    let mut pawn_table = PawnTable::new();
+                    let mut sum = 0.0;
+                    for position in chunk {
+                        let score = evaluator::evaluate(
+                            position.board,
+                            Color::White,
+                            params,
+                            &mut pawn_table,
+                        ) as f64;
+                        sum += (position.result - sigmoid(score, k)).powi(2);
+                    }
+                    sum
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).sum()
+    });
+
+    total / positions.len() as f64
+}
+
+// The scaling constant that best fits the current evaluation to the results
+fn find_k(positions: &[LabeledPosition], params: &EvalParams) -> f64 {
+    let mut best_k = 1.0;
+    let mut best_error = f64::MAX;
+    let mut k = 0.1;
+    while k <= 3.0 {
+        let error = evaluation_error(positions, params, k);
+        if error < best_error {
+            best_error = error;
+            best_k = k;
+        }
+        k += 0.05;
+    }
+    best_k
+}
+
+fn param_count(params: &mut EvalParams) -> usize {
+    params
+        .fields_mut()
+        .iter()
+        .map(|(_, values)| values.len())
+        .sum()
+}
+
+// Returns the parameter at flat index `index` so it can be read or changed
+fn param(params: &mut EvalParams, mut index: usize) -> (String, &mut i32) {
+    for (name, values) in params.fields_mut() {
+        if index < values.len() {
+            return (format!("{name}[{index}]"), &mut values[index]);
+        }
+        index -= values.len();
+    }
+    panic!("Parameter index out of range");
+}
+
+// Local search over every weight. After each pass the current parameters are written to
+// `output`, so a long run can be stopped at any time.
+pub(crate) fn tune(
+    positions: &[LabeledPosition],
+    mut params: EvalParams,
+    max_passes: u32,
+    output: &str,
+) -> Result<EvalParams, String> {
+    let k = find_k(positions, &params);
+    let mut best_error = evaluation_error(positions, &params, k);
+    let count = param_count(&mut params);
+    println!(
+        "Tuning {count} parameters on {} positions, K = {k:.2}, error {best_error:.6}",
+        positions.len()
+    );
+
+    for pass in 1..=max_passes {
+        let mut improved = 0;
+        for index in 0..count {
+            for step in [1, -1] {
+                *param(&mut params, index).1 += step;
+                let error = evaluation_error(positions, &params, k);
+                if error < best_error {
+                    best_error = error;
+                    improved += 1;
+                    let (name, value) = param(&mut params, index);
+                    println!("  {name} = {value} ({best_error:.6})");
+                    break;
+                }
+                *param(&mut params, index).1 -= step;
+            }
+        }
+
+        println!("Pass {pass}: {improved} parameters changed, error {best_error:.6}");
+        params.save(output)?;
+        if improved == 0 {
+            break;
+        }
+    }
+
+    Ok(params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_pgn_style_results() {
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+        assert_eq!(parse_result(&format!("{fen} \"1-0\";")), Some(1.0));
+        assert_eq!(parse_result(&format!("{fen} \"0-1\";")), Some(0.0));
+        assert_eq!(parse_result(&format!("{fen} \"1/2-1/2\";")), Some(0.5));
+    }
+
+    #[test]
+    fn reads_bracketed_results() {
+        let fen = "8/8/8/8/8/4k3/8/4K3 w - - 0 1";
+        assert_eq!(parse_result(&format!("{fen} [1.0]")), Some(1.0));
+        assert_eq!(parse_result(&format!("{fen} [1]")), Some(1.0));
+        assert_eq!(parse_result(&format!("{fen} [0.5]")), Some(0.5));
+        assert_eq!(parse_result(&format!("{fen} [0.0]")), Some(0.0));
+        assert_eq!(parse_result(&format!("{fen} [0]")), Some(0.0));
+    }
+
+    #[test]
+    fn rejects_lines_without_a_result() {
+        assert_eq!(parse_result("8/8/8/8/8/4k3/8/4K3 w - - 0 1"), None);
+    }
+}
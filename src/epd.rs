@@ -0,0 +1,226 @@
+use crate::engine::{eval_params::EvalParams, nnue::Network, SearchLimits, AI};
+use crate::model::{board::Board, move_::Move, notation};
+use std::fs;
+use std::sync::Arc;
+use std::time::Instant;
+
+// Runs the engine over a suite of test positions in EPD format, such as Win at Chess or
+// the Strategic Test Suite. A position is solved when the engine plays one of its best
+// moves (`bm`) and none of its avoid moves (`am`).
+
+struct TestPosition {
+    id: String,
+    fen: String,
+    best_moves: Vec<String>,
+    avoid_moves: Vec<String>,
+}
+
+// Splits the operations after the four position fields into opcodes and operands.
+// Operands may be quoted strings containing spaces or semicolons.
+fn parse_operations(text: &str) -> Vec<(String, Vec<String>)> {
+    let mut operations: Vec<(String, Vec<String>)> = Vec::new();
+    let mut words: Vec<String> = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '"' => {
+                if quoted {
+                    words.push(word.clone());
+                    word.clear();
+                }
+                quoted = !quoted;
+            }
+            _ if quoted => word.push(c),
+            ' ' | '\t' | ';' => {
+                if !word.is_empty() {
+                    words.push(word.clone());
+                    word.clear();
+                }
+                if c == ';' && !words.is_empty() {
+                    let opcode = words.remove(0);
+                    operations.push((opcode, words.clone()));
+                    words.clear();
+                }
+            }
+            _ => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    if !words.is_empty() {
+        let opcode = words.remove(0);
+        operations.push((opcode, words));
+    }
+    operations
+}
+
+fn parse_line(line: &str, number: usize) -> Result<TestPosition, String> {
+    let fields: Vec<&str> = line.splitn(5, char::is_whitespace).collect();
+    if fields.len() < 4 {
+        return Err(format!("Line {number}: expected a position and operations"));
+    }
+    let mut position = TestPosition {
+        id: format!("position {number}"),
+        fen: fields[..4].join(" "),
+        best_moves: Vec::new(),
+        avoid_moves: Vec::new(),
+    };
+    for (opcode, operands) in parse_operations(fields.get(4).unwrap_or(&"")) {
+        match opcode.as_str() {
+            "id" => position.id = operands.join(" "),
+            "bm" => position.best_moves = operands,
+            "am" => position.avoid_moves = operands,
+            _ => {}
+        }
+    }
+    if position.best_moves.is_empty() && position.avoid_moves.is_empty() {
+        return Err(format!("Line {number}: no bm or am operation"));
+    }
+    Ok(position)
+}
+
+fn parse_moves(board: &Board, moves: &[String]) -> Result<Vec<Move>, String> {
+    moves
+        .iter()
+        .map(|text| notation::parse_move(board, text).map_err(|e| format!("{text}: {e}")))
+        .collect()
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        return format!("\"{}\"", text.replace('"', "\"\""));
+    }
+    text.to_owned()
+}
+
+pub(crate) fn run_suite(
+    path: &str,
+    params: EvalParams,
+    network: Option<Arc<Network>>,
+    limits: SearchLimits,
+    csv_path: Option<&str>,
+) -> Result<(), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Could not read {path}: {e}"))?;
+    let mut positions: Vec<TestPosition> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        positions.push(parse_line(line, i + 1)?);
+    }
+
+    let mut csv = String::from("id,fen,best,avoid,played,result,millis\n");
+    let mut solved = 0;
+    let mut tested = 0;
+    for position in &positions {
+        let board = match Board::from_fen(&position.fen) {
+            Ok(board) => board,
+            Err(e) => {
+                println!("{}: skipped, {e}", position.id);
+                continue;
+            }
+        };
+        let (best, avoid) = match (
+            parse_moves(&board, &position.best_moves),
+            parse_moves(&board, &position.avoid_moves),
+        ) {
+            (Ok(best), Ok(avoid)) => (best, avoid),
+            (Err(e), _) | (_, Err(e)) => {
+                println!("{}: skipped, {e}", position.id);
+                continue;
+            }
+        };
+
+        // A fresh engine for each position so nothing carries over between tests
+        let mut ai = AI::with_params(params.clone());
+        if let Some(network) = &network {
+            ai.set_network(network.clone());
+        }
+        ai.set_limits(limits);
+        ai.set_verbose(false);
+        let start = Instant::now();
+        let played = ai.choose_move(board, board.turn);
+        let millis = start.elapsed().as_millis();
+
+        let passed = (best.is_empty() || best.contains(&played)) && !avoid.contains(&played);
+        tested += 1;
+        if passed {
+            solved += 1;
+        }
+        let played_san = notation::to_san(&board, played);
+        let result = if passed { "pass" } else { "fail" };
+        let mut expected = String::new();
+        if !position.best_moves.is_empty() {
+            expected.push_str(&format!("bm {}", position.best_moves.join(" ")));
+        }
+        if !position.avoid_moves.is_empty() {
+            if !expected.is_empty() {
+                expected.push_str(", ");
+            }
+            expected.push_str(&format!("am {}", position.avoid_moves.join(" ")));
+        }
+        println!(
+            "{}: {result}, played {played_san} ({expected}) in {millis} ms [{solved}/{tested}]",
+            position.id
+        );
+        csv.push_str(&format!(
+            "{},{},{},{},{},{result},{millis}\n",
+            csv_field(&position.id),
+            csv_field(&position.fen),
+            csv_field(&position.best_moves.join(" ")),
+            csv_field(&position.avoid_moves.join(" ")),
+            played_san,
+        ));
+    }
+
+    println!("Solved {solved} of {tested} positions from {path}");
+    if let Some(csv_path) = csv_path {
+        fs::write(csv_path, csv).map_err(|e| format!("Could not write {csv_path}: {e}"))?;
+        println!("Results written to {csv_path}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn operation(opcode: &str, operands: &[&str]) -> (String, Vec<String>) {
+        (
+            opcode.to_owned(),
+            operands.iter().map(|o| o.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn splits_operations() {
+        assert_eq!(
+            parse_operations("bm Nf3 e4; am d4;"),
+            vec![operation("bm", &["Nf3", "e4"]), operation("am", &["d4"])]
+        );
+    }
+
+    #[test]
+    fn keeps_quoted_operands_whole() {
+        assert_eq!(
+            parse_operations(r#"bm Qg6; id "WAC.001; mate in 3"; c0 "two words""#),
+            vec![
+                operation("bm", &["Qg6"]),
+                operation("id", &["WAC.001; mate in 3"]),
+                operation("c0", &["two words"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn last_operation_needs_no_semicolon() {
+        assert_eq!(
+            parse_operations("bm Rxb2"),
+            vec![operation("bm", &["Rxb2"])]
+        );
+        assert_eq!(parse_operations("  "), Vec::new());
+    }
+}
@@ -1,16 +1,581 @@
 use std::env;
+use std::process;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use crate::controller::Controller;
+use crate::engine::book::{self, Book, BookMode};
+use crate::engine::{
+    endgame::EndgameTables, eval_params::EvalParams, mate::MateSolver, nnue::Network, skill::Skill,
+    syzygy::Tablebase, tuner, SearchLimits, AI,
+};
+use crate::match_runner::{EngineConfig, MatchSettings, Sprt};
+use crate::model::{board::Board, color::Color, notation};
+use crate::network::RemotePlayer;
+use crate::render::{Renderer, Theme};
+use crate::saved_game::SavedGame;
 mod controller;
 mod engine;
+mod epd;
+mod match_runner;
 mod model;
+mod network;
 mod player;
+mod render;
+mod saved_game;
 mod starting_board;
+mod tui;
+mod uci;
 mod user_input;
+mod web;
 fn main() {
     env::set_var("RUST_BACKTRACE", "1");
+    let args: Vec<String> = env::args().collect();
+    let mut engine_params = EvalParams::default();
+    let mut eval_params_file: Option<String> = None;
+    let mut engine_network: Option<Arc<Network>> = None;
+    let mut book_file: Option<String> = None;
+    let mut book_mode = BookMode::Weighted;
+    let mut build_book_from: Option<String> = None;
+    let mut book_output = String::from("book.bin");
+    let mut book_plies: usize = 20;
+    let mut tune_file: Option<String> = None;
+    let mut tune_output = String::from("tuned_params.txt");
+    let mut tune_passes: u32 = 100;
+    let mut engine_tablebase: Option<Arc<Tablebase>> = None;
+    let mut generate_endgames: Option<String> = None;
+    let mut endgame_dir: Option<String> = None;
+    let mut match_games: Option<usize> = None;
+    let mut engine1 = String::new();
+    let mut engine2 = String::new();
+    let mut openings_file: Option<String> = None;
+    let mut match_threads: usize = 1;
+    let mut match_pgn = String::from("match.pgn");
+    let mut sprt = Sprt::default();
+    let mut max_plies: usize = 400;
+    let mut epd_file: Option<String> = None;
+    let mut epd_limits = SearchLimits {
+        depth: None,
+        move_time: Duration::from_secs(5),
+    };
+    let mut epd_csv: Option<String> = None;
+    let mut analyze_fen: Option<String> = None;
+    let mut mate_fen: Option<String> = None;
+    let mut human_color = Color::Black;
+    let mut terminal_ui = false;
+    let mut clock = Duration::from_secs(10 * 60);
+    let mut renderer = Renderer::default();
+    let mut ponder = false;
+    let mut engine_skill = Skill::default();
+    let mut uci_mode = false;
+    let mut autosave = Some(String::from("autosave.pgn"));
+    let mut resume_file: Option<String> = None;
+    let mut host_port: Option<u16> = None;
+    let mut connect_address: Option<String> = None;
+    let mut web_port: Option<u16> = None;
+    // Which side is at the bottom of the board, the human's unless chosen
+    let mut orientation: Option<Color> = None;
+    let mut mate_moves: u8 = 3;
+    let mut mate_checks_only = false;
+    let mut multi_pv: usize = 1;
+    let mut analyze_limits = SearchLimits {
+        depth: None,
+        move_time: Duration::from_secs(10),
+    };
 
-    let board = starting_board::make_board();
-    let game = controller::HumanGame {};
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--eval-params" => {
+                i += 1;
+                let path = arg_value(&args, i);
+                engine_params = EvalParams::load(path).unwrap_or_else(|e| exit(&e));
+                eval_params_file = Some(path.to_owned());
+            }
+            "--save-eval-params" => {
+                i += 1;
+                engine_params
+                    .save(arg_value(&args, i))
+                    .unwrap_or_else(|e| exit(&e));
+                return;
+            }
+            "--nnue" => {
+                i += 1;
+                let network = Network::load(arg_value(&args, i)).unwrap_or_else(|e| exit(&e));
+                engine_network = Some(Arc::new(network));
+            }
+            "--book" => {
+                i += 1;
+                book_file = Some(arg_value(&args, i).to_owned());
+            }
+            "--book-mode" => {
+                i += 1;
+                book_mode = match arg_value(&args, i) {
+                    "best" => BookMode::Best,
+                    "weighted" => BookMode::Weighted,
+                    other => exit(&format!("Unknown book mode {other}, use best or weighted")),
+                };
+            }
+            "--build-book" => {
+                i += 1;
+                build_book_from = Some(arg_value(&args, i).to_owned());
+            }
+            "--book-output" => {
+                i += 1;
+                book_output = arg_value(&args, i).to_owned();
+            }
+            "--book-plies" => {
+                i += 1;
+                book_plies = arg_value(&args, i)
+                    .parse()
+                    .unwrap_or_else(|_| exit("--book-plies takes a number"));
+            }
+            "--tune" => {
+                i += 1;
+                tune_file = Some(arg_value(&args, i).to_owned());
+            }
+            "--tune-output" => {
+                i += 1;
+                tune_output = arg_value(&args, i).to_owned();
+            }
+            "--tune-passes" => {
+                i += 1;
+                tune_passes = arg_value(&args, i)
+                    .parse()
+                    .unwrap_or_else(|_| exit("--tune-passes takes a number"));
+            }
+            "--syzygy-path" => {
+                i += 1;
+                let tablebase = Tablebase::new(arg_value(&args, i)).unwrap_or_else(|e| exit(&e));
+                println!("Syzygy tables up to {} pieces", tablebase.max_pieces());
+                engine_tablebase = Some(Arc::new(tablebase));
+            }
+            "--generate-endgames" => {
+                i += 1;
+                generate_endgames = Some(arg_value(&args, i).to_owned());
+            }
+            "--endgame-dir" => {
+                i += 1;
+                endgame_dir = Some(arg_value(&args, i).to_owned());
+            }
+            "--match" => {
+                i += 1;
+                let games = arg_value(&args, i)
+                    .parse()
+                    .unwrap_or_else(|_| exit("--match takes a number of games"));
+                match_games = Some(games);
+            }
+            "--engine1" => {
+                i += 1;
+                engine1 = arg_value(&args, i).to_owned();
+            }
+            "--engine2" => {
+                i += 1;
+                engine2 = arg_value(&args, i).to_owned();
+            }
+            "--openings" => {
+                i += 1;
+                openings_file = Some(arg_value(&args, i).to_owned());
+            }
+            "--match-threads" => {
+                i += 1;
+                match_threads = arg_value(&args, i)
+                    .parse()
+                    .unwrap_or_else(|_| exit("--match-threads takes a number"));
+            }
+            "--match-pgn" => {
+                i += 1;
+                match_pgn = arg_value(&args, i).to_owned();
+            }
+            "--sprt" => {
+                i += 1;
+                let bounds: Vec<f64> = arg_value(&args, i)
+                    .split(',')
+                    .map(|b| b.trim().parse())
+                    .collect::<Result<_, _>>()
+                    .unwrap_or_else(|_| exit("--sprt takes ELO0,ELO1"));
+                if bounds.len() != 2 {
+                    exit("--sprt takes ELO0,ELO1");
+                }
+                sprt.elo0 = bounds[0];
+                sprt.elo1 = bounds[1];
+            }
+            "--max-plies" => {
+                i += 1;
+                max_plies = arg_value(&args, i)
+                    .parse()
+                    .unwrap_or_else(|_| exit("--max-plies takes a number"));
+            }
+            "--epd" => {
+                i += 1;
+                epd_file = Some(arg_value(&args, i).to_owned());
+            }
+            "--epd-depth" => {
+                i += 1;
+                let depth = arg_value(&args, i)
+                    .parse()
+                    .unwrap_or_else(|_| exit("--epd-depth takes a number"));
+                epd_limits.depth = Some(depth);
+            }
+            "--epd-movetime" => {
+                i += 1;
+                let millis = arg_value(&args, i)
+                    .parse()
+                    .unwrap_or_else(|_| exit("--epd-movetime takes milliseconds"));
+                epd_limits.move_time = Duration::from_millis(millis);
+            }
+            "--epd-csv" => {
+                i += 1;
+                epd_csv = Some(arg_value(&args, i).to_owned());
+            }
+            "--analyze" => {
+                i += 1;
+                analyze_fen = Some(arg_value(&args, i).to_owned());
+            }
+            "--multipv" => {
+                i += 1;
+                multi_pv = arg_value(&args, i)
+                    .parse()
+                    .unwrap_or_else(|_| exit("--multipv takes a number of lines"));
+            }
+            "--analyze-depth" => {
+                i += 1;
+                let depth = arg_value(&args, i)
+                    .parse()
+                    .unwrap_or_else(|_| exit("--analyze-depth takes a number"));
+                analyze_limits.depth = Some(depth);
+            }
+            "--analyze-movetime" => {
+                i += 1;
+                let millis = arg_value(&args, i)
+                    .parse()
+                    .unwrap_or_else(|_| exit("--analyze-movetime takes milliseconds"));
+                analyze_limits.move_time = Duration::from_millis(millis);
+            }
+            "--mate" => {
+                i += 1;
+                mate_fen = Some(arg_value(&args, i).to_owned());
+            }
+            "--mate-moves" => {
+                i += 1;
+                mate_moves = arg_value(&args, i)
+                    .parse()
+                    .unwrap_or_else(|_| exit("--mate-moves takes a number"));
+            }
+            "--mate-checks-only" => mate_checks_only = true,
+            "--color" => {
+                i += 1;
+                human_color = match arg_value(&args, i) {
+                    "white" => Color::White,
+                    "black" => Color::Black,
+                    other => exit(&format!("Unknown color {other}, use white or black")),
+                };
+            }
+            "--tui" => terminal_ui = true,
+            "--clock" => {
+                i += 1;
+                let minutes: u64 = arg_value(&args, i)
+                    .parse()
+                    .unwrap_or_else(|_| exit("--clock takes a number of minutes"));
+                clock = Duration::from_secs(minutes * 60);
+            }
+            "--orientation" => {
+                i += 1;
+                orientation = match arg_value(&args, i) {
+                    "white" => Some(Color::White),
+                    "black" => Some(Color::Black),
+                    "auto" => None,
+                    other => exit(&format!(
+                        "Unknown orientation {other}, use white, black or auto"
+                    )),
+                };
+            }
+            "--ascii" => renderer.ascii = true,
+            "--ponder" => ponder = true,
+            "--skill" => {
+                i += 1;
+                let level = arg_value(&args, i)
+                    .parse()
+                    .unwrap_or_else(|_| exit("--skill takes a level from 0 to 20"));
+                engine_skill = Skill::new(level).unwrap_or_else(|e| exit(&e));
+            }
+            "--elo" => {
+                i += 1;
+                let elo = arg_value(&args, i)
+                    .parse()
+                    .unwrap_or_else(|_| exit("--elo takes a rating"));
+                engine_skill = Skill::from_elo(elo);
+            }
+            "--uci" => uci_mode = true,
+            "--autosave" => {
+                i += 1;
+                autosave = Some(arg_value(&args, i).to_owned());
+            }
+            "--no-autosave" => autosave = None,
+            "--host" => {
+                i += 1;
+                let port = arg_value(&args, i)
+                    .parse()
+                    .unwrap_or_else(|_| exit("--host takes a port number"));
+                host_port = Some(port);
+            }
+            "--web" => {
+                i += 1;
+                let port = arg_value(&args, i)
+                    .parse()
+                    .unwrap_or_else(|_| exit("--web takes a port number"));
+                web_port = Some(port);
+            }
+            "--connect" => {
+                i += 1;
+                connect_address = Some(arg_value(&args, i).to_owned());
+            }
+            "--resume" => {
+                i += 1;
+                resume_file = Some(arg_value(&args, i).to_owned());
+            }
+            "--theme" => {
+                i += 1;
+                renderer.theme = Theme::by_name(arg_value(&args, i)).unwrap_or_else(|e| exit(&e));
+            }
+            other => exit(&format!("Unknown option {other}")),
+        }
+        i += 1;
+    }
+
+    if let Some(path) = tune_file {
+        let positions = tuner::load_positions(&path).unwrap_or_else(|e| exit(&e));
+        tuner::tune(&positions, engine_params, tune_passes, &tune_output)
+            .unwrap_or_else(|e| exit(&e));
+        println!("Tuned parameters written to {tune_output}");
+        return;
+    }
+
+    if let Some(path) = build_book_from {
+        let games = book::build_book(&path, &book_output, book_plies).unwrap_or_else(|e| exit(&e));
+        println!("Built {book_output} from {games} games");
+        return;
+    }
+
+    if let Some(fen) = mate_fen {
+        let board = Board::from_fen(&fen).unwrap_or_else(|e| exit(&e));
+        let fullmove = fen
+            .split_whitespace()
+            .nth(5)
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(1);
+        let mut solver = MateSolver::new(mate_checks_only);
+        let start = Instant::now();
+        match solver.solve(&board, mate_moves) {
+            Some(mate) => println!(
+                "Mate in {}: {}",
+                mate.moves,
+                notation::line_to_san(&board, &mate.line, fullmove)
+            ),
+            None if mate_checks_only => {
+                println!("No forced mate in {mate_moves} moves using only checks")
+            }
+            None => println!("No forced mate in {mate_moves} moves"),
+        }
+        println!("{} nodes in {:?}", solver.nodes(), start.elapsed());
+        return;
+    }
+
+    if let Some(fen) = analyze_fen {
+        let board = Board::from_fen(&fen).unwrap_or_else(|e| exit(&e));
+        let fullmove = fen
+            .split_whitespace()
+            .nth(5)
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(1);
+        let mut ai = AI::with_params(engine_params);
+        if let Some(network) = engine_network {
+            ai.set_network(network);
+        }
+        ai.set_limits(analyze_limits);
+        ai.set_multi_pv(multi_pv);
+        let lines = ai.analyze(board, board.turn);
+        for (i, line) in lines.iter().enumerate() {
+            println!(
+                "{}. ({}) {}",
+                i + 1,
+                engine::format_score(line.score),
+                notation::line_to_san(&board, &line.pv, fullmove)
+            );
+        }
+        return;
+    }
+
+    if let Some(path) = epd_file {
+        epd::run_suite(
+            &path,
+            engine_params,
+            engine_network,
+            epd_limits,
+            epd_csv.as_deref(),
+        )
+        .unwrap_or_else(|e| exit(&e));
+        return;
+    }
+
+    if let Some(games) = match_games {
+        let first = EngineConfig::parse(&engine1, "engine1").unwrap_or_else(|e| exit(&e));
+        let second = EngineConfig::parse(&engine2, "engine2").unwrap_or_else(|e| exit(&e));
+        let openings = match openings_file {
+            Some(path) => match_runner::load_openings(&path).unwrap_or_else(|e| exit(&e)),
+            None => Vec::new(),
+        };
+        let settings = MatchSettings {
+            games,
+            openings,
+            threads: match_threads,
+            max_plies,
+            pgn_path: match_pgn,
+            sprt,
+        };
+        match_runner::run_match(&first, &second, &settings).unwrap_or_else(|e| exit(&e));
+        return;
+    }
+
+    if let Some(list) = generate_endgames {
+        let dir = endgame_dir.unwrap_or_else(|| String::from("endgames"));
+        let mut tables = EndgameTables::new();
+        for material in list.split(',') {
+            for name in tables.generate(material).unwrap_or_else(|e| exit(&e)) {
+                let (longest, wins, draws, losses) = tables.summary(&name).unwrap();
+                println!("{name}: {wins} won, {draws} drawn, {losses} lost, longest mate {longest} plies");
+            }
+        }
+        tables.save(&dir).unwrap_or_else(|e| exit(&e));
+        println!("Saved {} tables to {dir}", tables.names().len());
+        return;
+    }
+
+    let engine_endgame_tables = endgame_dir.map(|dir| {
+        let tables = EndgameTables::load(&dir).unwrap_or_else(|e| exit(&e));
+        println!("Loaded endgame tables {}", tables.names().join(", "));
+        Arc::new(tables)
+    });
+
+    let mut board = starting_board::make_board();
+    let resumed = resume_file.map(|path| {
+        let saved = SavedGame::load(&path).unwrap_or_else(|e| exit(&e));
+        if saved.result != "*" {
+            exit(&format!(
+                "The game in {path} is already over, {}",
+                saved.result
+            ));
+        }
+        println!(
+            "Resuming the game from {path} after {} moves",
+            saved.moves.len()
+        );
+        // Keep saving to the file the game came from unless told otherwise
+        if !args
+            .iter()
+            .any(|arg| arg == "--autosave" || arg == "--no-autosave")
+        {
+            autosave = Some(path.clone());
+        }
+        // The engine plays as before, except where the command line says otherwise
+        if !args.iter().any(|arg| arg == "--skill" || arg == "--elo") {
+            engine_skill = saved.skill;
+        }
+        if book_file.is_none() {
+            book_file = saved.book.clone();
+        }
+        if eval_params_file.is_none() {
+            if let Some(path) = &saved.eval_params {
+                engine_params = EvalParams::load(path).unwrap_or_else(|e| exit(&e));
+                eval_params_file = Some(path.clone());
+            }
+        }
+        human_color = saved.human_color;
+        terminal_ui = saved.terminal_ui;
+        clock = saved.clock;
+        ponder = saved.ponder;
+        board = saved.start;
+        saved
+    });
+
+    let engine_book = book_file.as_ref().map(|path| {
+        let book = Book::load(path, book_mode).unwrap_or_else(|e| exit(&e));
+        Arc::new(book)
+    });
+
+    if uci_mode || web_port.is_some() {
+        let mut engine = AI::with_params(engine_params);
+        engine.set_verbose(false);
+        engine.set_skill(engine_skill);
+        if let Some(network) = engine_network {
+            engine.set_network(network);
+        }
+        if let Some(book) = engine_book {
+            engine.set_book(book);
+        }
+        if let Some(tablebase) = engine_tablebase {
+            engine.set_tablebase(tablebase);
+        }
+        if let Some(tables) = engine_endgame_tables {
+            engine.set_endgame_tables(tables);
+        }
+        match web_port {
+            Some(port) => web::serve(port, engine).unwrap_or_else(|e| exit(&e)),
+            None => uci::run(engine),
+        }
+        return;
+    }
+
+    if host_port.is_some() || connect_address.is_some() {
+        let remote = match (host_port, connect_address) {
+            (Some(port), _) => RemotePlayer::host(
+                port,
+                human_color.opponent_color(),
+                starting_board::make_board(),
+                clock,
+            ),
+            (None, Some(address)) => RemotePlayer::connect(&address),
+            (None, None) => unreachable!(),
+        }
+        .unwrap_or_else(|e| exit(&e));
+        let local_color = remote.color().opponent_color();
+        renderer.flipped = orientation.unwrap_or(local_color) == Color::Black;
+        network::play(remote, renderer);
+        return;
+    }
+
+    if !engine_skill.full_strengthp() {
+        println!("The engine plays at skill level {}", engine_skill.level());
+    }
+    renderer.flipped = orientation.unwrap_or(human_color) == Color::Black;
+    let game = controller::HumanGame {
+        engine_params,
+        engine_network,
+        engine_book,
+        engine_tablebase,
+        engine_endgame_tables,
+        human_color,
+        terminal_ui,
+        clock,
+        renderer,
+        ponder,
+        engine_skill,
+        book_file,
+        eval_params_file,
+        autosave,
+        resumed,
+    };
     game.play_game(board);
 }
+
+fn arg_value(args: &[String], i: usize) -> &str {
+    match args.get(i) {
+        Some(value) => value,
+        None => exit(&format!("Missing value for {}", args[i - 1])),
+    }
+}
+
+fn exit(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(1);
+}
@@ -0,0 +1,549 @@
+use crate::engine::{eval_params::EvalParams, nnue::Network, skill::Skill, SearchLimits, AI};
+use crate::model::{board::Board, color::Color, notation, pgn::PgnGame, piece_type::PieceType};
+use crate::player::{Action, GameResult, Player};
+use std::fs::{self, File};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+// Plays engine configurations against each other to measure the difference between them.
+// Every opening is played twice with the colours swapped, games run on several threads,
+// and after each game the score, an Elo estimate and a sequential probability ratio test
+// (SPRT) are printed. The match stops early once the SPRT reaches a verdict.
+
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[derive(Clone)]
+pub(crate) struct EngineConfig {
+    pub(crate) name: String,
+    params: EvalParams,
+    network: Option<Arc<Network>>,
+    limits: SearchLimits,
+    skill: Skill,
+}
+
+impl EngineConfig {
+    // Reads a comma separated list of settings, such as
+    // "name=new,depth=4,movetime=500,params=tuned.txt,nnue=net.bin,skill=10". Time is in
+    // milliseconds and defaults to one second a move. `elo` picks a skill level by its
+    // rough strength instead of `skill`.
+    pub(crate) fn parse(spec: &str, default_name: &str) -> Result<EngineConfig, String> {
+        let mut config = EngineConfig {
+            name: default_name.to_owned(),
+            params: EvalParams::default(),
+            network: None,
+            limits: SearchLimits {
+                depth: None,
+                move_time: Duration::from_millis(1000),
+            },
+            skill: Skill::default(),
+        };
+        for setting in spec.split(',').filter(|s| !s.trim().is_empty()) {
+            let (key, value) = setting
+                .split_once('=')
+                .ok_or_else(|| format!("Engine setting {setting} should look like key=value"))?;
+            match key.trim() {
+                "name" => config.name = value.to_owned(),
+                "depth" => {
+                    let depth = value
+                        .parse()
+                        .map_err(|_| format!("depth takes a number, not {value}"))?;
+                    config.limits.depth = Some(depth);
+                }
+                "movetime" => {
+                    let millis = value
+                        .parse()
+                        .map_err(|_| format!("movetime takes milliseconds, not {value}"))?;
+                    config.limits.move_time = Duration::from_millis(millis);
+                }
+                "params" => config.params = EvalParams::load(value)?,
+                "nnue" => config.network = Some(Arc::new(Network::load(value)?)),
+                "skill" => {
+                    let level = value
+                        .parse()
+                        .map_err(|_| format!("skill takes a level, not {value}"))?;
+                    config.skill = Skill::new(level)?;
+                }
+                "elo" => {
+                    let elo = value
+                        .parse()
+                        .map_err(|_| format!("elo takes a rating, not {value}"))?;
+                    config.skill = Skill::from_elo(elo);
+                }
+                other => return Err(format!("Unknown engine setting {other}")),
+            }
+        }
+        Ok(config)
+    }
+
+    fn build(&self) -> AI {
+        let mut ai = AI::with_params(self.params.clone());
+        if let Some(network) = &self.network {
+            ai.set_network(network.clone());
+        }
+        ai.set_limits(self.limits);
+        ai.set_skill(self.skill);
+        ai.set_verbose(false);
+        ai
+    }
+}
+
+pub(crate) struct MatchSettings {
+    pub(crate) games: usize,
+    pub(crate) openings: Vec<String>,
+    pub(crate) threads: usize,
+    // Games still going after this many plies are drawn
+    pub(crate) max_plies: usize,
+    pub(crate) pgn_path: String,
+    pub(crate) sprt: Sprt,
+}
+
+// Tests H0: elo <= elo0 against H1: elo >= elo1, with error rates alpha and beta
+#[derive(Copy, Clone)]
+pub(crate) struct Sprt {
+    pub(crate) elo0: f64,
+    pub(crate) elo1: f64,
+    pub(crate) alpha: f64,
+    pub(crate) beta: f64,
+}
+
+impl Default for Sprt {
+    fn default() -> Sprt {
+        Sprt {
+            elo0: 0.0,
+            elo1: 5.0,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+}
+
+impl Sprt {
+    fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    // Log likelihood ratio of the results so far, using a normal approximation of the
+    // per game score
+    fn llr(&self, stats: &Stats) -> f64 {
+        let n = stats.games() as f64;
+        if n == 0.0 {
+            return 0.0;
+        }
+        let score = stats.score();
+        let variance = stats.variance();
+        if variance <= 0.0 {
+            return 0.0;
+        }
+        let s0 = expected_score(self.elo0);
+        let s1 = expected_score(self.elo1);
+        n * (s1 - s0) * (2.0 * score - s0 - s1) / (2.0 * variance)
+    }
+
+    fn verdict(&self, stats: &Stats) -> Option<&'static str> {
+        let llr = self.llr(stats);
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            return Some("H1 accepted, the first engine is stronger");
+        } else if llr <= lower {
+            return Some("H0 accepted, the first engine is not stronger");
+        }
+        None
+    }
+}
+
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+fn elo_from_score(score: f64) -> f64 {
+    let score = score.clamp(1e-6, 1.0 - 1e-6);
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+// Results from the first engine's point of view
+#[derive(Default)]
+struct Stats {
+    wins: usize,
+    draws: usize,
+    losses: usize,
+}
+
+impl Stats {
+    fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    fn score(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64
+    }
+
+    fn variance(&self) -> f64 {
+        let n = self.games() as f64;
+        let score = self.score();
+        (self.wins as f64 * (1.0 - score).powi(2)
+            + self.draws as f64 * (0.5 - score).powi(2)
+            + self.losses as f64 * score.powi(2))
+            / n
+    }
+
+    // Elo difference and the half width of its 95% confidence interval
+    fn elo(&self) -> (f64, f64) {
+        let score = self.score();
+        let error = (self.variance() / self.games() as f64).sqrt();
+        let low = elo_from_score(score - 1.96 * error);
+        let high = elo_from_score(score + 1.96 * error);
+        (elo_from_score(score), (high - low) / 2.0)
+    }
+}
+
+struct GameRecord {
+    number: usize,
+    fen: String,
+    white: String,
+    black: String,
+    moves: Vec<String>,
+    result: &'static str,
+    reason: String,
+}
+
+// Neither side can mate: bare kings, or a single minor piece
+fn insufficient_materialp(board: &Board) -> bool {
+    let mut minors = 0;
+    for piece in board.board.iter().flatten() {
+        match piece.piece_type {
+            PieceType::King | PieceType::Empty => {}
+            PieceType::Knight | PieceType::Bishop => minors += 1,
+            _ => return false,
+        }
+    }
+    minors <= 1
+}
+
+fn play_game(
+    number: usize,
+    fen: &str,
+    white: &EngineConfig,
+    black: &EngineConfig,
+    max_plies: usize,
+) -> GameRecord {
+    let mut record = GameRecord {
+        number,
+        fen: fen.to_owned(),
+        white: white.name.clone(),
+        black: black.name.clone(),
+        moves: Vec::new(),
+        result: "1/2-1/2",
+        reason: String::new(),
+    };
+    let mut board = match Board::from_fen(fen) {
+        Ok(board) => board,
+        Err(e) => {
+            record.result = "*";
+            record.reason = e;
+            return record;
+        }
+    };
+    let mut players = [white.build(), black.build()];
+    players[0].new_game(board, Color::White);
+    players[1].new_game(board, Color::Black);
+    let mut history: Vec<u64> = vec![board.hash()];
+    let mut halfmove_clock = 0;
+    // The side whose draw offer is waiting for an answer
+    let mut draw_offer: Option<Color> = None;
+
+    loop {
+        let color = board.turn;
+        let moves = board.get_all_moves(color);
+        if moves.is_empty() {
+            if board.in_checkp(color) {
+                record.result = if color == Color::White { "0-1" } else { "1-0" };
+                record.reason = String::from("checkmate");
+            } else {
+                record.reason = String::from("stalemate");
+            }
+            break;
+        }
+        let key = board.hash();
+        if history.iter().filter(|h| **h == key).count() >= 3 {
+            record.reason = String::from("threefold repetition");
+            break;
+        }
+        if halfmove_clock >= 100 {
+            record.reason = String::from("fifty move rule");
+            break;
+        }
+        if insufficient_materialp(&board) {
+            record.reason = String::from("insufficient material");
+            break;
+        }
+        if record.moves.len() >= max_plies {
+            record.reason = format!("adjudicated after {max_plies} plies");
+            break;
+        }
+
+        let side = if color == Color::White { 0 } else { 1 };
+        let loss = if color == Color::White { "0-1" } else { "1-0" };
+        let action = players[side].take_turn(board, color);
+        let offered = matches!(action, Action::OfferDraw(_));
+        if offered {
+            players[1 - side].draw_offered(board, color.opponent_color());
+        }
+        let opponent_offered = draw_offer == Some(color.opponent_color());
+        draw_offer = if offered { Some(color) } else { None };
+        let mv = match action {
+            Action::Move(mv) | Action::OfferDraw(mv) if moves.contains(&mv) => mv,
+            Action::AcceptDraw if opponent_offered => {
+                record.reason = String::from("draw agreed");
+                break;
+            }
+            Action::Resign => {
+                record.result = loss;
+                record.reason = String::from("resignation");
+                break;
+            }
+            other => {
+                record.result = loss;
+                record.reason = format!("illegal action {other:?}");
+                break;
+            }
+        };
+        let zeroing = board.get_piece(mv.start).piece_type == PieceType::Pawn
+            || board.get_piece(mv.end).color != Color::None;
+        record.moves.push(notation::to_san(&board, mv));
+        players[1 - side].opponent_moved(board, mv);
+        board.make_move(mv);
+        history.push(board.hash());
+        halfmove_clock = if zeroing { 0 } else { halfmove_clock + 1 };
+    }
+    let result = match record.result {
+        "1-0" => GameResult::WhiteWins(record.reason.clone()),
+        "0-1" => GameResult::BlackWins(record.reason.clone()),
+        _ => GameResult::Draw(record.reason.clone()),
+    };
+    for player in players.iter_mut() {
+        player.game_over(&result);
+    }
+    record
+}
+
+impl GameRecord {
+    fn to_pgn(&self) -> PgnGame {
+        let mut game = PgnGame::new();
+        game.tags = vec![
+            (String::from("Event"), String::from("Engine match")),
+            (String::from("Round"), self.number.to_string()),
+            (String::from("White"), self.white.clone()),
+            (String::from("Black"), self.black.clone()),
+            (String::from("Result"), self.result.to_owned()),
+            (String::from("Termination"), self.reason.clone()),
+        ];
+        if self.fen != START_FEN {
+            game.tags.push((String::from("SetUp"), String::from("1")));
+            game.tags.push((String::from("FEN"), self.fen.clone()));
+        }
+        game.moves = self.moves.clone();
+        game.result = self.result.to_owned();
+        game
+    }
+}
+
+// Reads one position per line, FEN or EPD. Blank lines and lines starting with # are
+// skipped.
+pub(crate) fn load_openings(path: &str) -> Result<Vec<String>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Could not read {path}: {e}"))?;
+    let mut openings: Vec<String> = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // EPD operations come after the fourth field
+        let fen = line
+            .split_whitespace()
+            .take(4)
+            .collect::<Vec<&str>>()
+            .join(" ");
+        Board::from_fen(&fen).map_err(|e| format!("{path}: {e}"))?;
+        openings.push(fen);
+    }
+    if openings.is_empty() {
+        return Err(format!("{path} has no positions"));
+    }
+    Ok(openings)
+}
+
+pub(crate) fn run_match(
+    first: &EngineConfig,
+    second: &EngineConfig,
+    settings: &MatchSettings,
+) -> Result<(), String> {
+    let openings: Vec<String> = if settings.openings.is_empty() {
+        vec![START_FEN.to_owned()]
+    } else {
+        settings.openings.clone()
+    };
+    println!(
+        "{} vs {}: {} games on {} threads",
+        first.name, second.name, settings.games, settings.threads
+    );
+
+    let next_game = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel::<GameRecord>();
+    // Each game is added as it ends, so an interrupted match keeps what it played
+    let mut pgn_file = File::create(&settings.pgn_path)
+        .map_err(|e| format!("Could not write {}: {e}", settings.pgn_path))?;
+    let mut stats = Stats::default();
+    let sprt = settings.sprt;
+    let (lower, upper) = sprt.bounds();
+
+    thread::scope(|scope| -> Result<(), String> {
+        for _ in 0..settings.threads.max(1) {
+            let sender = sender.clone();
+            let (next_game, stop, openings) = (&next_game, &stop, &openings);
+            scope.spawn(move || loop {
+                let number = next_game.fetch_add(1, Ordering::SeqCst);
+                if number >= settings.games || stop.load(Ordering::SeqCst) {
+                    return;
+                }
+                // Games 2k and 2k + 1 share an opening with colours swapped
+                let fen = &openings[(number / 2) % openings.len()];
+                let (white, black) = if number % 2 == 0 {
+                    (first, second)
+                } else {
+                    (second, first)
+                };
+                let record = play_game(number + 1, fen, white, black, settings.max_plies);
+                if sender.send(record).is_err() {
+                    return;
+                }
+            });
+        }
+        drop(sender);
+
+        for record in receiver {
+            // Odd numbered games are the ones the first engine plays as white
+            let first_white = record.number % 2 == 1;
+            match (record.result, first_white) {
+                ("1-0", true) | ("0-1", false) => stats.wins += 1,
+                ("0-1", true) | ("1-0", false) => stats.losses += 1,
+                ("1/2-1/2", _) => stats.draws += 1,
+                _ => {}
+            }
+            let (elo, error) = if stats.games() > 0 {
+                stats.elo()
+            } else {
+                (0.0, 0.0)
+            };
+            println!(
+                "Game {} {} - {} {} ({}) | +{} ={} -{} | Elo {elo:.1} +/- {error:.1} | LLR {:.2} ({lower:.2}, {upper:.2})",
+                record.number,
+                record.white,
+                record.black,
+                record.result,
+                record.reason,
+                stats.wins,
+                stats.draws,
+                stats.losses,
+                sprt.llr(&stats),
+            );
+            pgn_file
+                .write_all(record.to_pgn().to_pgn().as_bytes())
+                .and_then(|_| pgn_file.flush())
+                .map_err(|e| format!("Could not write {}: {e}", settings.pgn_path))?;
+            if let Some(verdict) = sprt.verdict(&stats) {
+                if !stop.swap(true, Ordering::SeqCst) {
+                    println!("SPRT: {verdict}, finishing games in progress");
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    println!(
+        "{} vs {}: +{} ={} -{} in {} games",
+        first.name,
+        second.name,
+        stats.wins,
+        stats.draws,
+        stats.losses,
+        stats.games()
+    );
+    if stats.games() > 0 {
+        let (elo, error) = stats.elo();
+        println!("Elo difference {elo:.1} +/- {error:.1}");
+    }
+    let llr = sprt.llr(&stats);
+    println!(
+        "SPRT [{}, {}] LLR {llr:.2} ({lower:.2}, {upper:.2}): {}",
+        sprt.elo0,
+        sprt.elo1,
+        sprt.verdict(&stats).unwrap_or("no verdict yet")
+    );
+    println!("Games saved to {}", settings.pgn_path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(wins: usize, draws: usize, losses: usize) -> Stats {
+        Stats {
+            wins,
+            draws,
+            losses,
+        }
+    }
+
+    fn close(actual: f64, expected: f64) -> bool {
+        (actual - expected).abs() < 0.01
+    }
+
+    #[test]
+    fn elo_with_error_bars() {
+        let (elo, error) = stats(60, 20, 20).elo();
+        assert!(close(elo, 147.19), "{elo}");
+        assert!(close(error, 66.01), "{error}");
+
+        let (elo, error) = stats(10, 0, 10).elo();
+        assert!(close(elo, 0.0), "{elo}");
+        assert!(close(error, 163.32), "{error}");
+
+        // A lopsided score is an Elo difference of the same size either way
+        let (better, _) = stats(30, 10, 10).elo();
+        let (worse, _) = stats(10, 10, 30).elo();
+        assert!(close(better, -worse));
+    }
+
+    #[test]
+    fn sprt_bounds_and_llr() {
+        let sprt = Sprt::default();
+        let (lower, upper) = sprt.bounds();
+        assert!(close(lower, -2.944) && close(upper, 2.944));
+        assert!(close(sprt.llr(&stats(60, 20, 20)), 0.8832));
+        assert!(close(sprt.llr(&stats(30, 40, 30)), -0.0173));
+        // No games, or nothing but draws, say nothing either way
+        assert_eq!(sprt.llr(&stats(0, 0, 0)), 0.0);
+        assert_eq!(sprt.llr(&stats(0, 50, 0)), 0.0);
+    }
+
+    #[test]
+    fn sprt_verdicts() {
+        let sprt = Sprt::default();
+        assert_eq!(sprt.verdict(&stats(60, 20, 20)), None);
+        assert_eq!(
+            sprt.verdict(&stats(300, 100, 100)),
+            Some("H1 accepted, the first engine is stronger")
+        );
+        assert_eq!(
+            sprt.verdict(&stats(100, 100, 300)),
+            Some("H0 accepted, the first engine is not stronger")
+        );
+    }
+}
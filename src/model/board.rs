@@ -1,5 +1,5 @@
 use crate::model::{
-    color::Color, move_::Move, piece::Piece, piece_type::PieceType, position::Position,
+    color::Color, move_::Move, piece::Piece, piece_type::PieceType, position::Position, zobrist,
 };
 use std::fmt;
 
@@ -17,6 +17,10 @@ pub(crate) struct Board {
     pub(crate) black_king: Position,
     pub(crate) white_king: Position,
     pub(crate) en_pessant: Position,
+    // Half moves since the last capture or pawn move, for the fifty move rule
+    pub(crate) halfmove_clock: u16,
+    // Starts at 1 and goes up after each Black move, as in FEN
+    pub(crate) fullmove_number: u16,
 }
 
 impl fmt::Display for Board {
@@ -83,11 +87,32 @@ impl Board {
         if piece.color == Color::None {
             return;
         }
+        self.turn = piece.color.opponent_color();
+        if piece.piece_type == PieceType::Pawn || self.get_piece(new_move.end).color != Color::None
+        {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if piece.color == Color::Black {
+            self.fullmove_number += 1;
+        }
 
         //if !(self.get_all_moves(piece.color).contains(&new_move)) {
         //    return;
         //}
 
+        // Moving a rook or capturing one on its starting square loses that castling right
+        for corner in [new_move.start, new_move.end] {
+            match (corner.x, corner.y) {
+                (0, 0) => self.white_queenside = false,
+                (7, 0) => self.white_kingside = false,
+                (0, 7) => self.black_queenside = false,
+                (7, 7) => self.black_kingside = false,
+                _ => {}
+            }
+        }
+
         self.put_piece(new_move.end, piece);
         self.put_piece(
             new_move.start,
@@ -239,11 +264,8 @@ impl Board {
     fn move_results_in_checkp(&self, mv: Move, color: Color) -> bool {
         let mut board: Board = self.clone();
         board.make_move(mv);
-        return board.checkp(if color == Color::Black {
-            self.black_king
-        } else {
-            self.white_king
-        });
+        // The king may be the piece that moved, so look where it is now
+        board.in_checkp(color)
     }
 
     fn get_bishop_moves(&self, pos: Position, moves: &mut Vec<Move>) -> () {
@@ -336,7 +358,6 @@ impl Board {
     }
 
     fn get_king_moves(&self, pos: Position, moves: &mut Vec<Move>) -> () {
-        let mut board = self.clone();
         let me: Color = self.get_piece(pos).color;
 
         for loc in [
@@ -350,24 +371,14 @@ impl Board {
             pos.down().right(),
         ] {
             if loc.validp() && self.get_piece(loc).color != me {
-                board.make_move(Move {
-                    start: pos,
-                    end: loc,
-                });
-                if !(board.checkp(loc)) {
-                    self.check_and_add_move(
-                        Move {
-                            start: pos,
-                            end: loc,
-                        },
-                        me,
-                        moves,
-                    );
-                }
-                board.make_move(Move {
-                    start: loc,
-                    end: pos,
-                });
+                self.check_and_add_move(
+                    Move {
+                        start: pos,
+                        end: loc,
+                    },
+                    me,
+                    moves,
+                );
             }
         }
 
@@ -592,6 +603,140 @@ impl Board {
         }
     }
 
+    // Zobrist key of the whole position, including side to move, castling rights and the
+    // en passant file. Equal positions in the sense of the repetition rule share a key.
+    pub(crate) fn hash(&self) -> u64 {
+        let mut hash: u64 = 0;
+        for y in 0..8 {
+            for x in 0..8 {
+                if let Some(index) = zobrist::piece_index(self.board[y][x]) {
+                    hash ^= zobrist::PIECE_KEYS[index][y * 8 + x];
+                }
+            }
+        }
+        if self.turn == Color::Black {
+            hash ^= zobrist::BLACK_TO_MOVE_KEY;
+        }
+        for (i, allowed) in [
+            self.white_kingside,
+            self.white_queenside,
+            self.black_kingside,
+            self.black_queenside,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            if allowed {
+                hash ^= zobrist::CASTLE_KEYS[i];
+            }
+        }
+        if self.en_pessant.validp() {
+            hash ^= zobrist::EN_PASSANT_KEYS[self.en_pessant.x as usize];
+        }
+        hash
+    }
+
+    // Zobrist key of the pawns alone, used to look up cached pawn structure evaluations
+    pub(crate) fn pawn_hash(&self) -> u64 {
+        let mut hash: u64 = 0;
+        for y in 0..8 {
+            for x in 0..8 {
+                let piece: Piece = self.board[y][x];
+                if piece.piece_type == PieceType::Pawn {
+                    hash ^= zobrist::PIECE_KEYS[zobrist::piece_index(piece).unwrap()][y * 8 + x];
+                }
+            }
+        }
+        hash
+    }
+
+    // Every square the piece on `pos` attacks, ignoring pins and whether the square is
+    // occupied by a friendly piece. Used by the evaluator for attack and mobility terms.
+    pub(crate) fn attacked_squares(&self, pos: Position) -> Vec<Position> {
+        let piece: Piece = self.get_piece(pos);
+        let mut squares: Vec<Position> = Vec::with_capacity(28);
+
+        let diagonals = [
+            |pos: Position| pos.up().left(),
+            |pos: Position| pos.up().right(),
+            |pos: Position| pos.down().left(),
+            |pos: Position| pos.down().right(),
+        ];
+        let straights = [
+            |pos: Position| pos.up(),
+            |pos: Position| pos.down(),
+            |pos: Position| pos.left(),
+            |pos: Position| pos.right(),
+        ];
+        let slide = |dirs: [fn(Position) -> Position; 4], squares: &mut Vec<Position>| {
+            for dir in dirs {
+                let mut temp: Position = dir(pos);
+                while temp.validp() {
+                    squares.push(temp);
+                    if self.get_piece(temp).color != Color::None {
+                        break;
+                    }
+                    temp = dir(temp);
+                }
+            }
+        };
+
+        match piece.piece_type {
+            PieceType::Bishop => slide(diagonals, &mut squares),
+            PieceType::Rook => slide(straights, &mut squares),
+            PieceType::Queen => {
+                slide(diagonals, &mut squares);
+                slide(straights, &mut squares);
+            }
+            PieceType::Knight => {
+                for p in [
+                    pos.up().up().left(),
+                    pos.up().up().right(),
+                    pos.up().left().left(),
+                    pos.up().right().right(),
+                    pos.down().down().left(),
+                    pos.down().down().right(),
+                    pos.down().left().left(),
+                    pos.down().right().right(),
+                ] {
+                    if p.validp() {
+                        squares.push(p);
+                    }
+                }
+            }
+            PieceType::King => {
+                for p in [
+                    pos.up(),
+                    pos.down(),
+                    pos.left(),
+                    pos.right(),
+                    pos.up().left(),
+                    pos.up().right(),
+                    pos.down().left(),
+                    pos.down().right(),
+                ] {
+                    if p.validp() {
+                        squares.push(p);
+                    }
+                }
+            }
+            PieceType::Pawn => {
+                let ahead = if piece.color == Color::White {
+                    pos.up()
+                } else {
+                    pos.down()
+                };
+                for p in [ahead.left(), ahead.right()] {
+                    if p.validp() {
+                        squares.push(p);
+                    }
+                }
+            }
+            PieceType::Empty => {}
+        }
+        squares
+    }
+
     fn checkp(&self, pos: Position) -> bool {
         let op: Color = self.get_piece(pos).color.opponent_color();
         let mut found_piece: Piece;
@@ -699,9 +844,39 @@ impl Board {
             }
         }
 
+        // Check the enemy king, so kings never stand next to each other
+        for p in [
+            pos.up(),
+            pos.down(),
+            pos.left(),
+            pos.right(),
+            pos.up().left(),
+            pos.up().right(),
+            pos.down().left(),
+            pos.down().right(),
+        ] {
+            if p.validp()
+                && self.get_piece(p)
+                    == (Piece {
+                        piece_type: PieceType::King,
+                        color: op,
+                    })
+            {
+                return true;
+            }
+        }
+
         return false;
     }
 
+    pub(crate) fn in_checkp(&self, color: Color) -> bool {
+        self.checkp(if color == Color::Black {
+            self.black_king
+        } else {
+            self.white_king
+        })
+    }
+
     pub(crate) fn checkmatep(&self, color: Color) -> bool {
         let kp: Position = if color == Color::Black {
             self.black_king
@@ -720,18 +895,313 @@ impl Board {
         return !self.checkp(kp) && self.get_all_moves(color).is_empty();
     }
 
+    // Whether `mv` takes a piece, en passant included
+    pub(crate) fn capturep(&self, mv: Move) -> bool {
+        self.get_piece(mv.end).color != Color::None
+            || (self.get_piece(mv.start).piece_type == PieceType::Pawn && mv.start.x != mv.end.x)
+    }
+
     pub(crate) fn fen_string(&self) -> String {
         let mut piece;
         let mut fen = String::new();
-        for row in 7..=0 {
+        for row in (0..8).rev() {
+            let mut empty = 0;
             for col in 0..8 {
                 piece = self.get_piece(Position { x: col, y: row });
-                if piece.piece_type != PieceType::Empty {
+                if piece.piece_type == PieceType::Empty {
+                    empty += 1;
+                } else {
+                    if empty > 0 {
+                        fen.push_str(&empty.to_string());
+                        empty = 0;
+                    }
                     fen.push_str(&piece.fen_string());
                 }
             }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if row > 0 {
+                fen.push('/');
+            }
         }
+        fen.push(' ');
         fen.push_str(&self.turn.to_string());
+
+        let mut castling = String::new();
+        for (allowed, c) in [
+            (self.white_kingside, 'K'),
+            (self.white_queenside, 'Q'),
+            (self.black_kingside, 'k'),
+            (self.black_queenside, 'q'),
+        ] {
+            if allowed {
+                castling.push(c);
+            }
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        fen.push(' ');
+        fen.push_str(&castling);
+
+        // We store the pawn that just moved two squares, FEN wants the square it skipped
+        fen.push(' ');
+        if self.en_pessant.validp() {
+            let skipped = if self.en_pessant.y == 3 {
+                self.en_pessant.down()
+            } else {
+                self.en_pessant.up()
+            };
+            fen.push_str(&skipped.to_string().to_lowercase());
+        } else {
+            fen.push('-');
+        }
+        fen.push_str(&format!(
+            " {} {}",
+            self.halfmove_clock, self.fullmove_number
+        ));
         return fen;
     }
+
+    // A board with no pieces, no castling rights and White to move
+    pub(crate) fn empty() -> Board {
+        let empty = Piece {
+            piece_type: PieceType::Empty,
+            color: Color::None,
+        };
+        Board {
+            board: [[empty; 8]; 8],
+            turn: Color::White,
+            white_kingside: false,
+            white_queenside: false,
+            black_kingside: false,
+            black_queenside: false,
+            black_king: Position { x: -1, y: -1 },
+            white_king: Position { x: -1, y: -1 },
+            en_pessant: Position { x: -1, y: -1 },
+            halfmove_clock: 0,
+            fullmove_number: 1,
+        }
+    }
+
+    // Sets up a piece, keeping track of where the kings are
+    pub(crate) fn place_piece(&mut self, pos: Position, piece: Piece) {
+        self.put_piece(pos, piece);
+        if piece.piece_type == PieceType::King {
+            if piece.color == Color::White {
+                self.white_king = pos;
+            } else {
+                self.black_king = pos;
+            }
+        }
+    }
+
+    pub(crate) fn from_fen(fen: &str) -> Result<Board, String> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() < 4 {
+            return Err(format!("FEN needs at least 4 fields: {fen}"));
+        }
+
+        let mut board = Board::empty();
+
+        let rows: Vec<&str> = fields[0].split('/').collect();
+        if rows.len() != 8 {
+            return Err(format!("FEN board needs 8 ranks: {}", fields[0]));
+        }
+        for (i, row) in rows.iter().enumerate() {
+            let y = 7 - i as isize;
+            let mut x: isize = 0;
+            for c in row.chars() {
+                if let Some(skip) = c.to_digit(10) {
+                    x += skip as isize;
+                    continue;
+                }
+                let piece_type = match c.to_ascii_lowercase() {
+                    'p' => PieceType::Pawn,
+                    'n' => PieceType::Knight,
+                    'b' => PieceType::Bishop,
+                    'r' => PieceType::Rook,
+                    'q' => PieceType::Queen,
+                    'k' => PieceType::King,
+                    _ => return Err(format!("Unknown piece '{c}' in FEN")),
+                };
+                let color = if c.is_ascii_uppercase() {
+                    Color::White
+                } else {
+                    Color::Black
+                };
+                if x > 7 {
+                    return Err(format!("FEN rank too long: {row}"));
+                }
+                board.place_piece(Position { x, y }, Piece { piece_type, color });
+                x += 1;
+            }
+            if x != 8 {
+                return Err(format!("FEN rank has the wrong length: {row}"));
+            }
+        }
+        if !board.white_king.validp() || !board.black_king.validp() {
+            return Err(format!("FEN is missing a king: {fen}"));
+        }
+
+        board.turn = match fields[1] {
+            "w" => Color::White,
+            "b" => Color::Black,
+            other => return Err(format!("Unknown side to move '{other}' in FEN")),
+        };
+
+        for c in fields[2].chars() {
+            match c {
+                'K' => board.white_kingside = true,
+                'Q' => board.white_queenside = true,
+                'k' => board.black_kingside = true,
+                'q' => board.black_queenside = true,
+                '-' => {}
+                _ => return Err(format!("Unknown castling right '{c}' in FEN")),
+            }
+        }
+
+        if fields[3] != "-" {
+            let skipped = Position::parse(fields[3])
+                .ok_or_else(|| format!("Bad en passant square '{}' in FEN", fields[3]))?;
+            board.en_pessant = if skipped.y == 2 {
+                skipped.up()
+            } else {
+                skipped.down()
+            };
+        }
+
+        // The move counters are optional, as in EPD
+        if let Some(clock) = fields.get(4) {
+            board.halfmove_clock = clock
+                .parse()
+                .map_err(|_| format!("Bad halfmove clock '{clock}' in FEN"))?;
+        }
+        if let Some(number) = fields.get(5) {
+            board.fullmove_number = match number.parse() {
+                Ok(number) if number > 0 => number,
+                _ => return Err(format!("Bad fullmove number '{number}' in FEN")),
+            };
+        }
+
+        Ok(board)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    fn castling_after(fen: &str, from: &str, to: &str) -> String {
+        let mut board = Board::from_fen(fen).unwrap();
+        board.make_move(Move {
+            start: Position::parse(from).unwrap(),
+            end: Position::parse(to).unwrap(),
+        });
+        board.fen_string().split(' ').nth(2).unwrap().to_owned()
+    }
+
+    #[test]
+    fn rook_move_loses_its_castling_right() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        assert_eq!(castling_after(fen, "h1", "h4"), "Qkq");
+        assert_eq!(castling_after(fen, "a1", "b1"), "Kkq");
+    }
+
+    #[test]
+    fn rook_capture_loses_the_captured_rooks_right() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        // Rxa8 loses White's queenside right and Black's
+        assert_eq!(castling_after(fen, "a1", "a8"), "Kk");
+        let fen = "r3k2r/8/8/8/8/8/6b1/R3K2R b KQkq - 0 1";
+        assert_eq!(castling_after(fen, "g2", "h1"), "Qkq");
+    }
+
+    #[test]
+    fn king_move_loses_both_rights() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1";
+        assert_eq!(castling_after(fen, "e8", "e7"), "KQ");
+    }
+
+    #[test]
+    fn make_move_passes_the_turn() {
+        let mut board = Board::from_fen(START_FEN).unwrap();
+        board.make_move(Move {
+            start: Position::parse("g1").unwrap(),
+            end: Position::parse("f3").unwrap(),
+        });
+        assert_eq!(board.turn, Color::Black);
+        board.make_move(Move {
+            start: Position::parse("g8").unwrap(),
+            end: Position::parse("f6").unwrap(),
+        });
+        assert_eq!(board.turn, Color::White);
+    }
+
+    #[test]
+    fn fen_round_trip() {
+        for fen in [
+            START_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "8/8/4k3/8/8/4K3/8/4R3 b - - 37 61",
+        ] {
+            assert_eq!(Board::from_fen(fen).unwrap().fen_string(), fen);
+        }
+    }
+
+    #[test]
+    fn fen_records_double_pawn_push() {
+        let mut board = Board::from_fen(START_FEN).unwrap();
+        board.make_move(Move {
+            start: Position::parse("e2").unwrap(),
+            end: Position::parse("e4").unwrap(),
+        });
+        assert_eq!(
+            board.fen_string(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+    }
+
+    #[test]
+    fn fen_counts_moves() {
+        let mut board = Board::from_fen(START_FEN).unwrap();
+        for (from, to) in [("e2", "e4"), ("e7", "e5"), ("g1", "f3"), ("b8", "c6")] {
+            board.make_move(Move {
+                start: Position::parse(from).unwrap(),
+                end: Position::parse(to).unwrap(),
+            });
+        }
+        assert_eq!(
+            board.fen_string(),
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3"
+        );
+        // A capture resets the halfmove clock
+        board.make_move(Move {
+            start: Position::parse("f3").unwrap(),
+            end: Position::parse("e5").unwrap(),
+        });
+        assert_eq!(board.halfmove_clock, 0);
+        assert_eq!(board.fullmove_number, 3);
+    }
+
+    #[test]
+    fn fen_without_counters_starts_at_move_one() {
+        let board = Board::from_fen("8/8/4k3/8/8/4K3/8/4R3 w - -").unwrap();
+        assert_eq!(board.fen_string(), "8/8/4k3/8/8/4K3/8/4R3 w - - 0 1");
+    }
+
+    #[test]
+    fn from_fen_rejects_bad_input() {
+        assert!(Board::from_fen("8/8/4k3/8/8/4K3/8/4R3 w - - x 1").is_err());
+        assert!(Board::from_fen("8/8/4k3/8/8/4K3/8/4R3 w - - 0 0").is_err());
+        assert!(Board::from_fen("8/8/8/8/8/8/8/8 w - - 0 1").is_err());
+        assert!(Board::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1").is_err());
+        assert!(Board::from_fen("rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -").is_err());
+        assert!(Board::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq -").is_err());
+    }
 }
@@ -16,6 +16,14 @@ impl Color {
         };
     }
 
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Color::White => "White",
+            Color::Black => "Black",
+            Color::None => "",
+        }
+    }
+
     pub(crate) fn opponent_color(&self) -> Color {
         if *self == Color::None {
             return Color::None;
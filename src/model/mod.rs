@@ -1,6 +1,9 @@
 pub(crate) mod board;
 pub(crate) mod color;
 pub(crate) mod move_;
+pub(crate) mod notation;
+pub(crate) mod pgn;
 pub(crate) mod piece;
 pub(crate) mod piece_type;
 pub(crate) mod position;
+pub(crate) mod zobrist;
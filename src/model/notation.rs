@@ -0,0 +1,297 @@
+use crate::model::{
+    board::Board, color::Color, move_::Move, piece::Piece, piece_type::PieceType,
+    position::Position,
+};
+
+// Standard algebraic notation (SAN) for reading and writing moves, e.g. "Nbd7", "exd5",
+// "O-O" or "e8=Q+". Moves are always for the side to move on the board.
+
+fn piece_letter(piece_type: PieceType) -> &'static str {
+    match piece_type {
+        PieceType::Knight => "N",
+        PieceType::Bishop => "B",
+        PieceType::Rook => "R",
+        PieceType::Queen => "Q",
+        PieceType::King => "K",
+        PieceType::Pawn | PieceType::Empty => "",
+    }
+}
+
+fn square_name(pos: Position) -> String {
+    pos.to_string().to_lowercase()
+}
+
+pub(crate) fn to_san(board: &Board, mv: Move) -> String {
+    let piece: Piece = board.get_piece(mv.start);
+    let mut san = String::new();
+
+    if piece.piece_type == PieceType::King && mv.kingside_castlep() {
+        san.push_str("O-O");
+    } else if piece.piece_type == PieceType::King && mv.queenside_castlep() {
+        san.push_str("O-O-O");
+    } else {
+        let capture = board.capturep(mv);
+
+        if piece.piece_type == PieceType::Pawn {
+            if capture {
+                san.push_str(&square_name(mv.start)[..1]);
+            }
+        } else {
+            san.push_str(piece_letter(piece.piece_type));
+            // Name the file, else the rank, else both, of the moving piece when another
+            // piece of the same kind could also go to the destination
+            let others: Vec<Move> = board
+                .get_all_moves(piece.color)
+                .into_iter()
+                .filter(|m| {
+                    m.end == mv.end
+                        && m.start != mv.start
+                        && board.get_piece(m.start).piece_type == piece.piece_type
+                })
+                .collect();
+            if !others.is_empty() {
+                let start = square_name(mv.start);
+                if others.iter().all(|m| m.start.x != mv.start.x) {
+                    san.push_str(&start[..1]);
+                } else if others.iter().all(|m| m.start.y != mv.start.y) {
+                    san.push_str(&start[1..]);
+                } else {
+                    san.push_str(&start);
+                }
+            }
+        }
+
+        if capture {
+            san.push('x');
+        }
+        san.push_str(&square_name(mv.end));
+
+        if piece.piece_type == PieceType::Pawn && (mv.end.y == 0 || mv.end.y == 7) {
+            san.push_str("=Q");
+        }
+    }
+
+    let mut after = *board;
+    after.make_move(mv);
+    let op = piece.color.opponent_color();
+    if after.checkmatep(op) {
+        san.push('#');
+    } else if after.in_checkp(op) {
+        san.push('+');
+    }
+    san
+}
+
+// A sequence of moves from the position in SAN with move numbers, e.g. "12... Nf6 13. e5"
+pub(crate) fn line_to_san(board: &Board, moves: &[Move], fullmove: u32) -> String {
+    let mut position = *board;
+    let mut number = fullmove;
+    let mut text = String::new();
+    for (i, mv) in moves.iter().enumerate() {
+        if !text.is_empty() {
+            text.push(' ');
+        }
+        if position.turn == Color::White {
+            text.push_str(&format!("{number}. "));
+        } else if i == 0 {
+            text.push_str(&format!("{number}... "));
+        }
+        text.push_str(&to_san(&position, *mv));
+        if position.turn == Color::Black {
+            number += 1;
+        }
+        position.make_move(*mv);
+    }
+    text
+}
+
+// Long algebraic notation as used by UCI, e.g. "e2e4", "e1g1" or "e7e8q"
+pub(crate) fn to_long_algebraic(board: &Board, mv: Move) -> String {
+    let mut text = square_name(mv.start) + &square_name(mv.end);
+    if board.get_piece(mv.start).piece_type == PieceType::Pawn && (mv.end.y == 0 || mv.end.y == 7) {
+        text.push('q');
+    }
+    text
+}
+
+// Accepts SAN as well as long algebraic notation ("e2e4", "e2-e4", "e7e8q"). Returns a
+// description of the problem when the text is not a legal move in the position.
+pub(crate) fn parse_move(board: &Board, text: &str) -> Result<Move, String> {
+    let moves = board.get_all_moves(board.turn);
+    let cleaned: String = text
+        .trim()
+        .trim_end_matches(['+', '#', '!', '?'])
+        .chars()
+        .filter(|c| !matches!(c, 'x' | '-' | ':' | '='))
+        .collect();
+    if cleaned.is_empty() {
+        return Err("Enter a move such as e4, Nf3 or e2e4".to_owned());
+    }
+
+    // Castling, in either letter O or digit 0 form
+    let upper = text
+        .trim()
+        .trim_end_matches(['+', '#', '!', '?'])
+        .to_uppercase();
+    if upper == "O-O" || upper == "0-0" || upper == "O-O-O" || upper == "0-0-0" {
+        let kingside = upper.len() == 3;
+        return moves
+            .into_iter()
+            .find(|m| {
+                board.get_piece(m.start).piece_type == PieceType::King
+                    && if kingside {
+                        m.kingside_castlep()
+                    } else {
+                        m.queenside_castlep()
+                    }
+            })
+            .ok_or_else(|| "Castling is not legal here".to_owned());
+    }
+
+    // Promotion suffix: "=Q", "Q" or "q", the "=" is already gone
+    let (body, promotion) = split_promotion(&cleaned);
+    if let Some(promotion) = promotion {
+        if promotion != PieceType::Queen {
+            return Err("Only promotion to a queen is supported".to_owned());
+        }
+    }
+
+    // Long algebraic: two squares
+    if body.len() == 4 && body.is_ascii() {
+        if let (Some(start), Some(end)) = (Position::parse(&body[..2]), Position::parse(&body[2..]))
+        {
+            let mv = Move { start, end };
+            if moves.contains(&mv) {
+                return Ok(mv);
+            }
+            return Err(format!("{text} is not a legal move here"));
+        }
+    }
+
+    let mut chars: Vec<char> = body.chars().collect();
+    let piece_type = match chars.first() {
+        Some('N') => PieceType::Knight,
+        Some('B') => PieceType::Bishop,
+        Some('R') => PieceType::Rook,
+        Some('Q') => PieceType::Queen,
+        Some('K') => PieceType::King,
+        _ => PieceType::Pawn,
+    };
+    if piece_type != PieceType::Pawn {
+        chars.remove(0);
+    }
+    if chars.len() < 2 {
+        return Err(format!("Could not read the move {text}"));
+    }
+    let dest: String = chars[chars.len() - 2..].iter().collect();
+    let end = Position::parse(&dest).ok_or_else(|| format!("{dest} is not a square"))?;
+    let hint: Vec<char> = chars[..chars.len() - 2].to_vec();
+    let mut from_file: Option<isize> = None;
+    let mut from_rank: Option<isize> = None;
+    for c in &hint {
+        match c {
+            'a'..='h' => from_file = Some((*c as u8 - b'a') as isize),
+            '1'..='8' => from_rank = Some((*c as u8 - b'1') as isize),
+            _ => return Err(format!("Could not read the move {text}")),
+        }
+    }
+    if piece_type == PieceType::Pawn && from_file.is_none() {
+        from_file = Some(end.x);
+    }
+
+    let candidates: Vec<Move> = moves
+        .into_iter()
+        .filter(|m| {
+            m.end == end
+                && board.get_piece(m.start).piece_type == piece_type
+                && from_file.is_none_or(|x| m.start.x == x)
+                && from_rank.is_none_or(|y| m.start.y == y)
+        })
+        .collect();
+    match candidates.len() {
+        0 => Err(format!("{text} is not a legal move here")),
+        1 => Ok(candidates[0]),
+        _ => Err(format!("{text} is ambiguous, say which piece moves")),
+    }
+}
+
+fn split_promotion(text: &str) -> (String, Option<PieceType>) {
+    let mut chars: Vec<char> = text.chars().collect();
+    // A trailing piece letter after a rank digit is a promotion ("e8Q", "e7e8q")
+    if chars.len() >= 3 && chars[chars.len() - 2].is_ascii_digit() {
+        let promotion = match chars[chars.len() - 1].to_ascii_uppercase() {
+            'Q' => Some(PieceType::Queen),
+            'R' => Some(PieceType::Rook),
+            'B' => Some(PieceType::Bishop),
+            'N' => Some(PieceType::Knight),
+            _ => None,
+        };
+        if promotion.is_some() {
+            chars.pop();
+            return (chars.into_iter().collect(), promotion);
+        }
+    }
+    (text.to_owned(), None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::starting_board;
+
+    fn mv(start: &str, end: &str) -> Move {
+        Move {
+            start: Position::parse(start).unwrap(),
+            end: Position::parse(end).unwrap(),
+        }
+    }
+
+    #[test]
+    fn parses_san() {
+        let board = starting_board::make_board();
+        assert_eq!(parse_move(&board, "e4"), Ok(mv("e2", "e4")));
+        assert_eq!(parse_move(&board, "Nf3"), Ok(mv("g1", "f3")));
+        assert_eq!(parse_move(&board, "Nc3!?"), Ok(mv("b1", "c3")));
+    }
+
+    #[test]
+    fn parses_long_algebraic() {
+        let board = starting_board::make_board();
+        assert_eq!(parse_move(&board, "e2e4"), Ok(mv("e2", "e4")));
+        assert_eq!(parse_move(&board, "g1-f3"), Ok(mv("g1", "f3")));
+        assert!(parse_move(&board, "e2e5").is_err());
+    }
+
+    #[test]
+    fn parses_captures_castling_and_disambiguation() {
+        let board =
+            Board::from_fen("r3k2r/ppp2ppp/8/3p4/4P3/8/PPPN1PPP/R3K1NR w KQkq - 0 1").unwrap();
+        assert_eq!(parse_move(&board, "exd5"), Ok(mv("e4", "d5")));
+        assert_eq!(parse_move(&board, "Ngf3"), Ok(mv("g1", "f3")));
+        assert_eq!(parse_move(&board, "Ndf3"), Ok(mv("d2", "f3")));
+        assert!(parse_move(&board, "Nf3").is_err());
+        assert_eq!(parse_move(&board, "O-O-O"), Ok(mv("e1", "c1")));
+        assert_eq!(parse_move(&board, "0-0-0"), Ok(mv("e1", "c1")));
+    }
+
+    #[test]
+    fn parses_promotion() {
+        let board = Board::from_fen("8/4P3/8/8/8/8/k7/4K3 w - - 0 1").unwrap();
+        assert_eq!(parse_move(&board, "e8=Q"), Ok(mv("e7", "e8")));
+        assert_eq!(parse_move(&board, "e7e8q"), Ok(mv("e7", "e8")));
+        assert!(parse_move(&board, "e8=N").is_err());
+    }
+
+    #[test]
+    fn writes_san_and_long_algebraic() {
+        let board =
+            Board::from_fen("r3k2r/ppp2ppp/8/3p4/4P3/8/PPPN1PPP/R3K1NR w KQkq - 0 1").unwrap();
+        assert_eq!(to_san(&board, mv("e4", "d5")), "exd5");
+        assert_eq!(to_san(&board, mv("g1", "f3")), "Ngf3");
+        assert_eq!(to_san(&board, mv("e1", "c1")), "O-O-O");
+        assert_eq!(to_long_algebraic(&board, mv("e4", "d5")), "e4d5");
+        let board = Board::from_fen("8/4P3/8/8/8/8/k7/4K3 w - - 0 1").unwrap();
+        assert_eq!(to_san(&board, mv("e7", "e8")), "e8=Q");
+        assert_eq!(to_long_algebraic(&board, mv("e7", "e8")), "e7e8q");
+    }
+}
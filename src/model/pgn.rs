@@ -0,0 +1,175 @@
+use crate::model::{board::Board, color::Color};
+use crate::starting_board;
+
+// Reading and writing games in Portable Game Notation. Comments, variations and annotation
+// glyphs are skipped when reading, only the tags and the main line moves (as SAN) are kept.
+
+pub(crate) struct PgnGame {
+    pub(crate) tags: Vec<(String, String)>,
+    pub(crate) moves: Vec<String>,
+    // "1-0", "0-1", "1/2-1/2" or "*"
+    pub(crate) result: String,
+}
+
+impl PgnGame {
+    pub(crate) fn new() -> PgnGame {
+        PgnGame {
+            tags: Vec::new(),
+            moves: Vec::new(),
+            result: String::from("*"),
+        }
+    }
+
+    pub(crate) fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // The position the game starts from, taken from the FEN tag when there is one
+    pub(crate) fn start_board(&self) -> Result<Board, String> {
+        match self.tag("FEN") {
+            Some(fen) => Board::from_fen(fen),
+            None => Ok(starting_board::make_board()),
+        }
+    }
+
+    // The game as PGN text, with move numbers and lines kept under 80 characters
+    pub(crate) fn to_pgn(&self) -> String {
+        let mut text = String::new();
+        for (name, value) in &self.tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            text.push_str(&format!("[{name} \"{value}\"]\n"));
+        }
+        text.push('\n');
+
+        // Numbered on from the move the start position is at
+        let (black_starts, first_move) = match self.start_board() {
+            Ok(board) => (board.turn == Color::Black, board.fullmove_number as usize),
+            Err(_) => (false, 1),
+        };
+        let mut tokens: Vec<String> = Vec::new();
+        let first_ply = if black_starts { 1 } else { 0 };
+        for (i, san) in self.moves.iter().enumerate() {
+            let ply = first_ply + i;
+            if ply % 2 == 0 {
+                tokens.push(format!("{}.", first_move + ply / 2));
+            } else if i == 0 {
+                tokens.push(format!("{}...", first_move + ply / 2));
+            }
+            tokens.push(san.clone());
+        }
+        tokens.push(self.result.clone());
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > 79 {
+                text.push_str(&line);
+                text.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        text.push_str(&line);
+        text.push_str("\n\n");
+        text
+    }
+}
+
+pub(crate) fn parse_games(text: &str) -> Vec<PgnGame> {
+    let mut games: Vec<PgnGame> = Vec::new();
+    let mut game = PgnGame::new();
+    let mut chars = text.chars().peekable();
+    let mut token = String::new();
+
+    // Called at every token boundary
+    let finish_token = |token: &mut String, game: &mut PgnGame, games: &mut Vec<PgnGame>| {
+        // Move numbers may be glued to the move: "12.e4", "12...e5"
+        let word = match token.as_str() {
+            "1-0" | "0-1" | "1/2-1/2" | "*" => token.as_str(),
+            _ => token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.'),
+        };
+        match word {
+            "" => {}
+            "1-0" | "0-1" | "1/2-1/2" | "*" => {
+                game.result = word.to_owned();
+                games.push(std::mem::replace(game, PgnGame::new()));
+            }
+            _ => game.moves.push(word.to_owned()),
+        }
+        token.clear();
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '[' => {
+                finish_token(&mut token, &mut game, &mut games);
+                // A tag after some moves without a result starts a new game
+                if !game.moves.is_empty() {
+                    games.push(std::mem::replace(&mut game, PgnGame::new()));
+                }
+                let mut tag = String::new();
+                for t in chars.by_ref() {
+                    if t == ']' {
+                        break;
+                    }
+                    tag.push(t);
+                }
+                let name: String = tag.split_whitespace().next().unwrap_or("").to_owned();
+                let value: String = match (tag.find('"'), tag.rfind('"')) {
+                    (Some(start), Some(end)) if end > start => tag[start + 1..end].to_owned(),
+                    _ => String::new(),
+                };
+                game.tags.push((name, value));
+            }
+            '{' => {
+                finish_token(&mut token, &mut game, &mut games);
+                for t in chars.by_ref() {
+                    if t == '}' {
+                        break;
+                    }
+                }
+            }
+            ';' => {
+                finish_token(&mut token, &mut game, &mut games);
+                for t in chars.by_ref() {
+                    if t == '\n' {
+                        break;
+                    }
+                }
+            }
+            '(' => {
+                finish_token(&mut token, &mut game, &mut games);
+                let mut depth = 1;
+                for t in chars.by_ref() {
+                    if t == '(' {
+                        depth += 1;
+                    } else if t == ')' {
+                        depth -= 1;
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                }
+            }
+            '$' => {
+                finish_token(&mut token, &mut game, &mut games);
+                while chars.peek().is_some_and(|d| d.is_ascii_digit()) {
+                    chars.next();
+                }
+            }
+            c if c.is_whitespace() => finish_token(&mut token, &mut game, &mut games),
+            c => token.push(c),
+        }
+    }
+    finish_token(&mut token, &mut game, &mut games);
+    if !game.moves.is_empty() {
+        games.push(game);
+    }
+
+    games
+}
@@ -53,24 +53,17 @@ impl Position {
         return self.x >= 0 && self.x <= 7 && self.y >= 0 && self.y <= 7;
     }
 
-    pub(crate) fn from_string(str: String) -> Position {
-        let x: isize;
-        match str.to_lowercase().chars().nth(0).unwrap() {
-            'a' => x = 0,
-            'b' => x = 1,
-            'c' => x = 2,
-            'd' => x = 3,
-            'e' => x = 4,
-            'f' => x = 5,
-            'g' => x = 6,
-            'h' => x = 7,
-            _ => x = 0,
+    // Reads a square such as "e4", or returns None for anything else
+    pub(crate) fn parse(str: &str) -> Option<Position> {
+        let mut chars = str.chars();
+        let file = chars.next()?.to_ascii_lowercase();
+        let rank = chars.next()?.to_digit(10)?;
+        if chars.next().is_some() || !('a'..='h').contains(&file) || !(1..=8).contains(&rank) {
+            return None;
         }
-
-        let y: isize = (str.chars().nth(1).unwrap().to_digit(10).unwrap() - 1)
-            .try_into()
-            .unwrap();
-
-        Position { x: x, y: y }
+        Some(Position {
+            x: (file as u8 - b'a') as isize,
+            y: rank as isize - 1,
+        })
     }
 }
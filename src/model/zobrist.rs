@@ -0,0 +1,77 @@
+use crate::model::{color::Color, piece::Piece, piece_type::PieceType};
+
+// Random keys for Zobrist hashing, generated at compile time with a fixed seed so hashes
+// are the same from run to run.
+const fn splitmix(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    (state, z ^ (z >> 31))
+}
+
+const fn piece_keys() -> [[u64; 64]; 12] {
+    let mut keys = [[0; 64]; 12];
+    let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+    let mut piece = 0;
+    while piece < 12 {
+        let mut square = 0;
+        while square < 64 {
+            let (next, key) = splitmix(state);
+            state = next;
+            keys[piece][square] = key;
+            square += 1;
+        }
+        piece += 1;
+    }
+    keys
+}
+
+pub(crate) const PIECE_KEYS: [[u64; 64]; 12] = piece_keys();
+
+// Side to move, the four castling rights and the eight en passant files
+const fn state_keys() -> [u64; 13] {
+    let mut keys = [0; 13];
+    let mut state: u64 = 0x6A09_E667_F3BC_C908;
+    let mut i = 0;
+    while i < 13 {
+        let (next, key) = splitmix(state);
+        state = next;
+        keys[i] = key;
+        i += 1;
+    }
+    keys
+}
+
+const STATE_KEYS: [u64; 13] = state_keys();
+pub(crate) const BLACK_TO_MOVE_KEY: u64 = STATE_KEYS[0];
+pub(crate) const CASTLE_KEYS: [u64; 4] =
+    [STATE_KEYS[1], STATE_KEYS[2], STATE_KEYS[3], STATE_KEYS[4]];
+pub(crate) const EN_PASSANT_KEYS: [u64; 8] = [
+    STATE_KEYS[5],
+    STATE_KEYS[6],
+    STATE_KEYS[7],
+    STATE_KEYS[8],
+    STATE_KEYS[9],
+    STATE_KEYS[10],
+    STATE_KEYS[11],
+    STATE_KEYS[12],
+];
+
+// Index of a piece into `PIECE_KEYS`, white pieces first
+pub(crate) fn piece_index(piece: Piece) -> Option<usize> {
+    let kind = match piece.piece_type {
+        PieceType::Pawn => 0,
+        PieceType::Knight => 1,
+        PieceType::Bishop => 2,
+        PieceType::Rook => 3,
+        PieceType::Queen => 4,
+        PieceType::King => 5,
+        PieceType::Empty => return None,
+    };
+    match piece.color {
+        Color::White => Some(kind),
+        Color::Black => Some(kind + 6),
+        Color::None => None,
+    }
+}
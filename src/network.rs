@@ -0,0 +1,614 @@
+use crate::model::{board::Board, color::Color, move_::Move, notation};
+use crate::player::{Action, GameResult, Player};
+use crate::render::Renderer;
+use crate::starting_board;
+use crate::tui::format_clock;
+use crate::user_input::Human;
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Two people playing over a network, one instance hosting and the other connecting. Each
+// instance plays the game between its own player and a `RemotePlayer` standing in for the
+// other, and the two exchange one line of text per message:
+//
+//   hello chess 1                      both sides, whenever a connection is made
+//   newgame <color> <clock ms> <fen>   host to client: the client's side and the start
+//   clock <white ms> <black ms>        time left, sent before the move it goes with
+//   draw                               a draw offer, sent before the move it goes with
+//   move <e2e4>                        a move in long algebraic notation
+//   result <1-0|0-1|1/2-1/2> <reason>  the game ended, by resignation or agreement say
+//   sync <moves>                       after reconnecting, every move played so far
+//
+// When the connection drops the client dials again and the host waits for it. Both then
+// send their moves, and a side that missed some plays them from the other's list.
+
+const HELLO: &str = "hello chess 1";
+// How long a client keeps trying to reconnect
+const RECONNECT_TIME: Duration = Duration::from_secs(60);
+
+enum Role {
+    Host(TcpListener),
+    Client(String),
+}
+
+pub(crate) struct RemotePlayer {
+    role: Role,
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    // The remote player's side
+    color: Color,
+    start: Board,
+    clock: Duration,
+    // Every move of the game, to resync after reconnecting
+    moves: Vec<Move>,
+    // Lines to handle before reading more, such as moves missed while disconnected
+    pending: VecDeque<String>,
+    // Time left as last reported by the other side, White first
+    clocks: Option<[Duration; 2]>,
+    // How the other side says the game ended
+    result: Option<GameResult>,
+    // Whether our draw offer waits for the other side's answer
+    draw_offer_sent: bool,
+}
+
+impl RemotePlayer {
+    // Waits on `port` for the other instance, which plays `remote_color`
+    pub(crate) fn host(
+        port: u16,
+        remote_color: Color,
+        start: Board,
+        clock: Duration,
+    ) -> Result<RemotePlayer, String> {
+        let listener = TcpListener::bind(("0.0.0.0", port))
+            .map_err(|e| format!("Could not listen on port {port}: {e}"))?;
+        println!("Waiting for an opponent on port {port}");
+        RemotePlayer::host_on(listener, remote_color, start, clock)
+    }
+
+    // Like `host`, on a listener that is already bound
+    fn host_on(
+        listener: TcpListener,
+        remote_color: Color,
+        start: Board,
+        clock: Duration,
+    ) -> Result<RemotePlayer, String> {
+        let (stream, address) = listener
+            .accept()
+            .map_err(|e| format!("Could not accept a connection: {e}"))?;
+        println!("{address} connected");
+        let mut remote =
+            RemotePlayer::new(Role::Host(listener), stream, remote_color, start, clock)?;
+        remote.handshake()?;
+        let line = format!(
+            "newgame {} {} {}",
+            remote_color.name().to_lowercase(),
+            clock.as_millis(),
+            start.fen_string()
+        );
+        remote.send(&line)?;
+        Ok(remote)
+    }
+
+    // Joins the game hosted at `address`, taking the side and clock the host chose
+    pub(crate) fn connect(address: &str) -> Result<RemotePlayer, String> {
+        let stream = TcpStream::connect(address)
+            .map_err(|e| format!("Could not connect to {address}: {e}"))?;
+        let mut remote = RemotePlayer::new(
+            Role::Client(address.to_owned()),
+            stream,
+            Color::White,
+            starting_board::make_board(),
+            Duration::ZERO,
+        )?;
+        remote.handshake()?;
+        let line = remote.read_raw().ok_or("The host closed the connection")?;
+        let words: Vec<&str> = line.splitn(4, ' ').collect();
+        match words.as_slice() {
+            ["newgame", color, clock, fen] => {
+                // The host sends the side this instance plays
+                remote.color = match *color {
+                    "white" => Color::Black,
+                    "black" => Color::White,
+                    _ => return Err(format!("Unknown color {color} from the host")),
+                };
+                let millis: u64 = clock.parse().map_err(|_| "Bad clock from the host")?;
+                remote.clock = Duration::from_millis(millis);
+                remote.start = Board::from_fen(fen)?;
+            }
+            _ => return Err(format!("Expected a new game from the host, got {line}")),
+        }
+        Ok(remote)
+    }
+
+    fn new(
+        role: Role,
+        stream: TcpStream,
+        color: Color,
+        start: Board,
+        clock: Duration,
+    ) -> Result<RemotePlayer, String> {
+        let reader = stream.try_clone().map_err(|e| e.to_string())?;
+        Ok(RemotePlayer {
+            role,
+            reader: BufReader::new(reader),
+            writer: stream,
+            color,
+            start,
+            clock,
+            moves: Vec::new(),
+            pending: VecDeque::new(),
+            clocks: None,
+            result: None,
+            draw_offer_sent: false,
+        })
+    }
+
+    pub(crate) fn color(&self) -> Color {
+        self.color
+    }
+
+    pub(crate) fn start(&self) -> Board {
+        self.start
+    }
+
+    pub(crate) fn clock(&self) -> Duration {
+        self.clock
+    }
+
+    pub(crate) fn clocks(&self) -> Option<[Duration; 2]> {
+        self.clocks
+    }
+
+    pub(crate) fn result(&self) -> Option<GameResult> {
+        self.result.clone()
+    }
+
+    fn handshake(&mut self) -> Result<(), String> {
+        self.send(HELLO)?;
+        match self.read_raw() {
+            Some(line) if line == HELLO => Ok(()),
+            Some(line) => Err(format!("Not a chess instance on the other side: {line}")),
+            None => Err(String::from("The connection closed during the handshake")),
+        }
+    }
+
+    fn send(&mut self, line: &str) -> Result<(), String> {
+        writeln!(self.writer, "{line}")
+            .and_then(|_| self.writer.flush())
+            .map_err(|e| format!("Could not send to the opponent: {e}"))
+    }
+
+    // Sends `line`, reconnecting if needed. Moves are part of the resync, so they only
+    // need sending again when `resend` is set.
+    fn transmit(&mut self, line: &str, resend: bool) {
+        if self.send(line).is_err() && self.reconnect() && resend {
+            self.send(line).ok();
+        }
+    }
+
+    fn read_raw(&mut self) -> Option<String> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.trim().to_owned()),
+        }
+    }
+
+    // The next line from the other side, reconnecting when the connection drops. None
+    // when the other side could not be reached again.
+    fn receive(&mut self) -> Option<String> {
+        loop {
+            if let Some(line) = self.pending.pop_front() {
+                return Some(line);
+            }
+            match self.read_raw() {
+                Some(line) => return Some(line),
+                None if self.reconnect() => {}
+                None => return None,
+            }
+        }
+    }
+
+    fn reconnect(&mut self) -> bool {
+        println!("Lost the connection to the opponent, reconnecting...");
+        let stream = match &self.role {
+            Role::Host(listener) => listener.accept().ok().map(|(stream, _)| stream),
+            Role::Client(address) => {
+                let deadline = Instant::now() + RECONNECT_TIME;
+                loop {
+                    match TcpStream::connect(address) {
+                        Ok(stream) => break Some(stream),
+                        Err(_) if Instant::now() < deadline => {
+                            thread::sleep(Duration::from_secs(1))
+                        }
+                        Err(_) => break None,
+                    }
+                }
+            }
+        };
+        let stream = match stream.and_then(|stream| Some((stream.try_clone().ok()?, stream))) {
+            Some(streams) => streams,
+            None => {
+                println!("Could not reconnect");
+                return false;
+            }
+        };
+        self.reader = BufReader::new(stream.0);
+        self.writer = stream.1;
+        if let Err(e) = self.handshake() {
+            println!("{e}");
+            return false;
+        }
+
+        let ours = self.move_list();
+        if self.send(&format!("sync {}", ours.join(" "))).is_err() {
+            return false;
+        }
+        let theirs: Vec<String> = match self.read_raw() {
+            Some(line) if line.starts_with("sync") => {
+                line.split_whitespace().skip(1).map(String::from).collect()
+            }
+            _ => {
+                println!("The opponent did not resync");
+                return false;
+            }
+        };
+        if theirs.starts_with(&ours) {
+            for text in &theirs[ours.len()..] {
+                self.pending.push_back(format!("move {text}"));
+            }
+        } else if !ours.starts_with(&theirs) {
+            println!("The two games no longer match");
+            return false;
+        }
+        println!("Reconnected");
+        true
+    }
+
+    fn move_list(&self) -> Vec<String> {
+        let mut board = self.start;
+        let mut list: Vec<String> = Vec::new();
+        for mv in &self.moves {
+            list.push(notation::to_long_algebraic(&board, *mv));
+            board.make_move(*mv);
+        }
+        list
+    }
+}
+
+impl Player for RemotePlayer {
+    fn take_turn(&mut self, board: Board, color: Color) -> Action {
+        // A draw offer arrives just before the move that goes with it
+        let mut offered = false;
+        loop {
+            let line = match self.receive() {
+                Some(line) => line,
+                None => {
+                    self.result = Some(GameResult::win_for(color.opponent_color(), "abandonment"));
+                    return Action::Resign;
+                }
+            };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["move", text] => match notation::parse_move(&board, text) {
+                    Ok(mv) => {
+                        // Moving declines our offer
+                        self.draw_offer_sent = false;
+                        self.moves.push(mv);
+                        return if offered {
+                            Action::OfferDraw(mv)
+                        } else {
+                            Action::Move(mv)
+                        };
+                    }
+                    Err(e) => {
+                        println!("The opponent sent {text}: {e}");
+                        self.result =
+                            Some(GameResult::win_for(color.opponent_color(), "illegal move"));
+                        return Action::Resign;
+                    }
+                },
+                ["draw"] => offered = true,
+                ["clock", white, black] => {
+                    if let (Ok(white), Ok(black)) = (white.parse(), black.parse()) {
+                        self.clocks =
+                            Some([Duration::from_millis(white), Duration::from_millis(black)]);
+                    }
+                }
+                ["result", result, reason @ ..] => {
+                    let reason = reason.join(" ");
+                    let result = match *result {
+                        "1-0" => GameResult::WhiteWins(reason),
+                        "0-1" => GameResult::BlackWins(reason),
+                        _ => GameResult::Draw(reason),
+                    };
+                    // A draw only ends the game when it answers our offer
+                    let action = match result {
+                        GameResult::Draw(_) if self.draw_offer_sent => Action::AcceptDraw,
+                        GameResult::Draw(_) => {
+                            println!("The opponent left with a draw that was not offered");
+                            self.result =
+                                Some(GameResult::win_for(color.opponent_color(), "abandonment"));
+                            return Action::Resign;
+                        }
+                        _ => Action::Resign,
+                    };
+                    self.result = Some(result);
+                    return action;
+                }
+                // Lines from newer versions are skipped
+                _ => {}
+            }
+        }
+    }
+
+    fn draw_offered(&mut self, _board: Board, _color: Color) {
+        self.draw_offer_sent = true;
+        self.transmit("draw", true);
+    }
+
+    fn opponent_moved(&mut self, board: Board, mv: Move) {
+        self.moves.push(mv);
+        let line = format!("move {}", notation::to_long_algebraic(&board, mv));
+        self.transmit(&line, false);
+    }
+
+    fn time_update(&mut self, own: Duration, opponent: Duration) {
+        let (white, black) = if self.color == Color::White {
+            (own, opponent)
+        } else {
+            (opponent, own)
+        };
+        let line = format!("clock {} {}", white.as_millis(), black.as_millis());
+        self.transmit(&line, true);
+    }
+
+    // The game is over either way, so there is no reconnecting for this
+    fn game_over(&mut self, result: &GameResult) {
+        let reason = match result {
+            GameResult::WhiteWins(reason)
+            | GameResult::BlackWins(reason)
+            | GameResult::Draw(reason) => reason,
+        };
+        self.send(&format!("result {} {reason}", result.pgn())).ok();
+    }
+}
+
+// Plays the game between the person at this terminal and the one on the other side
+pub(crate) fn play(mut remote: RemotePlayer, renderer: Renderer) {
+    let mut human = Human::new(renderer, None);
+    let remote_color = remote.color();
+    let local_color = remote_color.opponent_color();
+    let mut board = remote.start();
+    let mut clocks = [remote.clock(), remote.clock()];
+    let mut history: Vec<u64> = vec![board.hash()];
+    // The side whose draw offer is waiting for an answer
+    let mut draw_offer: Option<Color> = None;
+    human.new_game(board, local_color);
+    remote.new_game(board, remote_color);
+    println!("You play {}", local_color.name());
+
+    let result = loop {
+        let color = board.turn;
+        let side = if color == Color::White { 0 } else { 1 };
+        let key = board.hash();
+        if board.checkmatep(color) {
+            break GameResult::win_for(color.opponent_color(), "checkmate");
+        } else if board.stalematep(color) {
+            break GameResult::Draw(String::from("stalemate"));
+        } else if history.iter().filter(|h| **h == key).count() >= 3 {
+            break GameResult::Draw(String::from("threefold repetition"));
+        } else if clocks[side].is_zero() {
+            break GameResult::win_for(color.opponent_color(), "time");
+        }
+
+        let start = Instant::now();
+        let mut offered = false;
+        let mv = if color == local_color {
+            println!(
+                "White {}  Black {}",
+                format_clock(clocks[0]),
+                format_clock(clocks[1])
+            );
+            if draw_offer == Some(remote_color) {
+                human.draw_offered(board, local_color);
+            }
+            let action = loop {
+                match human.take_turn(board, color) {
+                    Action::RequestTakeback => {
+                        println!("Takebacks are not available in network games")
+                    }
+                    action => break action,
+                }
+            };
+            clocks[side] = clocks[side].saturating_sub(start.elapsed());
+            match action {
+                Action::Move(_) | Action::OfferDraw(_) if clocks[side].is_zero() => {
+                    break GameResult::win_for(remote_color, "time");
+                }
+                Action::Move(mv) | Action::OfferDraw(mv) => {
+                    if let Action::OfferDraw(_) = action {
+                        offered = true;
+                        remote.draw_offered(board, remote_color);
+                    }
+                    remote.time_update(clocks[1 - side], clocks[side]);
+                    remote.opponent_moved(board, mv);
+                    mv
+                }
+                Action::AcceptDraw if draw_offer == Some(remote_color) => {
+                    break GameResult::Draw(String::from("agreement"));
+                }
+                Action::AcceptDraw => {
+                    println!("No draw has been offered");
+                    continue;
+                }
+                _ => break GameResult::win_for(remote_color, "resignation"),
+            }
+        } else {
+            println!("Waiting for your opponent to move...");
+            let action = remote.take_turn(board, color);
+            clocks = match remote.clocks() {
+                Some(reported) => reported,
+                None => {
+                    clocks[side] = clocks[side].saturating_sub(start.elapsed());
+                    clocks
+                }
+            };
+            match action {
+                Action::Move(mv) | Action::OfferDraw(mv)
+                    if board.get_all_moves(color).contains(&mv) =>
+                {
+                    if let Action::OfferDraw(_) = action {
+                        offered = true;
+                    }
+                    human.opponent_moved(board, mv);
+                    mv
+                }
+                Action::Move(_) | Action::OfferDraw(_) => {
+                    break GameResult::win_for(local_color, "illegal move")
+                }
+                _ => {
+                    let resigned = GameResult::win_for(local_color, "resignation");
+                    break remote.result().unwrap_or(resigned);
+                }
+            }
+        };
+        draw_offer = if offered { Some(color) } else { None };
+        board.make_move(mv);
+        history.push(board.hash());
+    };
+    human.game_over(&result);
+    remote.game_over(&result);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::position::Position;
+    use std::net::Shutdown;
+
+    fn mv(start: &str, end: &str) -> Move {
+        Move {
+            start: Position::parse(start).unwrap(),
+            end: Position::parse(end).unwrap(),
+        }
+    }
+
+    // The host's stand-in for the client, and the client's stand-in for the host. The
+    // client plays Black.
+    fn connected_pair() -> (RemotePlayer, RemotePlayer) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let client = thread::spawn(move || RemotePlayer::connect(&address).unwrap());
+        let start = starting_board::make_board();
+        let host =
+            RemotePlayer::host_on(listener, Color::Black, start, Duration::from_secs(300)).unwrap();
+        (host, client.join().unwrap())
+    }
+
+    fn after(moves: &[Move]) -> Board {
+        let mut board = starting_board::make_board();
+        for mv in moves {
+            board.make_move(*mv);
+        }
+        board
+    }
+
+    #[test]
+    fn handshake_starts_a_new_game() {
+        let (host, client) = connected_pair();
+        assert_eq!(host.color(), Color::Black);
+        assert_eq!(client.color(), Color::White);
+        assert_eq!(client.clock(), Duration::from_secs(300));
+        assert_eq!(
+            client.start().fen_string(),
+            starting_board::make_board().fen_string()
+        );
+    }
+
+    #[test]
+    fn moves_and_clocks_reach_the_other_side() {
+        let (mut host, mut client) = connected_pair();
+        let board = after(&[]);
+        host.time_update(Duration::from_secs(290), Duration::from_secs(300));
+        host.opponent_moved(board, mv("e2", "e4"));
+        assert_eq!(
+            client.take_turn(board, Color::White),
+            Action::Move(mv("e2", "e4"))
+        );
+        assert_eq!(
+            client.clocks(),
+            Some([Duration::from_secs(300), Duration::from_secs(290)])
+        );
+
+        let board = after(&[mv("e2", "e4")]);
+        client.opponent_moved(board, mv("e7", "e5"));
+        assert_eq!(
+            host.take_turn(board, Color::Black),
+            Action::Move(mv("e7", "e5"))
+        );
+    }
+
+    #[test]
+    fn draw_offer_and_acceptance() {
+        let (mut host, mut client) = connected_pair();
+        let board = after(&[]);
+        host.draw_offered(board, Color::Black);
+        host.opponent_moved(board, mv("e2", "e4"));
+        assert_eq!(
+            client.take_turn(board, Color::White),
+            Action::OfferDraw(mv("e2", "e4"))
+        );
+
+        let agreed = GameResult::Draw(String::from("agreement"));
+        client.game_over(&agreed);
+        let board = after(&[mv("e2", "e4")]);
+        assert_eq!(host.take_turn(board, Color::Black), Action::AcceptDraw);
+        assert_eq!(host.result(), Some(agreed));
+    }
+
+    #[test]
+    fn draw_without_an_offer_is_rejected() {
+        let (mut host, mut client) = connected_pair();
+        let board = after(&[]);
+        host.opponent_moved(board, mv("e2", "e4"));
+        client.take_turn(board, Color::White);
+
+        client.game_over(&GameResult::Draw(String::from("agreement")));
+        let board = after(&[mv("e2", "e4")]);
+        assert_eq!(host.take_turn(board, Color::Black), Action::Resign);
+        assert_eq!(
+            host.result(),
+            Some(GameResult::WhiteWins(String::from("abandonment")))
+        );
+    }
+
+    #[test]
+    fn resignation() {
+        let (mut host, mut client) = connected_pair();
+        let resigned = GameResult::WhiteWins(String::from("resignation"));
+        client.game_over(&resigned);
+        assert_eq!(host.take_turn(after(&[]), Color::Black), Action::Resign);
+        assert_eq!(host.result(), Some(resigned));
+    }
+
+    #[test]
+    fn dropped_connection_resyncs() {
+        let (mut host, mut client) = connected_pair();
+        // The host played e4, but the connection dropped before the client got it
+        host.moves.push(mv("e2", "e4"));
+        client.writer.shutdown(Shutdown::Both).unwrap();
+
+        let client = thread::spawn(move || {
+            let action = client.take_turn(after(&[]), Color::White);
+            client.opponent_moved(after(&[mv("e2", "e4")]), mv("e7", "e5"));
+            action
+        });
+        let reply = host.take_turn(after(&[mv("e2", "e4")]), Color::Black);
+        assert_eq!(client.join().unwrap(), Action::Move(mv("e2", "e4")));
+        assert_eq!(reply, Action::Move(mv("e7", "e5")));
+    }
+}
@@ -1,6 +1,81 @@
 use crate::model::{board::Board, color::Color, move_::Move};
+use std::time::Duration;
 
+// What a player does on their turn
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum Action {
+    Move(Move),
+    // Offer a draw along with a move. The offer stands until the opponent has replied.
+    OfferDraw(Move),
+    // Accept the draw the opponent offered on their last turn
+    AcceptDraw,
+    Resign,
+    // Ask to take back the player's last move, and the opponent's reply to it
+    RequestTakeback,
+}
+
+// How a game ended, with the reason such as "checkmate" or "draw agreed"
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum GameResult {
+    WhiteWins(String),
+    BlackWins(String),
+    Draw(String),
+}
+
+impl GameResult {
+    pub(crate) fn win_for(color: Color, reason: &str) -> GameResult {
+        if color == Color::White {
+            GameResult::WhiteWins(reason.to_owned())
+        } else {
+            GameResult::BlackWins(reason.to_owned())
+        }
+    }
+
+    // The result as written at the end of a PGN game
+    pub(crate) fn pgn(&self) -> &'static str {
+        match self {
+            GameResult::WhiteWins(_) => "1-0",
+            GameResult::BlackWins(_) => "0-1",
+            GameResult::Draw(_) => "1/2-1/2",
+        }
+    }
+
+    pub(crate) fn description(&self) -> String {
+        match self {
+            GameResult::WhiteWins(reason) => format!("White wins by {reason}"),
+            GameResult::BlackWins(reason) => format!("Black wins by {reason}"),
+            GameResult::Draw(reason) => format!("Draw by {reason}"),
+        }
+    }
+}
+
+// Besides being asked for moves, players are told about the course of the game so they
+// can keep their own state in step, think on the opponent's time or learn from results.
+// Every notification is optional.
 pub(crate) trait Player {
-    fn take_turn(&mut self, board: Board, color: Color) -> Move;
+    // A game starts from `board`, with the player playing `color`
+    fn new_game(&mut self, _board: Board, _color: Color) {}
+
+    fn take_turn(&mut self, board: Board, color: Color) -> Action;
+
+    // The opponent has offered a draw. Answer with `Action::AcceptDraw` on the next turn,
+    // or move to decline.
+    fn draw_offered(&mut self, _board: Board, _color: Color) {}
+
+    // The opponent asks to take back their last move. Returns whether that is allowed.
+    fn takeback_requested(&mut self, _board: Board, _color: Color) -> bool {
+        false
+    }
+
+    // The last `plies` half moves were taken back, and the game goes on from `board`
+    fn moves_taken_back(&mut self, _board: Board, _plies: usize) {}
+
+    // The opponent played `mv` in the position `board`
+    fn opponent_moved(&mut self, _board: Board, _mv: Move) {}
+
+    // Time left on the clocks, sent before the player is asked to move
+    fn time_update(&mut self, _own: Duration, _opponent: Duration) {}
+
+    fn game_over(&mut self, _result: &GameResult) {}
 }
 // mod players
@@ -0,0 +1,220 @@
+use crate::model::{
+    board::Board, color::Color, move_::Move, piece::Piece, piece_type::PieceType,
+    position::Position,
+};
+
+// Draws the board for the terminal, for both the typed move loop and the full screen
+// interface. Squares are coloured with 24-bit escape codes from a theme, and the last
+// move, a king in check and the full screen cursor are highlighted.
+
+type Rgb = (u8, u8, u8);
+
+#[derive(Copy, Clone)]
+pub(crate) struct Theme {
+    light: Rgb,
+    dark: Rgb,
+    last_move: Rgb,
+    check: Rgb,
+    cursor: Rgb,
+    selected: Rgb,
+    target: Rgb,
+    white_ink: Rgb,
+    black_ink: Rgb,
+}
+
+const THEMES: [(&str, Theme); 4] = [
+    (
+        "wood",
+        Theme {
+            light: (240, 217, 181),
+            dark: (181, 136, 99),
+            last_move: (205, 210, 106),
+            check: (231, 76, 60),
+            cursor: (91, 155, 213),
+            selected: (246, 190, 0),
+            target: (130, 170, 90),
+            white_ink: (255, 255, 255),
+            black_ink: (0, 0, 0),
+        },
+    ),
+    (
+        "green",
+        Theme {
+            light: (238, 238, 210),
+            dark: (118, 150, 86),
+            last_move: (246, 246, 105),
+            check: (220, 60, 60),
+            cursor: (80, 140, 220),
+            selected: (186, 202, 68),
+            target: (100, 110, 64),
+            white_ink: (255, 255, 255),
+            black_ink: (0, 0, 0),
+        },
+    ),
+    (
+        "blue",
+        Theme {
+            light: (222, 227, 230),
+            dark: (140, 162, 173),
+            last_move: (171, 196, 120),
+            check: (214, 69, 65),
+            cursor: (230, 140, 50),
+            selected: (236, 200, 90),
+            target: (105, 145, 110),
+            white_ink: (255, 255, 255),
+            black_ink: (0, 0, 0),
+        },
+    ),
+    (
+        "gray",
+        Theme {
+            light: (170, 170, 170),
+            dark: (110, 110, 110),
+            last_move: (150, 150, 90),
+            check: (190, 60, 60),
+            cursor: (70, 120, 190),
+            selected: (200, 170, 60),
+            target: (90, 140, 90),
+            white_ink: (255, 255, 255),
+            black_ink: (0, 0, 0),
+        },
+    ),
+];
+
+impl Theme {
+    pub(crate) fn by_name(name: &str) -> Result<Theme, String> {
+        THEMES
+            .iter()
+            .find(|(theme_name, _)| *theme_name == name)
+            .map(|(_, theme)| *theme)
+            .ok_or_else(|| format!("Unknown theme {name}, use {}", Theme::names().join(", ")))
+    }
+
+    pub(crate) fn names() -> Vec<&'static str> {
+        THEMES.iter().map(|(name, _)| *name).collect()
+    }
+}
+
+// Squares to mark besides the last move and a king in check
+#[derive(Default)]
+pub(crate) struct Highlights {
+    pub(crate) last_move: Option<Move>,
+    pub(crate) cursor: Option<Position>,
+    pub(crate) selected: Option<Position>,
+    pub(crate) targets: Vec<Position>,
+}
+
+#[derive(Copy, Clone)]
+pub(crate) struct Renderer {
+    // Black at the bottom
+    pub(crate) flipped: bool,
+    // Letters instead of chess glyphs, for terminals without them
+    pub(crate) ascii: bool,
+    pub(crate) theme: Theme,
+}
+
+impl Default for Renderer {
+    fn default() -> Renderer {
+        Renderer {
+            flipped: false,
+            ascii: false,
+            theme: THEMES[0].1,
+        }
+    }
+}
+
+impl Renderer {
+    // The square drawn at `row` and `column` counting from the top left
+    pub(crate) fn square_at(&self, row: isize, column: isize) -> Position {
+        if self.flipped {
+            Position {
+                x: 7 - column,
+                y: row,
+            }
+        } else {
+            Position {
+                x: column,
+                y: 7 - row,
+            }
+        }
+    }
+
+    fn glyph(&self, piece: Piece) -> String {
+        if piece.piece_type == PieceType::Empty {
+            return String::from(" ");
+        } else if self.ascii {
+            return piece.fen_string();
+        }
+        // The solid glyphs for both sides, told apart by their colour
+        let solid = Piece {
+            piece_type: piece.piece_type,
+            color: Color::Black,
+        };
+        solid.to_string()
+    }
+
+    // One string per line: the eight ranks from the top, then the file letters
+    pub(crate) fn render_lines(&self, board: &Board, highlights: &Highlights) -> Vec<String> {
+        let checked_king = if board.in_checkp(Color::White) {
+            Some(board.white_king)
+        } else if board.in_checkp(Color::Black) {
+            Some(board.black_king)
+        } else {
+            None
+        };
+        let mut lines: Vec<String> = Vec::new();
+        for row in 0..8 {
+            let rank = self.square_at(row, 0).y;
+            let mut line = format!("{} ", rank + 1);
+            for column in 0..8 {
+                let pos = self.square_at(row, column);
+                let last_move = highlights
+                    .last_move
+                    .is_some_and(|mv| mv.start == pos || mv.end == pos);
+                let background = if highlights.cursor == Some(pos) {
+                    self.theme.cursor
+                } else if highlights.selected == Some(pos) {
+                    self.theme.selected
+                } else if highlights.targets.contains(&pos) {
+                    self.theme.target
+                } else if checked_king == Some(pos) {
+                    self.theme.check
+                } else if last_move {
+                    self.theme.last_move
+                } else if (pos.x + pos.y) % 2 == 1 {
+                    self.theme.light
+                } else {
+                    self.theme.dark
+                };
+                let piece = board.get_piece(pos);
+                let ink = if piece.color == Color::White {
+                    self.theme.white_ink
+                } else {
+                    self.theme.black_ink
+                };
+                let glyph =
+                    if piece.piece_type == PieceType::Empty && highlights.targets.contains(&pos) {
+                        String::from(if self.ascii { "." } else { "·" })
+                    } else {
+                        self.glyph(piece)
+                    };
+                line.push_str(&format!(
+                    "\x1b[1;48;2;{};{};{};38;2;{};{};{}m {glyph} \x1b[0m",
+                    background.0, background.1, background.2, ink.0, ink.1, ink.2
+                ));
+            }
+            lines.push(line);
+        }
+        let mut files = String::from("  ");
+        for column in 0..8 {
+            let file = self.square_at(0, column).x as u8;
+            files.push_str(&format!(" {} ", (b'a' + file) as char));
+        }
+        lines.push(files);
+        lines
+    }
+
+    pub(crate) fn render(&self, board: &Board, highlights: &Highlights) -> String {
+        self.render_lines(board, highlights).join("\n")
+    }
+}
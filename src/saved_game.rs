@@ -0,0 +1,217 @@
+use crate::engine::skill::Skill;
+use crate::model::{board::Board, color::Color, move_::Move, notation, pgn};
+use crate::starting_board;
+use std::fs;
+use std::time::Duration;
+
+// A game against the engine written as PGN, so it survives the program stopping and can
+// be resumed. Besides the usual tags, the file records how the game is played: the
+// human's side, the interface, the clocks, and the engine's skill, book, evaluation
+// parameters and whether it ponders.
+
+pub(crate) struct SavedGame {
+    pub(crate) start: Board,
+    pub(crate) moves: Vec<Move>,
+    pub(crate) human_color: Color,
+    pub(crate) terminal_ui: bool,
+    // Time each side started with
+    pub(crate) clock: Duration,
+    // Time left for White and Black in the terminal interface
+    pub(crate) clocks: Option<[Duration; 2]>,
+    pub(crate) ponder: bool,
+    pub(crate) skill: Skill,
+    // Files the engine's opening book and evaluation parameters were loaded from
+    pub(crate) book: Option<String>,
+    pub(crate) eval_params: Option<String>,
+    // "*" while the game is still going
+    pub(crate) result: String,
+}
+
+impl SavedGame {
+    pub(crate) fn to_pgn(&self) -> String {
+        let mut game = pgn::PgnGame::new();
+        let (white, black) = if self.human_color == Color::White {
+            ("Human", "Engine")
+        } else {
+            ("Engine", "Human")
+        };
+        let mut tags = vec![
+            ("Event", String::from("Casual game")),
+            ("White", String::from(white)),
+            ("Black", String::from(black)),
+            ("Result", self.result.clone()),
+        ];
+        let fen = self.start.fen_string();
+        if fen != starting_board::make_board().fen_string() {
+            tags.push(("SetUp", String::from("1")));
+            tags.push(("FEN", fen));
+        }
+        tags.push(("TimeControl", self.clock.as_secs().to_string()));
+        tags.push(("HumanColor", self.human_color.name().to_lowercase()));
+        let interface = if self.terminal_ui { "tui" } else { "text" };
+        tags.push(("Interface", String::from(interface)));
+        tags.push(("Ponder", self.ponder.to_string()));
+        tags.push(("EngineSkill", self.skill.level().to_string()));
+        if let Some(book) = &self.book {
+            tags.push(("EngineBook", book.clone()));
+        }
+        if let Some(eval_params) = &self.eval_params {
+            tags.push(("EngineParams", eval_params.clone()));
+        }
+        // Milliseconds left on each clock
+        if let Some([white_clock, black_clock]) = self.clocks {
+            tags.push(("WhiteClock", white_clock.as_millis().to_string()));
+            tags.push(("BlackClock", black_clock.as_millis().to_string()));
+        }
+        game.tags = tags
+            .into_iter()
+            .map(|(name, value)| (String::from(name), value))
+            .collect();
+
+        let mut board = self.start;
+        for mv in &self.moves {
+            game.moves.push(notation::to_san(&board, *mv));
+            board.make_move(*mv);
+        }
+        game.result = self.result.clone();
+        game.to_pgn()
+    }
+
+    pub(crate) fn from_pgn(text: &str) -> Result<SavedGame, String> {
+        let game = match pgn::parse_games(text).into_iter().next() {
+            Some(game) => game,
+            None => return Err(String::from("No game found")),
+        };
+        let start = game.start_board()?;
+        let mut board = start;
+        let mut moves: Vec<Move> = Vec::new();
+        for san in &game.moves {
+            let mv = notation::parse_move(&board, san).map_err(|e| format!("{san}: {e}"))?;
+            board.make_move(mv);
+            moves.push(mv);
+        }
+
+        let human_color = match game.tag("HumanColor") {
+            Some("white") => Color::White,
+            Some("black") | None => Color::Black,
+            Some(other) => return Err(format!("Unknown HumanColor {other}")),
+        };
+        let millis = |name: &str| -> Result<Option<Duration>, String> {
+            match game.tag(name) {
+                Some(value) => value
+                    .parse()
+                    .map(|ms| Some(Duration::from_millis(ms)))
+                    .map_err(|_| format!("{name} must be milliseconds")),
+                None => Ok(None),
+            }
+        };
+        let clocks = match (millis("WhiteClock")?, millis("BlackClock")?) {
+            (Some(white_clock), Some(black_clock)) => Some([white_clock, black_clock]),
+            _ => None,
+        };
+        let clock = match game.tag("TimeControl") {
+            Some(value) => Duration::from_secs(
+                value
+                    .parse()
+                    .map_err(|_| String::from("TimeControl must be seconds"))?,
+            ),
+            None => Duration::from_secs(10 * 60),
+        };
+        let skill = match game.tag("EngineSkill") {
+            Some(value) => Skill::new(
+                value
+                    .parse()
+                    .map_err(|_| String::from("EngineSkill must be a level"))?,
+            )?,
+            None => Skill::default(),
+        };
+        Ok(SavedGame {
+            start,
+            moves,
+            human_color,
+            terminal_ui: game.tag("Interface") == Some("tui"),
+            clock,
+            clocks,
+            ponder: game.tag("Ponder") == Some("true"),
+            skill,
+            book: game.tag("EngineBook").map(String::from),
+            eval_params: game.tag("EngineParams").map(String::from),
+            result: game.result,
+        })
+    }
+
+    pub(crate) fn load(path: &str) -> Result<SavedGame, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Could not read {path}: {e}"))?;
+        SavedGame::from_pgn(&text).map_err(|e| format!("{path}: {e}"))
+    }
+
+    // Written to a temporary file first, so a crash while saving leaves the old save whole
+    pub(crate) fn save(&self, path: &str) -> Result<(), String> {
+        let temporary = format!("{path}.tmp");
+        fs::write(&temporary, self.to_pgn())
+            .and_then(|_| fs::rename(&temporary, path))
+            .map_err(|e| format!("Could not write {path}: {e}"))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::position::Position;
+
+    #[test]
+    fn pgn_round_trip() {
+        let start =
+            Board::from_fen("r3k2r/ppp2ppp/8/3p4/4P3/8/PPPN1PPP/R3K1NR w KQkq - 0 1").unwrap();
+        let mut moves = Vec::new();
+        let mut board = start;
+        for (from, to) in [("e4", "d5"), ("e8", "c8"), ("g1", "f3")] {
+            let mv = Move {
+                start: Position::parse(from).unwrap(),
+                end: Position::parse(to).unwrap(),
+            };
+            moves.push(mv);
+            board.make_move(mv);
+        }
+        let saved = SavedGame {
+            start,
+            moves,
+            human_color: Color::White,
+            terminal_ui: true,
+            clock: Duration::from_secs(300),
+            clocks: Some([
+                Duration::from_millis(281_500),
+                Duration::from_millis(296_250),
+            ]),
+            ponder: true,
+            skill: Skill::new(7).unwrap(),
+            book: Some(String::from("books/main line.bin")),
+            eval_params: Some(String::from("tuned.txt")),
+            result: String::from("*"),
+        };
+
+        let loaded = SavedGame::from_pgn(&saved.to_pgn()).unwrap();
+        assert_eq!(loaded.start.fen_string(), saved.start.fen_string());
+        assert_eq!(loaded.moves, saved.moves);
+        assert_eq!(loaded.human_color, Color::White);
+        assert!(loaded.terminal_ui);
+        assert_eq!(loaded.clock, saved.clock);
+        assert_eq!(loaded.clocks, saved.clocks);
+        assert!(loaded.ponder);
+        assert_eq!(loaded.skill, saved.skill);
+        assert_eq!(loaded.book, saved.book);
+        assert_eq!(loaded.eval_params, saved.eval_params);
+        assert_eq!(loaded.result, "*");
+    }
+
+    #[test]
+    fn missing_config_tags_take_the_defaults() {
+        let loaded = SavedGame::from_pgn("[Result \"*\"]\n\n1. e4 *\n").unwrap();
+        assert_eq!(loaded.human_color, Color::Black);
+        assert_eq!(loaded.clocks, None);
+        assert_eq!(loaded.skill, Skill::default());
+        assert_eq!(loaded.book, None);
+        assert_eq!(loaded.eval_params, None);
+    }
+}
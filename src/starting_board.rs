@@ -286,5 +286,7 @@ pub(crate) fn make_board() -> Board {
         black_king: Position { y: 7, x: 4 },
         white_king: Position { y: 0, x: 4 },
         en_pessant: Position { x: -1, y: -1 },
+        halfmove_clock: 0,
+        fullmove_number: 1,
     };
 }
@@ -0,0 +1,298 @@
+use crate::engine;
+use crate::model::{board::Board, color::Color, move_::Move, position::Position};
+use crate::player::Action;
+use crate::render::{Highlights, Renderer};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+// Full screen terminal interface. The terminal is put in raw mode with `stty`, so keys
+// arrive one at a time without echo, and the screen is redrawn with ANSI escape codes.
+// Players move a cursor over the board with the arrow keys and pick a piece and then
+// one of its highlighted destinations with Enter or Space. Letter keys offer and accept
+// draws, resign and take back moves.
+
+// Where the side panel starts
+const PANEL_COLUMN: usize = 32;
+// Rows of moves shown in the side panel, the latest ones when there are more
+const MOVE_ROWS: usize = 14;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    Select,
+    Cancel,
+    Quit,
+    Char(char),
+}
+
+// What the screen shows besides the board
+pub(crate) struct GameView<'a> {
+    pub(crate) board: &'a Board,
+    // Moves so far in SAN
+    pub(crate) moves: &'a [String],
+    // Time left for white and black
+    pub(crate) clocks: [Duration; 2],
+    // Engine score from white's point of view
+    pub(crate) eval: Option<i16>,
+    pub(crate) last_move: Option<Move>,
+    pub(crate) status: &'a str,
+}
+
+// Puts the terminal in raw mode on the alternate screen, and restores it when dropped
+pub(crate) struct Terminal {
+    saved_mode: String,
+    renderer: Renderer,
+}
+
+fn stty(args: &[&str]) -> Result<String, String> {
+    let tty = File::open("/dev/tty").map_err(|e| format!("No terminal available: {e}"))?;
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::from(tty))
+        .output()
+        .map_err(|e| format!("Could not run stty: {e}"))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_owned());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+impl Terminal {
+    pub(crate) fn new(renderer: Renderer) -> Result<Terminal, String> {
+        let saved_mode = stty(&["-g"])?;
+        // Reads wait at most a tenth of a second, so a lone Escape can be told apart
+        // from the start of an arrow key sequence
+        stty(&["raw", "-echo", "min", "0", "time", "1"])?;
+        print!("\x1b[?1049h\x1b[?25l");
+        io::stdout().flush().ok();
+        Ok(Terminal {
+            saved_mode,
+            renderer,
+        })
+    }
+
+    fn read_byte(&self) -> Option<u8> {
+        let mut byte = [0u8; 1];
+        match io::stdin().read(&mut byte) {
+            Ok(1) => Some(byte[0]),
+            _ => None,
+        }
+    }
+
+    // Blocks until a key is pressed
+    pub(crate) fn read_key(&self) -> Key {
+        loop {
+            let Some(byte) = self.read_byte() else {
+                continue;
+            };
+            return match byte {
+                0x1b => match (self.read_byte(), self.read_byte()) {
+                    (Some(b'['), Some(b'A')) => Key::Up,
+                    (Some(b'['), Some(b'B')) => Key::Down,
+                    (Some(b'['), Some(b'C')) => Key::Right,
+                    (Some(b'['), Some(b'D')) => Key::Left,
+                    (None, _) => Key::Cancel,
+                    _ => continue,
+                },
+                b'\r' | b'\n' | b' ' => Key::Select,
+                0x7f | 0x08 => Key::Cancel,
+                // Ctrl-C and Ctrl-D, since raw mode turns off signals
+                0x03 | 0x04 | b'q' => Key::Quit,
+                other => Key::Char(other as char),
+            };
+        }
+    }
+
+    pub(crate) fn draw(
+        &self,
+        view: &GameView,
+        cursor: Option<Position>,
+        selected: Option<Position>,
+    ) {
+        let targets: Vec<Position> = match selected {
+            Some(from) => view
+                .board
+                .get_all_moves(view.board.turn)
+                .into_iter()
+                .filter(|m| m.start == from)
+                .map(|m| m.end)
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let highlights = Highlights {
+            last_move: view.last_move,
+            cursor,
+            selected,
+            targets,
+        };
+        let mut screen = String::from("\x1b[2J");
+        put(&mut screen, 1, 1, "\x1b[1mChess\x1b[0m");
+        for (i, line) in self
+            .renderer
+            .render_lines(view.board, &highlights)
+            .iter()
+            .enumerate()
+        {
+            put(&mut screen, 3 + i, 1, line);
+        }
+        put(&mut screen, 13, 1, view.status);
+        put(
+            &mut screen,
+            15,
+            1,
+            "\x1b[2mArrows move, Enter selects, Esc cancels, f flips, q quits\x1b[0m",
+        );
+        put(
+            &mut screen,
+            16,
+            1,
+            "\x1b[2md offers a draw, a accepts one, u takes back, r resigns\x1b[0m",
+        );
+
+        let mut row = 3;
+        for (color, name) in [(Color::White, "White"), (Color::Black, "Black")] {
+            let marker = if view.board.turn == color { ">" } else { " " };
+            let clock = view.clocks[if color == Color::White { 0 } else { 1 }];
+            put(
+                &mut screen,
+                row,
+                PANEL_COLUMN,
+                &format!("{marker} {name} {}", format_clock(clock)),
+            );
+            row += 1;
+        }
+        let eval = match view.eval {
+            Some(score) => match engine::mate_in(score) {
+                Some(moves) if moves > 0 => format!("White mates in {moves}"),
+                Some(moves) => format!("Black mates in {}", -moves),
+                None => format!("{:+.2}", score as f64 / 100.0),
+            },
+            None => String::from("-"),
+        };
+        put(&mut screen, row + 1, PANEL_COLUMN, &format!("Eval {eval}"));
+
+        put(&mut screen, row + 3, PANEL_COLUMN, "Moves");
+        let pairs: Vec<String> = view
+            .moves
+            .chunks(2)
+            .enumerate()
+            .map(|(i, pair)| {
+                format!(
+                    "{:>3}. {:<8}{}",
+                    i + 1,
+                    pair[0],
+                    pair.get(1).map_or("", |m| m)
+                )
+            })
+            .collect();
+        let first = pairs.len().saturating_sub(MOVE_ROWS);
+        for (i, text) in pairs[first..].iter().enumerate() {
+            put(&mut screen, row + 4 + i, PANEL_COLUMN, text);
+        }
+
+        print!("{screen}");
+        io::stdout().flush().ok();
+    }
+
+    // Lets the player pick one of the legal moves or another action. Returns `None` when
+    // they quit.
+    pub(crate) fn take_turn(&mut self, view: &GameView) -> Option<Action> {
+        let color = view.board.turn;
+        let moves = view.board.get_all_moves(color);
+        let home = if color == Color::White { 1 } else { 6 };
+        let mut cursor = Position { x: 4, y: home };
+        let mut selected: Option<Position> = None;
+        let mut message = String::new();
+        // A draw offer goes with the move that is picked next
+        let mut offering_draw = false;
+        // Resigning takes r twice
+        let mut resigning = false;
+
+        loop {
+            let status = if !message.is_empty() {
+                message.clone()
+            } else if offering_draw {
+                String::from("You offer a draw with your next move")
+            } else {
+                view.status.to_owned()
+            };
+            let current = GameView {
+                status: &status,
+                ..*view
+            };
+            self.draw(&current, Some(cursor), selected);
+            message.clear();
+
+            // Arrows move the cursor on the screen, whichever way up the board is
+            let step = if self.renderer.flipped { -1 } else { 1 };
+            let key = self.read_key();
+            if resigning {
+                resigning = false;
+                if key == Key::Char('r') {
+                    return Some(Action::Resign);
+                }
+            }
+            match key {
+                Key::Up => cursor.y = (cursor.y + step).clamp(0, 7),
+                Key::Down => cursor.y = (cursor.y - step).clamp(0, 7),
+                Key::Left => cursor.x = (cursor.x - step).clamp(0, 7),
+                Key::Right => cursor.x = (cursor.x + step).clamp(0, 7),
+                Key::Char('f') => self.renderer.flipped = !self.renderer.flipped,
+                Key::Cancel => selected = None,
+                Key::Quit => return None,
+                Key::Char('d') => offering_draw = !offering_draw,
+                Key::Char('a') => return Some(Action::AcceptDraw),
+                Key::Char('u') => return Some(Action::RequestTakeback),
+                Key::Char('r') => {
+                    resigning = true;
+                    message = String::from("Press r again to resign");
+                }
+                Key::Select => {
+                    if let Some(from) = selected {
+                        if let Some(mv) = moves.iter().find(|m| m.start == from && m.end == cursor)
+                        {
+                            return Some(if offering_draw {
+                                Action::OfferDraw(*mv)
+                            } else {
+                                Action::Move(*mv)
+                            });
+                        }
+                    }
+                    if view.board.get_piece(cursor).color != color {
+                        selected = None;
+                    } else if moves.iter().any(|m| m.start == cursor) {
+                        selected = Some(cursor);
+                    } else {
+                        selected = None;
+                        message = String::from("That piece has no legal moves");
+                    }
+                }
+                Key::Char(_) => {}
+            }
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        io::stdout().flush().ok();
+        stty(&[&self.saved_mode]).ok();
+    }
+}
+
+// Raw mode does not turn "\n" into a new line, so every line is placed explicitly
+fn put(screen: &mut String, row: usize, column: usize, text: &str) {
+    screen.push_str(&format!("\x1b[{row};{column}H{text}"));
+}
+
+pub(crate) fn format_clock(time: Duration) -> String {
+    let seconds = time.as_secs();
+    format!("{:02}:{:02}", seconds / 60, seconds % 60)
+}
@@ -0,0 +1,358 @@
+use crate::engine::{format_score, ponder::SearchControl, skill::Skill, SearchLimits, AI};
+use crate::model::{board::Board, color::Color, notation};
+use crate::player::Player;
+use crate::starting_board;
+use std::io;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// Speaks the Universal Chess Interface on standard input and output, so the engine can be
+// used from graphical interfaces and tournament managers. The search runs on its own
+// thread so that stop and ponderhit are read while it thinks.
+
+// Deadline for searches limited only by depth
+const NO_TIME_LIMIT: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+// Kept back from the clock for the interface's own overhead
+const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+
+struct Search {
+    control: Arc<SearchControl>,
+    // Time to think once a ponder search gets its ponderhit
+    move_time: Duration,
+    handle: JoinHandle<AI>,
+}
+
+struct Uci {
+    // None while the engine is searching on the search thread
+    engine: Option<AI>,
+    search: Option<Search>,
+    // None after a position command that could not be read, so go has nothing to search
+    board: Option<Board>,
+    // UCI_LimitStrength and UCI_Elo, which only take effect together
+    limit_strength: bool,
+    elo: u16,
+}
+
+pub(crate) fn run(engine: AI) {
+    let mut uci = Uci {
+        engine: Some(engine),
+        search: None,
+        board: Some(starting_board::make_board()),
+        limit_strength: false,
+        elo: Skill::max_elo(),
+    };
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.first().copied() {
+            Some("uci") => {
+                println!("id name chess");
+                println!("id author the chess authors");
+                println!("option name Ponder type check default false");
+                println!("option name UCI_LimitStrength type check default false");
+                println!(
+                    "option name UCI_Elo type spin default {} min {} max {}",
+                    Skill::max_elo(),
+                    Skill::min_elo(),
+                    Skill::max_elo()
+                );
+                println!("uciok");
+            }
+            Some("isready") => println!("readyok"),
+            Some("setoption") => uci.set_option(&words[1..]),
+            Some("ucinewgame") => {
+                let board = starting_board::make_board();
+                uci.board = Some(board);
+                uci.engine().new_game(board, Color::White);
+            }
+            Some("position") => match parse_position(&words[1..]) {
+                Ok(board) => uci.board = Some(board),
+                Err(e) => {
+                    uci.board = None;
+                    println!("info string {e}");
+                }
+            },
+            Some("go") => uci.go(&words[1..]),
+            Some("stop") => {
+                uci.engine();
+            }
+            Some("ponderhit") => {
+                if let Some(search) = &uci.search {
+                    search
+                        .control
+                        .set_deadline(Instant::now() + search.move_time);
+                }
+            }
+            Some("quit") => break,
+            _ => {}
+        }
+    }
+    uci.engine();
+}
+
+// "startpos" or "fen" and the six fields, then optionally "moves" and the moves played
+fn parse_position(words: &[&str]) -> Result<Board, String> {
+    let moves_at = words
+        .iter()
+        .position(|w| *w == "moves")
+        .unwrap_or(words.len());
+    let mut board = match words.first() {
+        Some(&"startpos") => starting_board::make_board(),
+        Some(&"fen") => Board::from_fen(&words[1..moves_at].join(" "))?,
+        _ => return Err(String::from("position needs startpos or fen")),
+    };
+    for text in words.iter().skip(moves_at + 1) {
+        let mv = notation::parse_move(&board, text).map_err(|e| format!("{text}: {e}"))?;
+        board.make_move(mv);
+    }
+    Ok(board)
+}
+
+// What a go command asks for
+#[derive(Debug, PartialEq)]
+struct GoLimits {
+    depth: Option<u8>,
+    move_time: Duration,
+    // Pondering and infinite searches have no deadline until ponderhit or stop
+    unlimited: bool,
+}
+
+// The search limits for `color` from the words after go. With a clock the engine spends a
+// share of it plus most of the increment, never more than is left.
+fn parse_go(words: &[&str], color: Color) -> GoLimits {
+    let mut depth: Option<u8> = None;
+    let mut move_time: Option<Duration> = None;
+    let mut clock: Option<Duration> = None;
+    let mut increment = Duration::ZERO;
+    let mut moves_to_go: u32 = 30;
+    let mut unlimited = false;
+    for (i, word) in words.iter().enumerate() {
+        let value = words.get(i + 1).and_then(|v| v.parse::<u64>().ok());
+        match (*word, value) {
+            ("ponder", _) | ("infinite", _) => unlimited = true,
+            ("depth", Some(value)) => depth = Some(value.min(u8::MAX as u64) as u8),
+            ("movetime", Some(value)) => move_time = Some(Duration::from_millis(value)),
+            ("wtime", Some(value)) if color == Color::White => {
+                clock = Some(Duration::from_millis(value))
+            }
+            ("btime", Some(value)) if color == Color::Black => {
+                clock = Some(Duration::from_millis(value))
+            }
+            ("winc", Some(value)) if color == Color::White => {
+                increment = Duration::from_millis(value)
+            }
+            ("binc", Some(value)) if color == Color::Black => {
+                increment = Duration::from_millis(value)
+            }
+            ("movestogo", Some(value)) => moves_to_go = value.max(1) as u32,
+            _ => {}
+        }
+    }
+    let move_time = match (move_time, clock) {
+        (Some(move_time), _) => move_time,
+        (None, Some(clock)) => {
+            let left = clock.saturating_sub(MOVE_OVERHEAD);
+            (clock / moves_to_go + increment * 3 / 4)
+                .min(left)
+                .max(Duration::from_millis(10))
+        }
+        (None, None) if depth.is_some() => NO_TIME_LIMIT,
+        (None, None) => SearchLimits::default().move_time,
+    };
+    GoLimits {
+        depth,
+        move_time,
+        unlimited,
+    }
+}
+
+impl Uci {
+    // The engine, stopping a running search first. Pondering and infinite searches never
+    // end on their own, so waiting for them would hang.
+    fn engine(&mut self) -> &mut AI {
+        if let Some(search) = self.search.take() {
+            search.control.stop();
+            self.engine = Some(search.handle.join().expect("search thread panicked"));
+        }
+        self.engine.as_mut().unwrap()
+    }
+
+    // "name <name> value <value>". The interface decides when to ponder, so Ponder needs
+    // no action.
+    fn set_option(&mut self, words: &[&str]) {
+        let value_at = words
+            .iter()
+            .position(|w| *w == "value")
+            .unwrap_or(words.len());
+        let name = words.get(1..value_at).unwrap_or_default().join(" ");
+        let value = words.get(value_at + 1..).unwrap_or_default().join(" ");
+        match name.as_str() {
+            "UCI_LimitStrength" => self.limit_strength = value == "true",
+            "UCI_Elo" => match value.parse() {
+                Ok(elo) => self.elo = elo,
+                Err(_) => {
+                    println!("info string UCI_Elo takes a rating, not {value}");
+                    return;
+                }
+            },
+            _ => return,
+        }
+        let skill = if self.limit_strength {
+            Skill::from_elo(self.elo)
+        } else {
+            Skill::default()
+        };
+        self.engine().set_skill(skill);
+    }
+
+    fn go(&mut self, words: &[&str]) {
+        // Finish a search still running before looking at the position
+        self.engine();
+        let board = match self.board {
+            Some(board) => board,
+            None => {
+                println!("info string No position to search");
+                println!("bestmove 0000");
+                return;
+            }
+        };
+        let color = board.turn;
+        let GoLimits {
+            depth,
+            move_time,
+            unlimited,
+        } = parse_go(words, color);
+        // Pondering and infinite searches run until a ponderhit or stop
+        let deadline = if unlimited {
+            None
+        } else {
+            Some(Instant::now() + move_time)
+        };
+
+        let mut engine = self.engine.take().unwrap();
+        let control = SearchControl::new(deadline);
+        engine.set_limits(SearchLimits { depth, move_time });
+        engine.set_control(Some(control.clone()));
+        let search_control = control.clone();
+        let handle = thread::spawn(move || {
+            if board.get_all_moves(color).is_empty() {
+                control.wait();
+                println!("bestmove 0000");
+                return engine;
+            }
+            let mv = engine.choose_move(board, color);
+            // Book and table moves come without a search, but still wait for the stop
+            control.wait();
+            let mut position = board;
+            let mut pv: Vec<String> = Vec::new();
+            for mv in engine.last_pv() {
+                pv.push(notation::to_long_algebraic(&position, *mv));
+                position.make_move(*mv);
+            }
+            if let Some(score) = engine.last_score() {
+                println!("info score {} pv {}", format_score(score), pv.join(" "));
+            }
+            let best = notation::to_long_algebraic(&board, mv);
+            match pv.get(1) {
+                Some(reply) => println!("bestmove {best} ponder {reply}"),
+                None => println!("bestmove {best}"),
+            }
+            engine.set_control(None);
+            engine
+        });
+        self.search = Some(Search {
+            control: search_control,
+            move_time,
+            handle,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn position_from_startpos_with_moves() {
+        let board = parse_position(&["startpos", "moves", "e2e4", "c7c5", "g1f3"]).unwrap();
+        assert_eq!(
+            board.fen_string(),
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+    }
+
+    #[test]
+    fn position_from_fen() {
+        let fen = "8/8/4k3/8/8/4K3/8/4R3 w - - 12 40";
+        let mut words = vec!["fen"];
+        words.extend(fen.split(' '));
+        assert_eq!(parse_position(&words).unwrap().fen_string(), fen);
+        words.extend(["moves", "e1e2"]);
+        assert_eq!(
+            parse_position(&words).unwrap().fen_string(),
+            "8/8/4k3/8/8/4K3/4R3/8 b - - 13 40"
+        );
+    }
+
+    #[test]
+    fn position_errors() {
+        assert!(parse_position(&[]).is_err());
+        assert!(parse_position(&["startpos", "moves", "e2e5"]).is_err());
+        assert!(parse_position(&["fen", "8/8/8", "w", "-", "-"]).is_err());
+    }
+
+    #[test]
+    fn go_with_fixed_limits() {
+        let limits = parse_go(&["movetime", "500"], Color::White);
+        assert_eq!(limits.move_time, Duration::from_millis(500));
+        assert!(!limits.unlimited);
+        let limits = parse_go(&["depth", "6"], Color::White);
+        assert_eq!(limits.depth, Some(6));
+        assert_eq!(limits.move_time, NO_TIME_LIMIT);
+        let limits = parse_go(&[], Color::White);
+        assert_eq!(limits.move_time, SearchLimits::default().move_time);
+        assert!(parse_go(&["infinite"], Color::White).unlimited);
+        assert!(parse_go(&["ponder", "wtime", "1000"], Color::White).unlimited);
+    }
+
+    #[test]
+    fn go_shares_out_the_clock() {
+        let words = [
+            "wtime", "60000", "btime", "30000", "winc", "1000", "binc", "0",
+        ];
+        // A thirtieth of the clock and three quarters of the increment
+        assert_eq!(
+            parse_go(&words, Color::White).move_time,
+            Duration::from_millis(2750)
+        );
+        assert_eq!(
+            parse_go(&words, Color::Black).move_time,
+            Duration::from_millis(1000)
+        );
+        let words = ["btime", "20000", "movestogo", "4"];
+        assert_eq!(
+            parse_go(&words, Color::Black).move_time,
+            Duration::from_millis(5000)
+        );
+    }
+
+    #[test]
+    fn go_never_spends_more_than_is_left() {
+        let words = ["wtime", "300", "winc", "2000"];
+        assert_eq!(
+            parse_go(&words, Color::White).move_time,
+            Duration::from_millis(250)
+        );
+        let words = ["wtime", "20"];
+        assert_eq!(
+            parse_go(&words, Color::White).move_time,
+            Duration::from_millis(10)
+        );
+    }
+}
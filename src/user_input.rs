@@ -1,44 +1,275 @@
-use crate::model::{board::Board, color::Color, move_::Move, position::Position};
-use crate::player;
+use crate::engine::{format_score, SearchLimits, AI};
+use crate::model::{board::Board, color::Color, move_::Move, notation, pgn::PgnGame};
+use crate::player::{self, Action, GameResult};
+use crate::render::{Highlights, Renderer};
+use crate::starting_board;
 
-use std::io;
-pub(crate) struct Human {}
+use std::io::{self, Write};
+use std::time::Duration;
 
-impl player::Player for Human {
-    fn take_turn(&mut self, board: Board, color: Color) -> Move {
-        let mut my_move;
-        let moves: Vec<Move> = board.get_all_moves(color);
-        println!("Your Move:");
-        let mut start = String::new();
+pub(crate) const HELP: &str = "Enter a move in SAN (e4, Nf3, exd5, O-O, e8=Q) or long algebraic
+notation (e2e4, e7e8q), or one of these commands:
+  undo    take back your last move
+  hint    ask the engine for a move
+  resign  give up the game
+  draw    offer a draw, or claim one by repetition or the fifty move rule
+  accept  accept the draw your opponent offered
+  flip    turn the board around
+  fen     show the position as FEN
+  pgn     show the game so far as PGN
+  eval    show the engine's opinion of the position
+  help    show this message";
+
+// Something the player typed
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum Command {
+    Move(Move),
+    Undo,
+    Hint,
+    Resign,
+    Draw,
+    Accept,
+    Flip,
+    Fen,
+    Pgn,
+    Eval,
+    Help,
+}
+
+// Reads a command or a move for the side to move on `board`. The error says what was
+// wrong with the input.
+pub(crate) fn parse_command(board: &Board, text: &str) -> Result<Command, String> {
+    let text = text.trim();
+    match text.to_lowercase().as_str() {
+        "" => Err(String::from("Enter a move, or help for the commands")),
+        "undo" | "takeback" => Ok(Command::Undo),
+        "hint" => Ok(Command::Hint),
+        "resign" => Ok(Command::Resign),
+        "draw" => Ok(Command::Draw),
+        "accept" => Ok(Command::Accept),
+        "flip" => Ok(Command::Flip),
+        "fen" => Ok(Command::Fen),
+        "pgn" => Ok(Command::Pgn),
+        "eval" => Ok(Command::Eval),
+        "help" | "?" => Ok(Command::Help),
+        _ => notation::parse_move(board, text)
+            .map(Command::Move)
+            .map_err(|e| format!("{e} (type help for the commands)")),
+    }
+}
 
-        io::stdin()
-            .read_line(&mut start)
-            .expect("Failed to read line");
+pub(crate) struct Human {
+    // Draws the board before each move
+    renderer: Renderer,
+    // Answers hint and eval, when there is an engine to ask
+    adviser: Option<AI>,
+    color: Color,
+    // The game so far, for pgn and the last move highlight
+    start: Board,
+    moves: Vec<Move>,
+}
+
+impl Human {
+    pub(crate) fn new(renderer: Renderer, adviser: Option<AI>) -> Human {
+        Human {
+            renderer,
+            adviser,
+            color: Color::White,
+            start: starting_board::make_board(),
+            moves: Vec::new(),
+        }
+    }
 
-        let end = start.split_off(2);
+    // Moves already played in a resumed game, after `new_game` with its start position
+    pub(crate) fn replay(&mut self, moves: &[Move]) {
+        self.moves = moves.to_vec();
+    }
 
-        my_move = Move {
-            start: Position::from_string(start),
-            end: Position::from_string(end),
+    // Asks until the player types something usable. A closed input counts as resigning.
+    pub(crate) fn read_command(&mut self, board: &Board) -> Command {
+        loop {
+            let side = if board.turn == Color::White {
+                "White"
+            } else {
+                "Black"
+            };
+            print!("{side} to move: ");
+            io::stdout().flush().ok();
+            let mut line = String::new();
+            match io::stdin().read_line(&mut line) {
+                Ok(0) | Err(_) => return Command::Resign,
+                Ok(_) => {}
+            }
+            match parse_command(board, &line) {
+                Ok(command) => return command,
+                Err(e) => println!("{e}"),
+            }
+        }
+    }
+
+    fn show_board(&self, board: &Board) {
+        let highlights = Highlights {
+            last_move: self.moves.last().copied(),
+            ..Highlights::default()
         };
+        println!("{}", self.renderer.render(board, &highlights));
+    }
 
-        while !(moves.contains(&my_move)) {
-            println!("Thats not a valid move, try again");
-            let mut start = String::new();
+    fn hint(&mut self, board: &Board) {
+        let Some(adviser) = &mut self.adviser else {
+            println!("There is no engine to ask for a hint");
+            return;
+        };
+        adviser.set_limits(SearchLimits {
+            depth: None,
+            move_time: Duration::from_secs(2),
+        });
+        match adviser.analyze(*board, board.turn).first() {
+            Some(line) => println!(
+                "Hint: {} ({})",
+                notation::to_san(board, line.mv),
+                format_score(line.score)
+            ),
+            None => println!("No hint available"),
+        }
+    }
 
-            io::stdin()
-                .read_line(&mut start)
-                .expect("Failed to read line");
+    fn eval(&mut self, board: &Board) {
+        match &mut self.adviser {
+            Some(adviser) => {
+                let score = adviser.static_eval(board, Color::White);
+                println!("Static evaluation {score:+} for White");
+            }
+            None => println!("There is no engine to evaluate the position"),
+        }
+    }
 
-            let end = start.split_off(2);
+    fn pgn(&self) -> String {
+        let mut game = PgnGame::new();
+        let (white, black) = if self.color == Color::White {
+            ("Human", "Opponent")
+        } else {
+            ("Opponent", "Human")
+        };
+        game.tags = vec![
+            (String::from("White"), String::from(white)),
+            (String::from("Black"), String::from(black)),
+            (String::from("Result"), String::from("*")),
+        ];
+        let mut board = self.start;
+        for mv in &self.moves {
+            game.moves.push(notation::to_san(&board, *mv));
+            board.make_move(*mv);
+        }
+        game.result = String::from("*");
+        game.to_pgn()
+    }
+
+    // The move that goes with a draw offer
+    fn read_move(&mut self, board: &Board) -> Option<Move> {
+        loop {
+            match self.read_command(board) {
+                Command::Move(mv) => return Some(mv),
+                Command::Resign => return None,
+                _ => println!("Enter the move to go with your draw offer"),
+            }
+        }
+    }
+}
 
-            my_move = Move {
-                start: Position::from_string(start),
-                end: Position::from_string(end),
+impl player::Player for Human {
+    fn new_game(&mut self, board: Board, color: Color) {
+        self.color = color;
+        self.start = board;
+        self.moves.clear();
+    }
+
+    fn take_turn(&mut self, board: Board, _color: Color) -> Action {
+        self.show_board(&board);
+        loop {
+            let action = match self.read_command(&board) {
+                Command::Move(mv) => Action::Move(mv),
+                Command::Draw => {
+                    println!("You offer a draw, now make your move");
+                    match self.read_move(&board) {
+                        Some(mv) => Action::OfferDraw(mv),
+                        None => Action::Resign,
+                    }
+                }
+                Command::Accept => Action::AcceptDraw,
+                Command::Resign => Action::Resign,
+                Command::Undo => Action::RequestTakeback,
+                Command::Hint => {
+                    self.hint(&board);
+                    continue;
+                }
+                Command::Flip => {
+                    self.renderer.flipped = !self.renderer.flipped;
+                    self.show_board(&board);
+                    continue;
+                }
+                Command::Fen => {
+                    println!("{}", board.fen_string());
+                    continue;
+                }
+                Command::Pgn => {
+                    print!("{}", self.pgn());
+                    continue;
+                }
+                Command::Eval => {
+                    self.eval(&board);
+                    continue;
+                }
+                Command::Help => {
+                    println!("{HELP}");
+                    continue;
+                }
             };
+            if let Action::Move(mv) | Action::OfferDraw(mv) = action {
+                self.moves.push(mv);
+            }
+            return action;
         }
+    }
+
+    fn draw_offered(&mut self, _board: Board, _color: Color) {
+        println!("Your opponent offers a draw. Type accept to accept, or move to decline.");
+    }
 
-        return my_move;
+    fn takeback_requested(&mut self, _board: Board, _color: Color) -> bool {
+        ask_yes_no("Your opponent asks to take back their last move. Allow it?")
+    }
+
+    fn moves_taken_back(&mut self, _board: Board, plies: usize) {
+        let kept = self.moves.len().saturating_sub(plies);
+        self.moves.truncate(kept);
+    }
+
+    fn opponent_moved(&mut self, board: Board, mv: Move) {
+        println!("Your opponent played {}", notation::to_san(&board, mv));
+        self.moves.push(mv);
+    }
+
+    fn game_over(&mut self, result: &GameResult) {
+        println!("{}. Game Over", result.description());
+    }
+}
+
+// A closed input counts as no
+pub(crate) fn ask_yes_no(question: &str) -> bool {
+    loop {
+        print!("{question} (y/n) ");
+        io::stdout().flush().ok();
+        let mut line = String::new();
+        match io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => return false,
+            Ok(_) => {}
+        }
+        match line.trim().to_lowercase().as_str() {
+            "y" | "yes" => return true,
+            "n" | "no" => return false,
+            _ => println!("Please answer y or n"),
+        }
     }
 }
 // mod user_input
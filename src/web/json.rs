@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+
+// Just enough JSON for the web API. Responses are built as text with these helpers, and
+// request bodies are flat objects whose values are strings, numbers or booleans.
+
+pub(crate) fn string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// `items` are JSON already
+pub(crate) fn array(items: &[String]) -> String {
+    format!("[{}]", items.join(","))
+}
+
+// `fields` pairs names with values that are JSON already
+pub(crate) fn object(fields: &[(&str, String)]) -> String {
+    let members: Vec<String> = fields
+        .iter()
+        .map(|(name, value)| format!("{}:{value}", string(name)))
+        .collect();
+    format!("{{{}}}", members.join(","))
+}
+
+// Reads a flat object into its fields. Strings are unescaped and other values kept as
+// written, so `{"depth": 6}` gives "depth" => "6". An empty body is an empty object.
+pub(crate) fn parse_object(text: &str) -> Result<HashMap<String, String>, String> {
+    let mut fields: HashMap<String, String> = HashMap::new();
+    let mut chars = text.trim().chars().peekable();
+    if chars.peek().is_none() {
+        return Ok(fields);
+    }
+    if chars.next() != Some('{') {
+        return Err(String::from("Expected a JSON object"));
+    }
+    loop {
+        skip_whitespace(&mut chars);
+        match chars.next() {
+            Some('}') => break,
+            Some('"') => {}
+            _ => return Err(String::from("Expected a field name")),
+        }
+        let name = read_string(&mut chars)?;
+        skip_whitespace(&mut chars);
+        if chars.next() != Some(':') {
+            return Err(format!("Expected : after {name}"));
+        }
+        skip_whitespace(&mut chars);
+        let value = if chars.peek() == Some(&'"') {
+            chars.next();
+            read_string(&mut chars)?
+        } else {
+            let mut token = String::new();
+            while let Some(c) = chars.peek() {
+                if *c == ',' || *c == '}' || c.is_whitespace() {
+                    break;
+                }
+                if *c == '{' || *c == '[' {
+                    return Err(format!("{name} must be a string, number or boolean"));
+                }
+                token.push(*c);
+                chars.next();
+            }
+            token
+        };
+        fields.insert(name, value);
+        skip_whitespace(&mut chars);
+        match chars.next() {
+            Some(',') => {}
+            Some('}') => break,
+            _ => return Err(String::from("Expected , or }")),
+        }
+    }
+    Ok(fields)
+}
+
+fn skip_whitespace(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+// The rest of a string whose opening quote has been read
+fn read_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, String> {
+    let mut text = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(text),
+            Some('\\') => match chars.next() {
+                Some('n') => text.push('\n'),
+                Some('r') => text.push('\r'),
+                Some('t') => text.push('\t'),
+                Some('b') => text.push('\u{8}'),
+                Some('f') => text.push('\u{c}'),
+                Some('u') => {
+                    let code: String = chars.by_ref().take(4).collect();
+                    let c = u32::from_str_radix(&code, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| format!("Bad escape \\u{code}"))?;
+                    text.push(c);
+                }
+                Some(c) => text.push(c),
+                None => return Err(String::from("Unfinished string")),
+            },
+            Some(c) => text.push(c),
+            None => return Err(String::from("Unfinished string")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_flat_objects() {
+        let fields = parse_object(r#" { "move": "e4", "depth" : 6, "ponder":false } "#).unwrap();
+        assert_eq!(fields.len(), 3);
+        assert_eq!(fields["move"], "e4");
+        assert_eq!(fields["depth"], "6");
+        assert_eq!(fields["ponder"], "false");
+        assert!(parse_object("{}").unwrap().is_empty());
+    }
+
+    #[test]
+    fn empty_body_is_an_empty_object() {
+        assert!(parse_object("").unwrap().is_empty());
+        assert!(parse_object(" \r\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn unescapes_strings() {
+        let fields = parse_object(r#"{"fen": "a\"b\\c\nd\t\/", "name": "éA"}"#).unwrap();
+        assert_eq!(fields["fen"], "a\"b\\c\nd\t/");
+        assert_eq!(fields["name"], "éA");
+        assert!(parse_object(r#"{"name": "\u12"}"#).is_err());
+        assert!(parse_object(r#"{"name": "\ud800"}"#).is_err());
+    }
+
+    #[test]
+    fn rejects_nested_values() {
+        assert!(parse_object(r#"{"move": {"from": "e2"}}"#).is_err());
+        assert!(parse_object(r#"{"moves": ["e4", "e5"]}"#).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_objects() {
+        assert!(parse_object("[]").is_err());
+        assert!(parse_object(r#"{"move" "e4"}"#).is_err());
+        assert!(parse_object(r#"{"move": "e4""#).is_err());
+        assert!(parse_object(r#"{"move": "e4"#).is_err());
+        assert!(parse_object(r#"{move: "e4"}"#).is_err());
+        assert!(parse_object(r#"{"a": 1 "b": 2}"#).is_err());
+    }
+
+    #[test]
+    fn writes_json() {
+        assert_eq!(string("say \"hi\"\\\n\u{1}"), r#""say \"hi\"\\\n\u0001""#);
+        assert_eq!(
+            object(&[
+                ("a", string("x")),
+                ("b", array(&[1.to_string(), 2.to_string()]))
+            ]),
+            r#"{"a":"x","b":[1,2]}"#
+        );
+    }
+}
@@ -0,0 +1,528 @@
+mod json;
+
+use crate::engine::{format_score, SearchLimits, AI};
+use crate::model::{board::Board, color::Color, move_::Move, notation, pgn::PgnGame};
+use crate::starting_board;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// A small HTTP server on localhost so that other programs and browsers can use the board
+// and the engine. Each connection is answered on its own thread, so a long engine search
+// does not hold up other games; searches themselves take turns with the one engine. Bodies
+// are JSON and so are the answers. The page at / is a board that plays through the same API.
+//
+//   POST   /api/games                    {"fen"?}                       new game
+//   GET    /api/games/<id>                                              state, FEN and PGN
+//   DELETE /api/games/<id>
+//   GET    /api/games/<id>/legal-moves
+//   POST   /api/games/<id>/moves         {"move"}                       SAN or e2e4
+//   POST   /api/games/<id>/engine-move   {"movetime"?, "depth"?}
+//   POST   /api/games/<id>/analysis      {"movetime"?, "depth"?, "multipv"?}
+
+const INDEX: &str = include_str!("index.html");
+// Requests larger than this are refused
+const MAX_BODY: usize = 64 * 1024;
+const DEFAULT_MOVE_TIME: Duration = Duration::from_secs(1);
+const MAX_MOVE_TIME: Duration = Duration::from_secs(60);
+
+struct Request {
+    method: String,
+    path: String,
+    body: String,
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+struct WebGame {
+    start: Board,
+    board: Board,
+    moves: Vec<Move>,
+}
+
+// The games lock is never held during a search, only the engine lock
+struct Server {
+    engine: Mutex<AI>,
+    games: Mutex<Games>,
+}
+
+struct Games {
+    games: HashMap<u64, WebGame>,
+    next_id: u64,
+}
+
+pub(crate) fn serve(port: u16, engine: AI) -> Result<(), String> {
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .map_err(|e| format!("Could not listen on port {port}: {e}"))?;
+    println!("Serving on http://127.0.0.1:{port}/");
+    run(listener, engine);
+    Ok(())
+}
+
+fn run(listener: TcpListener, mut engine: AI) {
+    engine.set_verbose(false);
+    let server = Arc::new(Server {
+        engine: Mutex::new(engine),
+        games: Mutex::new(Games {
+            games: HashMap::new(),
+            next_id: 1,
+        }),
+    });
+    for stream in listener.incoming() {
+        match stream {
+            Ok(mut stream) => {
+                let server = server.clone();
+                thread::spawn(move || {
+                    let response = match read_request(&mut stream) {
+                        Ok(request) => server.handle(&request),
+                        Err(e) => error(400, &e),
+                    };
+                    if let Err(e) = write_response(&mut stream, &response) {
+                        println!("Could not answer a request: {e}");
+                    }
+                });
+            }
+            Err(e) => println!("Could not accept a connection: {e}"),
+        }
+    }
+}
+
+fn read_request(stream: &mut TcpStream) -> Result<Request, String> {
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .map_err(|e| e.to_string())?;
+    let mut reader = BufReader::new(stream.try_clone().map_err(|e| e.to_string())?);
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(|e| e.to_string())?;
+    let mut words = line.split_whitespace();
+    let method = words.next().ok_or("Empty request")?.to_owned();
+    let target = words.next().ok_or("No path in the request")?;
+    let path = target.split('?').next().unwrap_or("/").to_owned();
+
+    let mut length: usize = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            break;
+        }
+        let header = line.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().map_err(|_| "Bad Content-Length")?;
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Err(String::from("Request body too large"));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).map_err(|e| e.to_string())?;
+    let body = String::from_utf8(body).map_err(|_| "The body is not UTF-8")?;
+    Ok(Request { method, path, body })
+}
+
+fn write_response(stream: &mut TcpStream, response: &Response) -> Result<(), String> {
+    let reason = match response.status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        _ => "Error",
+    };
+    let head = format!(
+        "HTTP/1.1 {} {reason}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    stream
+        .write_all(head.as_bytes())
+        .and_then(|_| stream.write_all(response.body.as_bytes()))
+        .map_err(|e| e.to_string())
+}
+
+fn ok(status: u16, body: String) -> Response {
+    Response {
+        status,
+        content_type: "application/json",
+        body,
+    }
+}
+
+fn error(status: u16, message: &str) -> Response {
+    ok(status, json::object(&[("error", json::string(message))]))
+}
+
+// A search limit from the request, at most a minute
+fn limits(fields: &HashMap<String, String>) -> Result<SearchLimits, String> {
+    let move_time = match fields.get("movetime") {
+        Some(value) => {
+            let millis: u64 = value.parse().map_err(|_| "movetime must be milliseconds")?;
+            Duration::from_millis(millis).min(MAX_MOVE_TIME)
+        }
+        None => DEFAULT_MOVE_TIME,
+    };
+    let depth = match fields.get("depth") {
+        Some(value) => Some(
+            value
+                .parse()
+                .map_err(|_| "depth must be a number up to 255")?,
+        ),
+        None => None,
+    };
+    Ok(SearchLimits { depth, move_time })
+}
+
+impl Server {
+    fn handle(&self, request: &Request) -> Response {
+        let parts: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+        let fields = match json::parse_object(&request.body) {
+            Ok(fields) => fields,
+            Err(e) => return error(400, &e),
+        };
+        let result = match (request.method.as_str(), parts.as_slice()) {
+            ("GET", [""]) | ("GET", ["index.html"]) => {
+                return Response {
+                    status: 200,
+                    content_type: "text/html; charset=utf-8",
+                    body: String::from(INDEX),
+                };
+            }
+            ("POST", ["api", "games"]) => self.create(&fields),
+            ("GET", ["api", "games", id]) => {
+                let games = self.games.lock().unwrap();
+                games.game(id).map(|game| (200, state(id, game)))
+            }
+            ("DELETE", ["api", "games", id]) => self.delete(id),
+            ("GET", ["api", "games", id, "legal-moves"]) => {
+                let games = self.games.lock().unwrap();
+                games.game(id).map(|game| (200, legal_moves(&game.board)))
+            }
+            ("POST", ["api", "games", id, "moves"]) => self.play(id, &fields),
+            ("POST", ["api", "games", id, "engine-move"]) => self.engine_move(id, &fields),
+            ("POST", ["api", "games", id, "analysis"]) => self.analysis(id, &fields),
+            (_, ["api", ..]) | (_, [""]) => return error(405, "Method not allowed here"),
+            _ => return error(404, "Not found"),
+        };
+        match result {
+            Ok((status, body)) => ok(status, body),
+            Err((status, message)) => error(status, &message),
+        }
+    }
+
+    fn create(&self, fields: &HashMap<String, String>) -> Result<(u16, String), (u16, String)> {
+        let start = match fields.get("fen") {
+            Some(fen) => Board::from_fen(fen).map_err(|e| (400, e))?,
+            None => starting_board::make_board(),
+        };
+        let mut games = self.games.lock().unwrap();
+        let id = games.next_id;
+        games.next_id += 1;
+        let game = WebGame {
+            start,
+            board: start,
+            moves: Vec::new(),
+        };
+        let body = state(&id.to_string(), &game);
+        games.games.insert(id, game);
+        Ok((201, body))
+    }
+
+    fn delete(&self, id: &str) -> Result<(u16, String), (u16, String)> {
+        let mut games = self.games.lock().unwrap();
+        games.game(id)?;
+        games.games.remove(&id.parse::<u64>().unwrap());
+        Ok((200, json::object(&[("deleted", id.to_owned())])))
+    }
+
+    fn play(
+        &self,
+        id: &str,
+        fields: &HashMap<String, String>,
+    ) -> Result<(u16, String), (u16, String)> {
+        let text = fields
+            .get("move")
+            .ok_or((400, String::from("Send the move as {\"move\": \"e4\"}")))?;
+        let mut games = self.games.lock().unwrap();
+        let game = games.game_mut(id)?;
+        if game_over(&game.board).is_some() {
+            return Err((400, String::from("The game is over")));
+        }
+        let mv = notation::parse_move(&game.board, text).map_err(|e| (400, e))?;
+        game.board.make_move(mv);
+        game.moves.push(mv);
+        Ok((200, state(id, game)))
+    }
+
+    fn engine_move(
+        &self,
+        id: &str,
+        fields: &HashMap<String, String>,
+    ) -> Result<(u16, String), (u16, String)> {
+        let limits = limits(fields).map_err(|e| (400, e))?;
+        let (board, played) = {
+            let games = self.games.lock().unwrap();
+            let game = games.game(id)?;
+            (game.board, game.moves.len())
+        };
+        if game_over(&board).is_some() {
+            return Err((400, String::from("The game is over")));
+        }
+        let mv = {
+            let mut engine = self.engine.lock().unwrap();
+            engine.set_limits(limits);
+            engine.choose_move(board, board.turn)
+        };
+        let mut games = self.games.lock().unwrap();
+        let game = games.game_mut(id)?;
+        // Another request may have moved while the engine was thinking
+        if game.moves.len() != played {
+            return Err((409, String::from("The game changed during the search")));
+        }
+        game.board.make_move(mv);
+        game.moves.push(mv);
+        Ok((200, state(id, game)))
+    }
+
+    fn analysis(
+        &self,
+        id: &str,
+        fields: &HashMap<String, String>,
+    ) -> Result<(u16, String), (u16, String)> {
+        let limits = limits(fields).map_err(|e| (400, e))?;
+        let lines: usize = match fields.get("multipv") {
+            Some(value) => value
+                .parse()
+                .map_err(|_| (400, String::from("multipv must be a number")))?,
+            None => 1,
+        };
+        let board = self.games.lock().unwrap().game(id)?.board;
+        let found = {
+            let mut engine = self.engine.lock().unwrap();
+            engine.set_limits(limits);
+            engine.set_multi_pv(lines);
+            let found = engine.analyze(board, board.turn);
+            engine.set_multi_pv(1);
+            found
+        };
+
+        let mut items: Vec<String> = Vec::new();
+        // Weaker skill levels search extra lines to choose from, which are left out here
+        for line in found.iter().take(lines) {
+            let mut position = board;
+            let mut san: Vec<String> = Vec::new();
+            let mut uci: Vec<String> = Vec::new();
+            for mv in &line.pv {
+                san.push(json::string(&notation::to_san(&position, *mv)));
+                uci.push(json::string(&notation::to_long_algebraic(&position, *mv)));
+                position.make_move(*mv);
+            }
+            items.push(json::object(&[
+                (
+                    "move",
+                    json::string(&notation::to_long_algebraic(&board, line.mv)),
+                ),
+                ("score", json::string(&format_score(line.score))),
+                ("depth", line.depth.to_string()),
+                ("pv", json::array(&uci)),
+                ("pv_san", json::array(&san)),
+            ]));
+        }
+        Ok((
+            200,
+            json::object(&[
+                ("fen", json::string(&board.fen_string())),
+                ("lines", json::array(&items)),
+            ]),
+        ))
+    }
+}
+
+impl Games {
+    fn game(&self, id: &str) -> Result<&WebGame, (u16, String)> {
+        id.parse()
+            .ok()
+            .and_then(|id: u64| self.games.get(&id))
+            .ok_or((404, format!("No game {id}")))
+    }
+
+    fn game_mut(&mut self, id: &str) -> Result<&mut WebGame, (u16, String)> {
+        id.parse()
+            .ok()
+            .and_then(|id: u64| self.games.get_mut(&id))
+            .ok_or((404, format!("No game {id}")))
+    }
+}
+
+// The result and how the game ended, when it has
+fn game_over(board: &Board) -> Option<(&'static str, &'static str)> {
+    let color = board.turn;
+    if board.checkmatep(color) {
+        let result = if color == Color::White { "0-1" } else { "1-0" };
+        return Some((result, "checkmate"));
+    } else if board.stalematep(color) {
+        return Some(("1/2-1/2", "stalemate"));
+    }
+    None
+}
+
+fn legal_moves(board: &Board) -> String {
+    let moves: Vec<String> = board
+        .get_all_moves(board.turn)
+        .into_iter()
+        .map(|mv| {
+            json::object(&[
+                ("uci", json::string(&notation::to_long_algebraic(board, mv))),
+                ("san", json::string(&notation::to_san(board, mv))),
+            ])
+        })
+        .collect();
+    json::array(&moves)
+}
+
+fn state(id: &str, game: &WebGame) -> String {
+    let (result, status) = game_over(&game.board).unwrap_or(("*", "playing"));
+    let mut pgn = PgnGame::new();
+    pgn.tags = vec![
+        (String::from("Event"), String::from("Web game")),
+        (String::from("Result"), String::from(result)),
+    ];
+    if game.start.fen_string() != starting_board::make_board().fen_string() {
+        pgn.tags.push((String::from("SetUp"), String::from("1")));
+        pgn.tags
+            .push((String::from("FEN"), game.start.fen_string()));
+    }
+    let mut position = game.start;
+    let mut san: Vec<String> = Vec::new();
+    let mut last_move = String::from("null");
+    for mv in &game.moves {
+        let text = notation::to_san(&position, *mv);
+        pgn.moves.push(text.clone());
+        san.push(json::string(&text));
+        last_move = json::string(&notation::to_long_algebraic(&position, *mv));
+        position.make_move(*mv);
+    }
+    pgn.result = String::from(result);
+    let turn = game.board.turn.name().to_lowercase();
+
+    json::object(&[
+        ("id", id.to_owned()),
+        ("fen", json::string(&game.board.fen_string())),
+        ("pgn", json::string(&pgn.to_pgn())),
+        ("turn", json::string(&turn)),
+        ("status", json::string(status)),
+        ("result", json::string(result)),
+        ("check", game.board.in_checkp(game.board.turn).to_string()),
+        ("moves", json::array(&san)),
+        ("last_move", last_move),
+        ("legal_moves", legal_moves(&game.board)),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::eval_params::EvalParams;
+    use std::net::SocketAddr;
+
+    fn start_server() -> SocketAddr {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        let engine = AI::with_params(EvalParams::default());
+        thread::spawn(move || run(listener, engine));
+        address
+    }
+
+    // The status and body of one request
+    fn request(address: SocketAddr, method: &str, path: &str, body: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "{method} {path} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let mut answer = String::new();
+        stream.read_to_string(&mut answer).unwrap();
+        let (head, body) = answer.split_once("\r\n\r\n").unwrap();
+        let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+        (status, body.to_owned())
+    }
+
+    #[test]
+    fn create_move_and_state() {
+        let address = start_server();
+        let (status, body) = request(address, "POST", "/api/games", "");
+        assert_eq!(status, 201);
+        assert!(body.starts_with(r#"{"id":1,"#));
+        assert!(body.contains(r#""turn":"white""#));
+
+        let (status, body) = request(address, "POST", "/api/games/1/moves", r#"{"move": "e4"}"#);
+        assert_eq!(status, 200);
+        assert!(body.contains(r#""moves":["e4"]"#));
+        assert!(body.contains(r#""last_move":"e2e4""#));
+
+        let (status, body) = request(address, "GET", "/api/games/1", "");
+        assert_eq!(status, 200);
+        assert!(
+            body.contains(r#""fen":"rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1""#)
+        );
+        assert!(body.contains(r#""status":"playing""#));
+
+        let (status, body) = request(
+            address,
+            "POST",
+            "/api/games/1/engine-move",
+            r#"{"depth": 1, "movetime": 200}"#,
+        );
+        assert_eq!(status, 200);
+        assert!(body.contains(r#""turn":"white""#));
+    }
+
+    #[test]
+    fn state_counts_moves_from_the_start_position() {
+        let address = start_server();
+        let fen = "4k3/8/8/8/8/8/4P3/4K2R b K - 7 20";
+        let (status, _) = request(
+            address,
+            "POST",
+            "/api/games",
+            &format!(r#"{{"fen": "{fen}"}}"#),
+        );
+        assert_eq!(status, 201);
+        request(address, "POST", "/api/games/1/moves", r#"{"move": "Kd7"}"#);
+        let (_, body) = request(address, "POST", "/api/games/1/moves", r#"{"move": "e4"}"#);
+        assert!(body.contains(r#""fen":"8/3k4/8/8/4P3/8/8/4K2R b K e3 0 21""#));
+        assert!(body.contains(r#"20... Kd7 21. e4"#));
+    }
+
+    #[test]
+    fn bad_requests() {
+        let address = start_server();
+        assert_eq!(request(address, "GET", "/api/games/7", "").0, 404);
+        assert_eq!(
+            request(address, "POST", "/api/games", "{\"fen\": \"8/8\"}").0,
+            400
+        );
+        assert_eq!(request(address, "POST", "/api/games", "{").0, 400);
+        request(address, "POST", "/api/games", "");
+        let (status, body) = request(address, "POST", "/api/games/1/moves", r#"{"move": "e5"}"#);
+        assert_eq!(status, 400);
+        assert!(body.starts_with(r#"{"error":"#));
+        assert_eq!(request(address, "PUT", "/api/games/1", "").0, 405);
+        assert_eq!(request(address, "DELETE", "/api/games/1", "").0, 200);
+        assert_eq!(request(address, "GET", "/api/games/1", "").0, 404);
+    }
+}